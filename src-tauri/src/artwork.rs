@@ -0,0 +1,203 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use image::{DynamicImage, codecs::jpeg::JpegEncoder};
+use log::warn;
+use symphonia::core::{io::MediaSourceStream, meta::StandardVisualKey, probe::Hint};
+
+use crate::cache;
+
+const ARTWORK_DIR: &str = "artwork";
+
+/// Edge lengths, in pixels, of the thumbnails generated next to every cover.
+pub const THUMBNAIL_SIZES: [u32; 4] = [64, 128, 256, 512];
+
+/// The thumbnail size list views show.
+pub const LIST_THUMBNAIL_SIZE: u32 = 128;
+
+const JPEG_QUALITY: u8 = 85;
+
+/// A cached cover and the thumbnail list views load instead of it.
+pub struct CachedArtwork {
+    pub image_path: PathBuf,
+    pub thumbnail_path: Option<PathBuf>,
+}
+
+/// Sidecar image names looked for next to a track, in order of preference.
+const SIDECAR_NAMES: [&str; 3] = ["folder", "cover", "front"];
+
+/// Finds artwork that does not need a network lookup: a picture embedded in the file,
+/// or failing that a sidecar image in its folder. The image is copied into the
+/// cache so the frontend can load it as a file.
+pub fn local_artwork(music_path: &Path, cache_dir: &Path) -> Option<CachedArtwork> {
+    let data = embedded_artwork(music_path)
+        .filter(|data| image_extension(data).is_some())
+        .or_else(|| sidecar_artwork(music_path))?;
+    cache_local(&data, music_path, cache_dir)
+}
+
+/// Like `local_artwork`, for files already known to have no embedded picture.
+pub fn local_sidecar_artwork(music_path: &Path, cache_dir: &Path) -> Option<CachedArtwork> {
+    let data = sidecar_artwork(music_path)?;
+    cache_local(&data, music_path, cache_dir)
+}
+
+fn cache_local(data: &[u8], music_path: &Path, cache_dir: &Path) -> Option<CachedArtwork> {
+    save_artwork(data, cache_dir)
+        .map_err(|err| {
+            warn!(
+                "failed to cache artwork of {}: {}",
+                music_path.display(),
+                err
+            )
+        })
+        .ok()
+}
+
+/// The front cover embedded in the file, or its first picture if none is marked as
+/// the front cover.
+fn embedded_artwork(path: &Path) -> Option<Vec<u8>> {
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+    let source = File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(source), Default::default());
+    let mut probed = symphonia::default::get_probe()
+        .format(&hint, mss, &Default::default(), &Default::default())
+        .ok()?;
+
+    let format_metadata = probed.format.metadata();
+    let probed_metadata = probed.metadata.get();
+    let visuals = match format_metadata.current() {
+        Some(revision) => revision.visuals(),
+        None => probed_metadata
+            .as_ref()
+            .and_then(|m| m.current())?
+            .visuals(),
+    };
+    let visual = visuals
+        .iter()
+        .find(|visual| visual.usage == Some(StandardVisualKey::FrontCover))
+        .or_else(|| visuals.first())?;
+    Some(visual.data.to_vec())
+}
+
+fn sidecar_artwork(music_path: &Path) -> Option<Vec<u8>> {
+    let dir = music_path.parent()?;
+    let (_, path) = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let stem = path.file_stem()?.to_str()?.to_lowercase();
+            let rank = SIDECAR_NAMES.iter().position(|name| *name == stem)?;
+            path.is_file().then_some((rank, path))
+        })
+        .min()?;
+    fs::read(path).ok()
+}
+
+/// Stores image bytes under a name derived from their hash, so identical artwork is
+/// kept once however many tracks use it. Images are transcoded to JPEG and
+/// thumbnailed; formats that cannot be decoded are kept as they are.
+pub fn save_artwork(data: &[u8], cache_dir: &Path) -> io::Result<CachedArtwork> {
+    let extension = image_extension(data)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unrecognised image format"))?;
+    let dir = cache_dir.join(ARTWORK_DIR);
+    fs::create_dir_all(&dir)?;
+    let hash = format!("{:x}", md5::compute(data));
+
+    let image_path = dir.join(format!("{}.jpg", hash));
+    if image_path.exists() {
+        cache::touch(&image_path);
+        return Ok(CachedArtwork {
+            thumbnail_path: thumbnail(&image_path, LIST_THUMBNAIL_SIZE),
+            image_path,
+        });
+    }
+    let image = match image::load_from_memory(data) {
+        Ok(image) => image,
+        Err(err) => {
+            warn!("failed to decode artwork, keeping it as is: {}", err);
+            let raw_path = dir.join(format!("{}.{}", hash, extension));
+            if !raw_path.exists() {
+                fs::write(&raw_path, data)?;
+            }
+            return Ok(CachedArtwork {
+                image_path: raw_path,
+                thumbnail_path: None,
+            });
+        }
+    };
+
+    for size in THUMBNAIL_SIZES {
+        if image.width().max(image.height()) > size {
+            write_jpeg(
+                &image.thumbnail(size, size),
+                &thumbnail_name(&image_path, size),
+            )?;
+        }
+    }
+    write_jpeg(&image, &image_path)?;
+    Ok(CachedArtwork {
+        thumbnail_path: thumbnail(&image_path, LIST_THUMBNAIL_SIZE),
+        image_path,
+    })
+}
+
+/// The smallest generated thumbnail at least `size` pixels across, or the cover
+/// itself when it is no larger than that. Thumbnails missing from covers cached
+/// before they were introduced are generated on demand.
+pub fn thumbnail(image_path: &Path, size: u32) -> Option<PathBuf> {
+    let size = THUMBNAIL_SIZES.into_iter().find(|s| *s >= size)?;
+    let path = thumbnail_name(image_path, size);
+    if path.exists() {
+        cache::touch(&path);
+        return Some(path);
+    }
+    let image = image::open(image_path).ok()?;
+    if image.width().max(image.height()) <= size {
+        return Some(image_path.to_path_buf());
+    }
+    write_jpeg(&image.thumbnail(size, size), &path)
+        .map_err(|err| warn!("failed to write thumbnail {}: {}", path.display(), err))
+        .ok()?;
+    Some(path)
+}
+
+fn thumbnail_name(image_path: &Path, size: u32) -> PathBuf {
+    let stem = image_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    image_path.with_file_name(format!("{}_{}.jpg", stem, size))
+}
+
+/// JPEG has no alpha channel, so transparent covers are flattened first.
+fn write_jpeg(image: &DynamicImage, path: &Path) -> io::Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_with_encoder(JpegEncoder::new_with_quality(writer, JPEG_QUALITY))
+        .map_err(io::Error::other)
+}
+
+/// Recognises the image formats webviews can display by their magic bytes.
+fn image_extension(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpg")
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if data.starts_with(b"GIF8") {
+        Some("gif")
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        Some("webp")
+    } else if data.starts_with(b"BM") {
+        Some("bmp")
+    } else {
+        None
+    }
+}
@@ -1,29 +1,213 @@
 use std::{
+    collections::HashMap,
     fs,
     io::Error,
     path::{Path, PathBuf},
-    sync::mpsc::Sender,
+    sync::{Arc, mpsc::Sender},
+    time::SystemTime,
 };
 
-use chrono::{DateTime, Utc};
-use futures::future::join_all;
+use chrono::Utc;
+use futures::{StreamExt, future::join_all, stream};
 use log::warn;
 use serde::{Deserialize, Serialize};
-use tauri_plugin_http::reqwest;
 
 use crate::{
-    music::{MusicError, MusicFile, MusicMap},
+    artwork::{self, CachedArtwork},
+    file_reader::{self, AudioSniff},
+    fingerprint,
+    http_client::HttpClient,
+    matching::{self, MatchOutcome},
+    music::{
+        MatchChoice, MusicError, MusicFile, MusicMap, MusicMeta, StoredFileInfo, StoredFingerprint,
+        TrackMatch,
+    },
+    path_tags::PathPattern,
     player,
+    provider::{MetadataProvider, TrackQuery},
 };
 
 const CACHE_DIR: &str = "cache";
-const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/58.0.3029.110 Safari/537.3";
 
+/// Looked-up metadata, one JSON file per track identity.
+const META_DIR: &str = "meta";
+
+/// Lyrics found online, one LRC file per track identity.
+const LYRICS_DIR: &str = "lyrics";
+
+/// How long a lookup that found nothing is trusted before the track is tried again.
+const MISS_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Files looked up at once.
+const LOOKUP_CONCURRENCY: usize = 8;
+
+/// AcoustID results scoring below this are treated as no match.
+const MIN_ACOUSTID_SCORE: f64 = 0.5;
+
+/// Identifies untagged files by fingerprint instead of guessing from their filename.
+#[derive(Clone, Debug)]
+pub struct AcoustIdConfig {
+    pub url: String,
+    pub client_key: String,
+    pub fingerprints: HashMap<String, StoredFingerprint>,
+}
+
+impl AcoustIdConfig {
+    pub fn new(
+        url: String,
+        client_key: String,
+        fingerprints: HashMap<String, StoredFingerprint>,
+    ) -> Self {
+        Self {
+            url,
+            client_key,
+            fingerprints,
+        }
+    }
+}
+
+/// Everything a metadata lookup needs besides the files themselves.
+pub struct LookupConfig {
+    /// Whether providers may be asked at all; offline lookups only use what is in the
+    /// files, next to them or already cached.
+    pub online: bool,
+    pub client: Arc<HttpClient>,
+    pub providers: Vec<Box<dyn MetadataProvider>>,
+    pub acoustid: Option<AcoustIdConfig>,
+    pub strip_patterns: Vec<String>,
+    pub path_patterns: Vec<PathPattern>,
+    /// What earlier lookups read from the files, keyed by path.
+    pub file_infos: HashMap<String, StoredFileInfo>,
+}
+
+/// What is left to do after a lookup: ambiguous matches for the user to choose from,
+/// the paths of files whose lookup failed or was skipped while offline and should
+/// be run later, and the file infos to remember.
+#[derive(Default)]
+pub struct LookupReport {
+    pub choices: Vec<MatchChoice>,
+    pub failed: Vec<String>,
+    pub skipped: Vec<String>,
+    pub file_infos: HashMap<String, StoredFileInfo>,
+}
+
+/// Resolves metadata and artwork for `music_files`, sending each result through `tx`.
 pub async fn init_cache(
     cache_dir: PathBuf,
     music_files: Vec<MusicFile>,
+    config: LookupConfig,
     tx: &Sender<MusicMap>,
-) -> Result<(), MusicError> {
+) -> Result<LookupReport, MusicError> {
+    let cache_dir = create_cache_dir(cache_dir)?;
+
+    let local_dir = cache_dir.clone();
+    let strip_patterns = config.strip_patterns.clone();
+    let file_infos = config.file_infos.clone();
+    let (cached, pending, file_infos) = tauri::async_runtime::spawn_blocking(move || {
+        resolve_cached(music_files, &local_dir, &strip_patterns, &file_infos)
+    })
+    .await
+    .map_err(|e| MusicError::new(None, "cache".to_string(), e.to_string()))?;
+    for music_map in cached {
+        let _ = tx.send(music_map);
+    }
+
+    if !config.online {
+        return Ok(LookupReport {
+            skipped: pending
+                .into_iter()
+                .map(|lookup| lookup.music_file.path)
+                .collect(),
+            file_infos,
+            ..Default::default()
+        });
+    }
+    if config.providers.is_empty() || pending.is_empty() {
+        return Ok(LookupReport {
+            file_infos,
+            ..Default::default()
+        });
+    }
+    let client = config.client.as_ref();
+    let results = request_music_data(client, pending, &config).await;
+
+    let mut report = LookupReport {
+        file_infos,
+        ..Default::default()
+    };
+    let mut accepted = Vec::new();
+    for (lookup, result) in results {
+        match result {
+            Ok(MatchOutcome::Accepted(found)) => accepted.push((lookup, found)),
+            Ok(MatchOutcome::Ambiguous(candidates)) => {
+                report.choices.push(MatchChoice::new(
+                    lookup.music_file.name,
+                    lookup.music_file.path,
+                    candidates,
+                ));
+            }
+            Ok(MatchOutcome::Rejected) => {
+                warn!("no confident match for {}", lookup.music_file.name);
+                save_miss_to_cache(&cache_dir, &lookup.identity);
+            }
+            Err(err) => {
+                warn!("lookup of {} failed: {}", lookup.music_file.name, err);
+                report.failed.push(lookup.music_file.path);
+            }
+        }
+    }
+
+    let futures = accepted.into_iter().map(|(lookup, found)| {
+        let cache_dir = &cache_dir;
+        let providers = &config.providers;
+        async move {
+            if let Some(music_map) = apply_match(client, providers, cache_dir, lookup, found).await
+            {
+                let _ = tx.send(music_map);
+            }
+        }
+    });
+    join_all(futures).await;
+    Ok(report)
+}
+
+/// Applies the candidate the user picked for an ambiguous lookup.
+pub async fn pick_match(
+    cache_dir: PathBuf,
+    client: &HttpClient,
+    providers: &[Box<dyn MetadataProvider>],
+    music_name: String,
+    music_path: String,
+    found: TrackMatch,
+) -> Result<MusicMap, MusicError> {
+    let cache_dir = create_cache_dir(cache_dir)?;
+    let identity_path = music_path.clone();
+    let identity = tauri::async_runtime::spawn_blocking(move || file_identity(&identity_path))
+        .await
+        .map_err(|e| MusicError::new(None, "cache".to_string(), e.to_string()))?;
+    let lookup = Lookup {
+        music_file: MusicFile::new(
+            String::new(),
+            music_name.clone(),
+            music_path,
+            None,
+            None,
+            None,
+        ),
+        identity,
+    };
+    apply_match(client, providers, &cache_dir, lookup, found)
+        .await
+        .ok_or_else(|| {
+            MusicError::new(
+                None,
+                music_name,
+                "no artwork found for the chosen match".to_string(),
+            )
+        })
+}
+
+fn create_cache_dir(cache_dir: PathBuf) -> Result<PathBuf, MusicError> {
     let cache_dir = cache_dir.join(CACHE_DIR);
     if !cache_dir.exists() {
         fs::create_dir_all(&cache_dir).map_err(|e| {
@@ -34,84 +218,41 @@ pub async fn init_cache(
             )
         })?;
     }
+    Ok(cache_dir)
+}
 
-    let mut filtered_music_files = music_files.clone();
-    for music_file in &music_files {
-        let music_name = &music_file.name;
-        if let Some(meta_path) = load_meta_cache(&cache_dir, music_name) {
-            filtered_music_files.retain(|music| music.name != *music_name);
-            if let Ok(meta) = fs::read_to_string(meta_path)
-                && let Ok(music_map) = serde_json::from_str::<MusicMap>(&meta)
-            {
-                let _ = tx.send(music_map);
-            }
-        }
-    }
-
-    let results = request_music_data(filtered_music_files).await;
-
-    let futures = results
-        .into_iter()
-        .filter_map(|result| result.ok())
-        .filter_map(|music_data_res| {
-            let mut filtered_results: Vec<&Body> = music_data_res
-                .results
-                .iter()
-                .filter(|music_data| {
-                    music_data.kind.as_deref() == Some("song")
-                        && music_data.wrapper_type.as_deref() == Some("track")
-                })
-                .collect();
-
-            filtered_results.sort_by(|a, b| {
-                let a_date = a
-                    .release_date
-                    .as_deref()
-                    .and_then(|s| s.parse::<DateTime<Utc>>().ok());
-                let b_date = b
-                    .release_date
-                    .as_deref()
-                    .and_then(|s| s.parse::<DateTime<Utc>>().ok());
-                a_date.cmp(&b_date)
-            });
-
-            let music_data = filtered_results.first()?;
-            let title = music_data
-                .track_name
-                .as_deref()
-                .unwrap_or_default()
-                .to_string();
-            let artist = music_data
-                .artist_name
-                .as_deref()
-                .unwrap_or_default()
-                .to_string();
-            let album = music_data
-                .collection_name
-                .as_deref()
-                .unwrap_or_default()
-                .to_string();
-            let artwork_url = music_data.artwork_url100.as_ref()?;
-            let url = artwork_url.replace("100x100", "1000x1000");
-            let hashed_filename = format!("{:x}", md5::compute(&title));
-            let filename = format!("{}.webp", hashed_filename);
-            let cache_dir = cache_dir.clone();
-            let tx = tx.clone();
-            let music_name = music_data_res.music_name;
-
-            Some(async move {
-                if let Ok(path) = save_img_to_cache(&url, &filename, &cache_dir).await {
-                    let music_map =
-                        MusicMap::new(music_name, title, artist, album, path.display().to_string());
-                    save_meta_to_cache(&music_map, &cache_dir);
-                    let _ = tx.send(music_map);
-                }
-            })
-        })
-        .collect::<Vec<_>>();
+/// A file still to be looked up online, with the identity its result is cached under.
+struct Lookup {
+    music_file: MusicFile,
+    identity: String,
+}
 
-    join_all(futures).await;
-    Ok(())
+/// Downloads the match's artwork and records the match in the meta cache.
+async fn apply_match(
+    client: &HttpClient,
+    providers: &[Box<dyn MetadataProvider>],
+    cache_dir: &Path,
+    lookup: Lookup,
+    found: TrackMatch,
+) -> Option<MusicMap> {
+    let url = resolve_artwork(client, providers, &found).await?;
+    let artwork = save_img_to_cache(client, &url, cache_dir)
+        .await
+        .map_err(|err| warn!("failed to save artwork: {}", err))
+        .ok()?;
+    let music_map = MusicMap::new(
+        lookup.music_file.resume_key(),
+        lookup.music_file.name,
+        found.title,
+        found.artist,
+        found.album,
+        artwork.image_path.display().to_string(),
+        artwork
+            .thumbnail_path
+            .map(|path| path.display().to_string()),
+    );
+    save_meta_to_cache(&music_map, cache_dir, &lookup.identity);
+    Some(music_map)
 }
 
 pub fn clear_cache(cache_dir: PathBuf) -> Result<(), MusicError> {
@@ -128,80 +269,460 @@ pub fn clear_cache(cache_dir: PathBuf) -> Result<(), MusicError> {
     Ok(())
 }
 
-fn load_meta_cache(cache_dir: &Path, music_name: &str) -> Option<PathBuf> {
-    let hash_name = format!("{:x}", md5::compute(music_name));
-    let filename = format!("{}.json", hash_name);
-    let path = cache_dir.join(&filename);
-    path.exists().then_some(path)
-}
-
-async fn request_music_data(music_files: Vec<MusicFile>) -> Vec<Result<MusicDataRes, Error>> {
-    let futures = music_files.iter().map(|music_file| async move {
-        let music_path = &music_file.path;
-        let music_meta = player::load_metadata(music_path);
-        let keyword = music_meta
-            .map(|meta| format!("{} + {}", meta.artist, meta.title))
-            .unwrap_or_else(|| music_file.name.replace('-', " + "));
-        let encoded_keyword = urlencoding::encode(&keyword);
-        let url = format!(
-            "https://itunes.apple.com/cn/search?term={}",
-            encoded_keyword
-        );
+/// Resolves artwork found in or next to the files themselves, then metadata cached
+/// by earlier lookups, and returns the files that still need an online lookup along
+/// with the file infos that were read or changed.
+fn resolve_cached(
+    music_files: Vec<MusicFile>,
+    cache_dir: &Path,
+    strip_patterns: &[String],
+    file_infos: &HashMap<String, StoredFileInfo>,
+) -> (Vec<MusicMap>, Vec<Lookup>, HashMap<String, StoredFileInfo>) {
+    let mut found = Vec::new();
+    let mut remaining = Vec::new();
+    let mut updated: HashMap<String, StoredFileInfo> = HashMap::new();
+    for music_file in music_files {
+        // Cue sheet tracks of one file share its entry.
+        let stored = updated
+            .get(&music_file.path)
+            .or_else(|| file_infos.get(&music_file.path));
+        let (info, artwork) = file_info(&music_file.path, stored, cache_dir);
+        let identity = info.identity.clone();
+        if stored != Some(&info) {
+            updated.insert(music_file.path.clone(), info);
+        }
+        if let Some(artwork) = artwork {
+            // The tags of a cue sheet's file describe the whole album, not the track.
+            let meta = if music_file.segment.is_some() {
+                MusicMeta {
+                    title: music_file.name.clone(),
+                    artist: music_file.artist.clone().unwrap_or_default(),
+                    album: music_file.album.clone().unwrap_or_default(),
+                }
+            } else {
+                player::load_metadata(&music_file.path, strip_patterns)
+                    .unwrap_or_else(|| MusicMeta::new(music_file.name.clone()))
+            };
+            found.push(MusicMap::new(
+                music_file.resume_key(),
+                music_file.name,
+                meta.title,
+                meta.artist,
+                meta.album,
+                artwork.image_path.display().to_string(),
+                artwork
+                    .thumbnail_path
+                    .map(|path| path.display().to_string()),
+            ));
+            continue;
+        }
+        // Cue sheet tracks share their file's identity and already carry their names.
+        if music_file.segment.is_some() {
+            continue;
+        }
+        if let Some(music_map) = load_meta_cache(cache_dir, &identity) {
+            // The file may have been renamed since its metadata was cached.
+            found.push(MusicMap {
+                key: music_file.resume_key(),
+                name: music_file.name,
+                ..music_map
+            });
+        } else if !recently_missed(cache_dir, &identity) {
+            remaining.push(Lookup {
+                music_file,
+                identity,
+            });
+        }
+    }
+    (found, remaining, updated)
+}
+
+/// The file's identity and local artwork. Both are taken from `stored` while the
+/// file keeps its size and modification time and the artwork is still cached;
+/// files without artwork of their own are only checked for a newly added sidecar
+/// image.
+fn file_info(
+    music_path: &str,
+    stored: Option<&StoredFileInfo>,
+    cache_dir: &Path,
+) -> (StoredFileInfo, Option<CachedArtwork>) {
+    let path = file_reader::local_path(music_path);
+    let (size, modified) = file_stamp(&path);
+    let current = stored.filter(|info| info.size == size && info.modified == modified);
+    if let Some(info) = current {
+        match &info.artwork {
+            Some(image_path) if Path::new(image_path).exists() => {
+                let image_path = PathBuf::from(image_path);
+                touch(&image_path);
+                let artwork = CachedArtwork {
+                    thumbnail_path: artwork::thumbnail(&image_path, artwork::LIST_THUMBNAIL_SIZE),
+                    image_path,
+                };
+                return (info.clone(), Some(artwork));
+            }
+            None => {
+                let artwork = artwork::local_sidecar_artwork(&path, cache_dir);
+                let info = StoredFileInfo {
+                    artwork: artwork
+                        .as_ref()
+                        .map(|artwork| artwork.image_path.display().to_string()),
+                    ..info.clone()
+                };
+                return (info, artwork);
+            }
+            // Evicted from the cache, so read the file again.
+            Some(_) => {}
+        }
+    }
+
+    let identity = current
+        .map(|info| info.identity.clone())
+        .unwrap_or_else(|| file_identity(music_path));
+    let artwork = artwork::local_artwork(&path, cache_dir);
+    let info = StoredFileInfo::new(
+        identity,
+        artwork
+            .as_ref()
+            .map(|artwork| artwork.image_path.display().to_string()),
+        size,
+        modified,
+    );
+    (info, artwork)
+}
+
+/// The size and modification time, in seconds, that stored file infos are checked
+/// against.
+fn file_stamp(path: &Path) -> (u64, u64) {
+    let Ok(metadata) = fs::metadata(path) else {
+        return (0, 0);
+    };
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|time| time.as_secs())
+        .unwrap_or(0);
+    (metadata.len(), modified)
+}
+
+/// The identity of the file at `music_path`, taken from `stored` while the file
+/// keeps its size and modification time.
+pub fn current_identity(music_path: &str, stored: Option<&StoredFileInfo>) -> String {
+    let (size, modified) = file_stamp(&file_reader::local_path(music_path));
+    match stored.filter(|info| info.size == size && info.modified == modified) {
+        Some(info) => info.identity.clone(),
+        None => file_identity(music_path),
+    }
+}
+
+/// Names a file's cached metadata by its audio content, falling back to its path
+/// for files whose packets cannot be read.
+fn file_identity(music_path: &str) -> String {
+    file_reader::track_identity(&file_reader::local_path(music_path))
+        .unwrap_or_else(|| format!("{:x}", md5::compute(music_path)))
+}
+
+/// Where lyrics fetched for `music_path` are cached.
+pub fn lyrics_path(cache_dir: &Path, music_path: &str) -> PathBuf {
+    cache_dir
+        .join(CACHE_DIR)
+        .join(LYRICS_DIR)
+        .join(format!("{}.lrc", file_identity(music_path)))
+}
 
-        let client = reqwest::Client::builder()
-            .user_agent(USER_AGENT)
-            .build()
-            .map_err(|e| Error::other(format!("failed to build client: {}", e)))?;
-
-        let text = client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e| Error::other(format!("request failed: {}", e)))?
-            .text()
-            .await
-            .map_err(|e| Error::other(format!("failed to read response: {}", e)))?;
-
-        let mut music_info: MusicDataRes = serde_json::from_str(&text)
-            .map_err(|e| Error::other(format!("failed to parse response: {}", e)))?;
-        music_info.music_name = music_file.name.clone();
-        Ok::<MusicDataRes, Error>(music_info)
+/// Loads cached metadata, treating it as missing once its artwork has been evicted.
+fn load_meta_cache(cache_dir: &Path, identity: &str) -> Option<MusicMap> {
+    let path = cache_dir.join(META_DIR).join(format!("{}.json", identity));
+    let meta = fs::read_to_string(&path).ok()?;
+    let music_map: MusicMap = serde_json::from_str(&meta).ok()?;
+    if !Path::new(&music_map.image_path).exists() {
+        return None;
+    }
+    touch(&path);
+    touch(Path::new(&music_map.image_path));
+    if let Some(thumbnail_path) = &music_map.thumbnail_path {
+        touch(Path::new(thumbnail_path));
+    }
+    Some(music_map)
+}
+
+/// Whether the track was looked up without a match less than `MISS_TTL_SECS` ago.
+fn recently_missed(cache_dir: &Path, identity: &str) -> bool {
+    let path = cache_dir.join(META_DIR).join(format!("{}.miss", identity));
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|miss| serde_json::from_str::<CachedMiss>(&miss).ok())
+        .is_some_and(|miss| Utc::now().timestamp() - miss.checked_at < MISS_TTL_SECS)
+}
+
+fn save_miss_to_cache(cache_dir: &Path, identity: &str) {
+    let meta_dir = cache_dir.join(META_DIR);
+    let miss = CachedMiss {
+        checked_at: Utc::now().timestamp(),
+    };
+    let result = fs::create_dir_all(&meta_dir).and_then(|_| {
+        let json = serde_json::to_string(&miss).map_err(Error::other)?;
+        fs::write(meta_dir.join(format!("{}.miss", identity)), json)
     });
+    if let Err(e) = result {
+        warn!("failed to save negative lookup: {}", e);
+    }
+}
+
+/// Drops the cached metadata, lyrics and negative lookups of `music_paths`, so the
+/// next lookup asks the providers again.
+pub fn forget(cache_dir: PathBuf, music_paths: &[String]) {
+    let meta_dir = cache_dir.join(CACHE_DIR).join(META_DIR);
+    let lyrics_dir = cache_dir.join(CACHE_DIR).join(LYRICS_DIR);
+    for music_path in music_paths {
+        let identity = file_identity(music_path);
+        for extension in ["json", "miss"] {
+            let _ = fs::remove_file(meta_dir.join(format!("{}.{}", identity, extension)));
+        }
+        let _ = fs::remove_file(lyrics_dir.join(format!("{}.lrc", identity)));
+    }
+}
 
-    join_all(futures).await
+/// Marks a cache entry as used; eviction removes the least recently touched files
+/// first.
+pub fn touch(path: &Path) {
+    if let Ok(file) = fs::File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
 }
 
-async fn save_img_to_cache(url: &str, filename: &str, cache_dir: &Path) -> Result<PathBuf, Error> {
-    let file_path = cache_dir.join(filename);
-    if file_path.exists() {
-        return Ok(file_path);
+/// Evicts the least recently used files until the cache fits in `limit` bytes and
+/// returns how many bytes were freed. Artwork, metadata and anything else stored
+/// under the cache share the one budget.
+pub fn enforce_limit(cache_dir: PathBuf, limit: u64) -> u64 {
+    let mut entries = Vec::new();
+    collect_entries(&cache_dir.join(CACHE_DIR), &mut entries);
+    let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    if total <= limit {
+        return 0;
     }
 
-    let client = reqwest::Client::builder()
-        .user_agent(USER_AGENT)
-        .build()
-        .map_err(|e| Error::other(format!("failed to build client: {}", e)))?;
+    entries.sort_by_key(|(modified, _, _)| *modified);
+    let mut freed = 0;
+    for (_, size, path) in entries {
+        if total <= limit {
+            break;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                total -= size;
+                freed += size;
+            }
+            Err(err) => warn!("failed to evict {}: {}", path.display(), err),
+        }
+    }
+    freed
+}
 
-    let bytes = client
-        .get(url)
-        .send()
-        .await
-        .map_err(|e| Error::other(format!("request failed: {}", e)))?
-        .bytes()
+fn collect_entries(dir: &Path, entries: &mut Vec<(SystemTime, u64, PathBuf)>) {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in read_dir.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            collect_entries(&entry.path(), entries);
+        } else if metadata.is_file() {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            entries.push((modified, metadata.len(), entry.path()));
+        }
+    }
+}
+
+/// Looks up at most `LOOKUP_CONCURRENCY` files at a time; the client further limits
+/// the requests they make.
+async fn request_music_data(
+    client: &HttpClient,
+    lookups: Vec<Lookup>,
+    config: &LookupConfig,
+) -> Vec<(Lookup, Result<MatchOutcome, Error>)> {
+    stream::iter(lookups)
+        .map(|lookup| async move {
+            let result = async {
+                let query = track_query(client, &lookup.music_file, config).await?;
+                search(client, &config.providers, &query).await
+            }
+            .await;
+            (lookup, result)
+        })
+        .buffer_unordered(LOOKUP_CONCURRENCY)
+        .collect::<Vec<_>>()
         .await
-        .map_err(|e| Error::other(format!("failed to read bytes: {}", e)))?;
+}
+
+/// Describes a file by its tags, its AcoustID match or, failing both, its path.
+async fn track_query(
+    client: &HttpClient,
+    music_file: &MusicFile,
+    config: &LookupConfig,
+) -> Result<TrackQuery, Error> {
+    let music_meta = player::load_metadata(&music_file.path, &config.strip_patterns);
+    let non_empty = |value: String| Some(value).filter(|value| !value.is_empty());
+    let query = match (music_meta, &config.acoustid) {
+        (Some(meta), _) if !meta.title.is_empty() => TrackQuery::new(
+            Some(meta.title),
+            non_empty(meta.artist),
+            non_empty(meta.album),
+        ),
+        (_, Some(acoustid)) => {
+            let (artist, title) = identify(client, acoustid, &music_file.path).await?;
+            TrackQuery::new(Some(title), non_empty(artist), None)
+        }
+        (_, None) => path_query(music_file, &config.path_patterns),
+    };
+    Ok(query.with_duration(decoded_duration(&music_file.path)))
+}
+
+pub fn decoded_duration(music_path: &str) -> Option<f64> {
+    match file_reader::sniff_audio_file(&file_reader::local_path(music_path)) {
+        AudioSniff::Playable(duration) => duration,
+        _ => None,
+    }
+}
+
+/// Builds a query from the tags encoded in a file's path, falling back to its bare
+/// name when no pattern fits.
+fn path_query(music_file: &MusicFile, path_patterns: &[PathPattern]) -> TrackQuery {
+    path_patterns
+        .iter()
+        .find_map(|pattern| pattern.parse_path(Path::new(&music_file.path)))
+        .filter(|tags| tags.title.is_some())
+        .map(|tags| TrackQuery::new(tags.title, tags.artist, tags.album))
+        .unwrap_or_else(|| TrackQuery::from_keyword(music_file.name.clone()))
+}
 
-    fs::write(&file_path, &bytes)?;
-    Ok(file_path)
+/// Asks each provider in turn until one returns a candidate that scores above the
+/// threshold. Fails only when no provider could be reached, so that a network error
+/// is not recorded as a track without a match.
+async fn search(
+    client: &HttpClient,
+    providers: &[Box<dyn MetadataProvider>],
+    query: &TrackQuery,
+) -> Result<MatchOutcome, Error> {
+    let mut last_error = None;
+    let mut answered = false;
+    for provider in providers.iter().filter(|provider| provider.can_search()) {
+        match provider.search(client, query).await {
+            Ok(matches) => {
+                answered = true;
+                match matching::rank(query, matches) {
+                    MatchOutcome::Rejected => {}
+                    outcome => return Ok(outcome),
+                }
+            }
+            Err(err) => {
+                warn!("{:?} search failed: {}", provider.kind(), err);
+                last_error = Some(err);
+            }
+        }
+    }
+    match last_error {
+        Some(err) if !answered => Err(err),
+        _ => Ok(MatchOutcome::Rejected),
+    }
 }
 
-fn save_meta_to_cache(music_map: &MusicMap, cache_dir: &Path) {
-    if let Ok(json) = serde_json::to_string(music_map)
-        && let Err(e) = fs::write(
-            cache_dir.join(format!("{:x}.json", md5::compute(&music_map.name))),
-            json,
-        )
-    {
+/// Uses the match's own artwork if it has any, otherwise asks each provider.
+async fn resolve_artwork(
+    client: &HttpClient,
+    providers: &[Box<dyn MetadataProvider>],
+    found: &TrackMatch,
+) -> Option<String> {
+    if let Some(url) = &found.artwork_url {
+        return Some(url.clone());
+    }
+    for provider in providers {
+        match provider.artwork(client, found).await {
+            Ok(Some(url)) => return Some(url),
+            Ok(None) => {}
+            Err(err) => warn!("{:?} artwork lookup failed: {}", provider.kind(), err),
+        }
+    }
+    None
+}
+
+/// Looks up a file's fingerprint on an AcoustID-compatible server and returns the
+/// artist and title of the best scoring recording.
+async fn identify(
+    client: &HttpClient,
+    config: &AcoustIdConfig,
+    music_path: &str,
+) -> Result<(String, String), Error> {
+    let path = music_path.to_string();
+    let stored = config.fingerprints.get(music_path).cloned();
+    let stored = tauri::async_runtime::spawn_blocking(move || {
+        fingerprint::stored_or_compute(&path, stored.as_ref())
+    })
+    .await
+    .map_err(|e| Error::other(format!("fingerprint task failed: {}", e)))?
+    .map_err(|e| Error::other(format!("failed to fingerprint: {}", e)))?;
+
+    let url = format!(
+        "{}?format=json&meta=recordings&client={}&duration={}&fingerprint={}",
+        config.url,
+        urlencoding::encode(&config.client_key),
+        stored.duration.round() as u64,
+        stored.fingerprint
+    );
+    let text = client
+        .get_text(&url)
+        .await?
+        .ok_or_else(|| Error::other("acoustid lookup failed: not found"))?;
+    let response: AcoustIdRes = serde_json::from_str(&text)
+        .map_err(|e| Error::other(format!("failed to parse response: {}", e)))?;
+    if response.status != "ok" {
+        return Err(Error::other(format!(
+            "acoustid lookup failed: {}",
+            response.error.map(|e| e.message).unwrap_or(response.status)
+        )));
+    }
+
+    let mut results = response.results;
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+    results
+        .into_iter()
+        .filter(|result| result.score >= MIN_ACOUSTID_SCORE)
+        .flat_map(|result| result.recordings)
+        .find_map(|recording| {
+            let title = recording.title?;
+            let artist = recording
+                .artists
+                .into_iter()
+                .map(|artist| artist.name)
+                .collect::<Vec<_>>()
+                .join(", ");
+            Some((artist, title))
+        })
+        .ok_or_else(|| Error::other("no acoustid match"))
+}
+
+async fn save_img_to_cache(
+    client: &HttpClient,
+    url: &str,
+    cache_dir: &Path,
+) -> Result<CachedArtwork, Error> {
+    let bytes = client.get_bytes(url).await?;
+
+    let cache_dir = cache_dir.to_path_buf();
+    tauri::async_runtime::spawn_blocking(move || artwork::save_artwork(&bytes, &cache_dir))
+        .await
+        .map_err(|e| Error::other(format!("artwork task failed: {}", e)))?
+}
+
+fn save_meta_to_cache(music_map: &MusicMap, cache_dir: &Path, identity: &str) {
+    let meta_dir = cache_dir.join(META_DIR);
+    let result = fs::create_dir_all(&meta_dir).and_then(|_| {
+        let json = serde_json::to_string(music_map).map_err(Error::other)?;
+        fs::write(meta_dir.join(format!("{}.json", identity)), json)
+    });
+    if let Err(e) = result {
         warn!("failed to save meta cache: {}", e);
     }
 }
@@ -227,45 +748,145 @@ pub fn get_cache_dir_size(cache_dir: PathBuf) -> String {
     format!("{:.2}", size_mb)
 }
 
+/// Records a lookup that found no match, so the track is not looked up on every start.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct MusicDataRes {
-    #[serde(skip)]
-    pub music_name: String,
-    pub result_count: u32,
-    pub results: Vec<Body>,
+struct CachedMiss {
+    checked_at: i64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Body {
-    pub wrapper_type: Option<String>,
-    pub kind: Option<String>,
-    pub artist_id: Option<i64>,
-    pub collection_id: Option<i64>,
-    pub track_id: Option<i64>,
-    pub artist_name: Option<String>,
-    pub collection_name: Option<String>,
-    pub track_name: Option<String>,
-    pub collection_censored_name: Option<String>,
-    pub track_censored_name: Option<String>,
-    pub artist_view_url: Option<String>,
-    pub collection_view_url: Option<String>,
-    pub track_view_url: Option<String>,
-    pub preview_url: Option<String>,
-    pub artwork_url30: Option<String>,
-    pub artwork_url60: Option<String>,
-    pub artwork_url100: Option<String>,
-    pub release_date: Option<String>,
-    pub collection_explicitness: Option<String>,
-    pub track_explicitness: Option<String>,
-    pub disc_count: Option<i32>,
-    pub disc_number: Option<i32>,
-    pub track_count: Option<i32>,
-    pub track_number: Option<i32>,
-    pub track_time_millis: Option<i64>,
-    pub country: Option<String>,
-    pub currency: Option<String>,
-    pub primary_genre_name: Option<String>,
-    pub is_streamable: Option<bool>,
+#[derive(Debug, Deserialize)]
+pub struct AcoustIdRes {
+    pub status: String,
+    #[serde(default)]
+    pub error: Option<AcoustIdError>,
+    #[serde(default)]
+    pub results: Vec<AcoustIdResult>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcoustIdError {
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcoustIdResult {
+    pub score: f64,
+    #[serde(default)]
+    pub recordings: Vec<AcoustIdRecording>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcoustIdRecording {
+    pub title: Option<String>,
+    #[serde(default)]
+    pub artists: Vec<AcoustIdArtist>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcoustIdArtist {
+    pub name: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TempDir, TestServer};
+
+    /// A config whose fingerprint for `path` is already known, so `identify` only
+    /// talks to the server.
+    fn config(server: &TestServer, dir: &TempDir) -> (AcoustIdConfig, String) {
+        let path = dir.path().join("track.mp3");
+        fs::write(&path, b"not decoded").unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let metadata = fs::metadata(&path).unwrap();
+        let modified = metadata
+            .modified()
+            .unwrap()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let fingerprints = HashMap::from([(
+            path.clone(),
+            StoredFingerprint::new(
+                "AQAAfingerprint".to_string(),
+                215.4,
+                metadata.len(),
+                modified,
+            ),
+        )]);
+        let config = AcoustIdConfig::new(
+            format!("{}/v2/lookup", server.url),
+            "test key".to_string(),
+            fingerprints,
+        );
+        (config, path)
+    }
+
+    fn lookup(server: &TestServer) -> Result<(String, String), Error> {
+        let dir = TempDir::new();
+        let (config, path) = config(server, &dir);
+        let client = HttpClient::new().unwrap();
+        tauri::async_runtime::block_on(identify(&client, &config, &path))
+    }
+
+    #[test]
+    fn identify_picks_the_best_scoring_recording() {
+        let server = TestServer::start(|_| {
+            (
+                200,
+                r#"{"status":"ok","results":[
+                    {"score":0.4,"recordings":[{"title":"Low","artists":[{"name":"Nobody"}]}]},
+                    {"score":0.93,"recordings":[
+                        {"artists":[{"name":"Untitled"}]},
+                        {"title":"Best","artists":[{"name":"One"},{"name":"Two"}]}
+                    ]}
+                ]}"#
+                .to_string(),
+            )
+        });
+
+        let (artist, title) = lookup(&server).unwrap();
+        assert_eq!(artist, "One, Two");
+        assert_eq!(title, "Best");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("/v2/lookup?format=json&meta=recordings"));
+        assert!(requests[0].contains("client=test%20key"));
+        assert!(requests[0].contains("duration=215"));
+        assert!(requests[0].contains("fingerprint=AQAAfingerprint"));
+    }
+
+    #[test]
+    fn identify_ignores_low_scores() {
+        let server = TestServer::start(|_| {
+            (
+                200,
+                r#"{"status":"ok","results":[{"score":0.2,"recordings":[{"title":"Weak"}]}]}"#
+                    .to_string(),
+            )
+        });
+        let err = lookup(&server).unwrap_err();
+        assert_eq!(err.to_string(), "no acoustid match");
+    }
+
+    #[test]
+    fn identify_reports_server_errors() {
+        let server = TestServer::start(|_| {
+            (
+                200,
+                r#"{"status":"error","error":{"code":4,"message":"invalid API key"}}"#.to_string(),
+            )
+        });
+        let err = lookup(&server).unwrap_err();
+        assert_eq!(err.to_string(), "acoustid lookup failed: invalid API key");
+    }
+
+    #[test]
+    fn identify_treats_not_found_as_failure() {
+        let server = TestServer::start(|_| (404, String::new()));
+        let err = lookup(&server).unwrap_err();
+        assert_eq!(err.to_string(), "acoustid lookup failed: not found");
+    }
 }
@@ -0,0 +1,649 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    sync::Mutex,
+};
+
+use lofty::{
+    config::ParseOptions,
+    id3::v2::{Frame, Id3v2Version},
+    mpeg::MpegFile,
+};
+use symphonia::core::{io::MediaSourceStream, meta::StandardTagKey, probe::Hint};
+use tauri::{AppHandle, Manager};
+
+use crate::{music::Chapter, state::ChapterState};
+
+/// Nero `chpl` chapter times count 100 ns units.
+const CHPL_UNITS_PER_SECOND: f64 = 10_000_000.0;
+
+/// Matroska chapter times count nanoseconds.
+const MATROSKA_UNITS_PER_SECOND: f64 = 1_000_000_000.0;
+
+/// FLAC cue sheets end with a lead-out track numbered 170 (CD-DA) or 255.
+const LEAD_OUT_TRACKS: [u32; 2] = [170, 255];
+
+/// A chapter starting within this many seconds of the position is the one
+/// being played, not the next.
+const NEXT_CHAPTER_MARGIN_SECS: f64 = 0.5;
+
+/// Going back this far into a chapter restarts it instead of going to the
+/// previous one.
+const RESTART_CHAPTER_SECS: f64 = 3.0;
+
+/// Samples read from a QuickTime chapter track at most, whatever its tables claim.
+const MAX_QUICKTIME_CHAPTERS: usize = 10_000;
+
+const EBML_SEGMENT: u64 = 0x1853_8067;
+const EBML_CLUSTER: u64 = 0x1F43_B675;
+const EBML_CHAPTERS: u64 = 0x1043_A770;
+const EBML_EDITION_ENTRY: u64 = 0x45B9;
+const EBML_EDITION_FLAG_DEFAULT: u64 = 0x45DB;
+const EBML_CHAPTER_ATOM: u64 = 0xB6;
+const EBML_CHAPTER_TIME_START: u64 = 0x91;
+const EBML_CHAPTER_TIME_END: u64 = 0x92;
+const EBML_CHAPTER_FLAG_HIDDEN: u64 = 0x98;
+const EBML_CHAPTER_DISPLAY: u64 = 0x80;
+const EBML_CHAP_STRING: u64 = 0x85;
+
+/// Chapters of `path`, sorted by start. The demuxer's own cue points come first;
+/// MP3, MP4 and Matroska chapters that symphonia does not expose are read from
+/// the ID3 CHAP/CTOC frames, the Nero `chpl` atom or a QuickTime chapter track,
+/// and the EBML Chapters element.
+/// Chapters without an end run to the start of the next one.
+pub fn read_chapters(path: &Path) -> Vec<Chapter> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    let mut chapters = container_cues(path, &extension).unwrap_or_default();
+    if chapters.is_empty() {
+        chapters = match extension.as_str() {
+            "mp3" => id3_chapters(path),
+            "m4a" | "m4b" | "mp4" => mp4_chapters(path),
+            "mka" | "mkv" | "webm" => matroska_chapters(path),
+            _ => None,
+        }
+        .unwrap_or_default();
+    }
+
+    chapters.retain(|chapter| chapter.start.is_finite() && chapter.start >= 0.0);
+    chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+    chapters.dedup_by(|next, previous| next.start == previous.start);
+    for index in 1..chapters.len() {
+        let next_start = chapters[index].start;
+        let previous = &mut chapters[index - 1];
+        if previous.end.is_none_or(|end| end <= previous.start) {
+            previous.end = Some(next_start);
+        }
+    }
+    chapters
+}
+
+/// The chapter of track `id` playing at `seconds`.
+pub fn current(app: &AppHandle, id: &str, seconds: f64) -> Option<usize> {
+    app.state::<Mutex<ChapterState>>()
+        .lock()
+        .ok()?
+        .index_at(id, seconds)
+}
+
+/// Start of the chapter after the one playing at `position`.
+pub fn next_start(chapters: &[Chapter], position: f64) -> Option<f64> {
+    chapters
+        .iter()
+        .find(|chapter| chapter.start > position + NEXT_CHAPTER_MARGIN_SECS)
+        .map(|chapter| chapter.start)
+}
+
+/// Start of the chapter playing at `position`, or of the one before it when the
+/// current chapter only just started.
+pub fn previous_start(chapters: &[Chapter], position: f64) -> Option<f64> {
+    let index = chapters
+        .iter()
+        .rposition(|chapter| chapter.start <= position)?;
+    if index == 0 || position - chapters[index].start > RESTART_CHAPTER_SECS {
+        Some(chapters[index].start)
+    } else {
+        Some(chapters[index - 1].start)
+    }
+}
+
+/// Cue points the demuxer reads from the container, such as a FLAC CUESHEET block.
+fn container_cues(path: &Path, extension: &str) -> Option<Vec<Chapter>> {
+    let mut hint = Hint::new();
+    hint.with_extension(extension);
+    let source = File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(source), Default::default());
+    let probed = symphonia::default::get_probe()
+        .format(&hint, mss, &Default::default(), &Default::default())
+        .ok()?;
+    let reader = probed.format;
+    let time_base = reader.default_track()?.codec_params.time_base?;
+    Some(
+        reader
+            .cues()
+            .iter()
+            .filter(|cue| !LEAD_OUT_TRACKS.contains(&cue.index))
+            .map(|cue| {
+                let title = cue
+                    .tags
+                    .iter()
+                    .find(|tag| tag.std_key == Some(StandardTagKey::TrackTitle))
+                    .map(|tag| tag.value.to_string())
+                    .unwrap_or_else(|| format!("Chapter {}", cue.index));
+                let time = time_base.calc_time(cue.start_ts);
+                Chapter::new(title, time.seconds as f64 + time.frac, None)
+            })
+            .collect(),
+    )
+}
+
+/// CHAP frames of an MP3's ID3v2 tag, in the order of the top-level CTOC frame
+/// when there is one.
+fn id3_chapters(path: &Path) -> Option<Vec<Chapter>> {
+    let mut file = File::open(path).ok()?;
+    let mpeg = MpegFile::read_from(&mut file, ParseOptions::new()).ok()?;
+    let tag = mpeg.id3v2()?;
+    let synchsafe = tag.original_version() == Id3v2Version::V4;
+
+    let mut chapters = HashMap::new();
+    let mut order: Option<Vec<String>> = None;
+    for frame in tag {
+        let Frame::Binary(binary) = frame else {
+            continue;
+        };
+        match frame.id_str() {
+            "CHAP" => {
+                if let Some((element_id, chapter)) = parse_chap(&binary.data, synchsafe) {
+                    chapters.insert(element_id, chapter);
+                }
+            }
+            "CTOC" => {
+                if let Some((top_level, entries)) = parse_ctoc(&binary.data)
+                    && (top_level || order.is_none())
+                {
+                    order = Some(entries);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    match order {
+        Some(order) => Some(
+            order
+                .iter()
+                .filter_map(|element_id| chapters.remove(element_id))
+                .collect(),
+        ),
+        None => Some(chapters.into_values().collect()),
+    }
+}
+
+/// A CHAP frame: element id, start and end in milliseconds, byte offsets, then
+/// sub-frames of which TIT2 holds the title.
+fn parse_chap(data: &[u8], synchsafe: bool) -> Option<(String, Chapter)> {
+    let (element_id, rest) = split_terminated(data)?;
+    let start_ms = u32::from_be_bytes(rest.get(0..4)?.try_into().ok()?);
+    let end_ms = u32::from_be_bytes(rest.get(4..8)?.try_into().ok()?);
+
+    let mut sub_frames = rest.get(16..).unwrap_or_default();
+    let mut title = None;
+    while sub_frames.len() >= 10 {
+        let size_bytes: [u8; 4] = sub_frames[4..8].try_into().ok()?;
+        let size = if synchsafe {
+            size_bytes
+                .iter()
+                .fold(0usize, |size, byte| (size << 7) | (*byte & 0x7F) as usize)
+        } else {
+            u32::from_be_bytes(size_bytes) as usize
+        };
+        let Some(body) = sub_frames.get(10..10 + size) else {
+            break;
+        };
+        if &sub_frames[0..4] == b"TIT2" {
+            title = decode_id3_text(body);
+            break;
+        }
+        sub_frames = &sub_frames[10 + size..];
+    }
+
+    let start = start_ms as f64 / 1000.0;
+    let end = (end_ms != u32::MAX).then_some(end_ms as f64 / 1000.0);
+    let title = title.unwrap_or_else(|| element_id.clone());
+    Some((element_id, Chapter::new(title, start, end)))
+}
+
+/// A CTOC frame: element id, flags, entry count and the ids of its entries.
+/// Returns whether it is the top-level table along with the entries.
+fn parse_ctoc(data: &[u8]) -> Option<(bool, Vec<String>)> {
+    let (_, rest) = split_terminated(data)?;
+    let flags = *rest.first()?;
+    let count = *rest.get(1)? as usize;
+    let mut entries = Vec::with_capacity(count);
+    let mut rest = rest.get(2..)?;
+    for _ in 0..count {
+        let (entry, remaining) = split_terminated(rest)?;
+        entries.push(entry);
+        rest = remaining;
+    }
+    Some((flags & 0x02 != 0, entries))
+}
+
+/// Splits a null-terminated ISO-8859-1 string off the front of `data`.
+fn split_terminated(data: &[u8]) -> Option<(String, &[u8])> {
+    let end = data.iter().position(|byte| *byte == 0)?;
+    let text = data[..end].iter().map(|byte| *byte as char).collect();
+    Some((text, &data[end + 1..]))
+}
+
+/// Decodes the body of an ID3 text frame from its declared encoding.
+fn decode_id3_text(body: &[u8]) -> Option<String> {
+    let (&encoding, text) = body.split_first()?;
+    let decoded = match encoding {
+        0 => text.iter().map(|byte| *byte as char).collect(),
+        1 | 2 => {
+            let (big_endian, text) = match text {
+                [0xFE, 0xFF, rest @ ..] => (true, rest),
+                [0xFF, 0xFE, rest @ ..] => (false, rest),
+                _ => (encoding == 2, text),
+            };
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|pair| {
+                    if big_endian {
+                        u16::from_be_bytes([pair[0], pair[1]])
+                    } else {
+                        u16::from_le_bytes([pair[0], pair[1]])
+                    }
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(text).into_owned(),
+    };
+    let decoded = decoded.trim_matches('\0').trim();
+    (!decoded.is_empty()).then(|| decoded.to_string())
+}
+
+/// Chapters of an MP4 file: Nero chapters, as written by most M4B tools, or else
+/// a QuickTime chapter track, as iTunes and Audible write them.
+fn mp4_chapters(path: &Path) -> Option<Vec<Chapter>> {
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    let moov = find_box(&mut file, (0, len), b"moov")?;
+    nero_chapters(&mut file, moov)
+        .filter(|chapters| !chapters.is_empty())
+        .or_else(|| quicktime_chapters(&mut file, moov))
+}
+
+/// Nero chapters from `moov/udta/chpl`.
+fn nero_chapters(file: &mut File, moov: (u64, u64)) -> Option<Vec<Chapter>> {
+    let udta = find_box(file, moov, b"udta")?;
+    let data = read_range(file, find_box(file, udta, b"chpl")?)?;
+
+    let version = *data.first()?;
+    let mut pos = if version > 0 { 8 } else { 4 };
+    let count = *data.get(pos)? as usize;
+    pos += 1;
+    let mut chapters = Vec::with_capacity(count);
+    for _ in 0..count {
+        let start = u64::from_be_bytes(data.get(pos..pos + 8)?.try_into().ok()?);
+        let title_len = *data.get(pos + 8)? as usize;
+        pos += 9;
+        let title = String::from_utf8_lossy(data.get(pos..pos + title_len)?).into_owned();
+        pos += title_len;
+        chapters.push(Chapter::new(
+            title,
+            start as f64 / CHPL_UNITS_PER_SECOND,
+            None,
+        ));
+    }
+    Some(chapters)
+}
+
+/// QuickTime chapters: a text track named by another track's `tref/chap` box,
+/// with one sample per chapter holding its title. Edit lists are not applied.
+fn quicktime_chapters(file: &mut File, moov: (u64, u64)) -> Option<Vec<Chapter>> {
+    let traks: Vec<(u64, u64)> = child_boxes(file, moov)
+        .into_iter()
+        .filter(|(name, _)| name == b"trak")
+        .map(|(_, payload)| payload)
+        .collect();
+    let chapter_track = traks.iter().find_map(|trak| {
+        let tref = find_box(file, *trak, b"tref")?;
+        let chap = read_range(file, find_box(file, tref, b"chap")?)?;
+        be_u32(&chap, 0)
+    })?;
+    let trak = traks.into_iter().find(|trak| {
+        find_box(file, *trak, b"tkhd")
+            .and_then(|tkhd| read_range(file, tkhd))
+            .and_then(|tkhd| be_u32(&tkhd, if tkhd.first() == Some(&1) { 20 } else { 12 }))
+            == Some(chapter_track)
+    })?;
+
+    let mdia = find_box(file, trak, b"mdia")?;
+    let mdhd = read_range(file, find_box(file, mdia, b"mdhd")?)?;
+    let timescale = be_u32(&mdhd, if mdhd.first() == Some(&1) { 20 } else { 12 })?;
+    if timescale == 0 {
+        return None;
+    }
+    let minf = find_box(file, mdia, b"minf")?;
+    let stbl = find_box(file, minf, b"stbl")?;
+    let stts = read_range(file, find_box(file, stbl, b"stts")?)?;
+    let stsz = read_range(file, find_box(file, stbl, b"stsz")?)?;
+    let stsc = read_range(file, find_box(file, stbl, b"stsc")?)?;
+    let chunk_offsets: Vec<u64> = match find_box(file, stbl, b"stco") {
+        Some(stco) => table(&read_range(file, stco)?, 4)
+            .into_iter()
+            .filter_map(|entry| be_u32(entry, 0).map(u64::from))
+            .collect(),
+        None => table(&read_range(file, find_box(file, stbl, b"co64")?)?, 8)
+            .into_iter()
+            .filter_map(|entry| Some(u64::from_be_bytes(entry.try_into().ok()?)))
+            .collect(),
+    };
+
+    // Sizes are listed unless every sample has the same one.
+    let sample_count = (be_u32(&stsz, 8)? as usize).min(MAX_QUICKTIME_CHAPTERS);
+    let sizes: Vec<u32> = match be_u32(&stsz, 4)? {
+        0 => stsz
+            .get(12..)?
+            .chunks_exact(4)
+            .take(sample_count)
+            .filter_map(|entry| be_u32(entry, 0))
+            .collect(),
+        size => vec![size; sample_count],
+    };
+
+    let mut durations = Vec::with_capacity(sizes.len());
+    for entry in table(&stts, 8) {
+        let (count, delta) = (be_u32(entry, 0)?, be_u32(entry, 4)?);
+        let missing = sizes.len() - durations.len();
+        durations.extend(std::iter::repeat_n(delta, (count as usize).min(missing)));
+    }
+
+    // Chunks take their sample count from the last stsc entry starting at or
+    // before them.
+    let runs: Vec<(u32, u32)> = table(&stsc, 12)
+        .into_iter()
+        .filter_map(|entry| Some((be_u32(entry, 0)?, be_u32(entry, 4)?)))
+        .collect();
+    let mut offsets = Vec::with_capacity(sizes.len());
+    for (index, chunk_offset) in chunk_offsets.iter().enumerate() {
+        let chunk = index as u32 + 1;
+        let per_chunk = runs
+            .iter()
+            .rev()
+            .find(|(first, _)| *first <= chunk)
+            .map_or(0, |(_, count)| *count);
+        let mut offset = *chunk_offset;
+        for _ in 0..per_chunk {
+            let Some(size) = sizes.get(offsets.len()) else {
+                break;
+            };
+            offsets.push(offset);
+            offset = offset.saturating_add(u64::from(*size));
+        }
+    }
+
+    let mut chapters = Vec::with_capacity(offsets.len());
+    let mut time = 0u64;
+    for (index, ((offset, size), delta)) in offsets.iter().zip(&sizes).zip(&durations).enumerate() {
+        // A text sample is a 16-bit length followed by the text.
+        let end = offset.saturating_add(u64::from(*size).min(2 + 0xFFFF));
+        let sample = read_range(file, (*offset, end)).unwrap_or_default();
+        let title = sample
+            .get(0..2)
+            .map(|len| u16::from_be_bytes([len[0], len[1]]) as usize)
+            .and_then(|len| sample.get(2..2 + len))
+            .and_then(decode_quicktime_text)
+            .unwrap_or_else(|| format!("Chapter {}", index + 1));
+        let start = time as f64 / timescale as f64;
+        time += u64::from(*delta);
+        chapters.push(Chapter::new(
+            title,
+            start,
+            Some(time as f64 / timescale as f64),
+        ));
+    }
+    Some(chapters)
+}
+
+/// The entries of a full box's table: a version and flags, an entry count, then
+/// entries of `entry_len` bytes.
+fn table(data: &[u8], entry_len: usize) -> Vec<&[u8]> {
+    let count = be_u32(data, 4).unwrap_or(0) as usize;
+    data.get(8..)
+        .unwrap_or_default()
+        .chunks_exact(entry_len)
+        .take(count)
+        .collect()
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// QuickTime text is UTF-8 unless it starts with a UTF-16 byte order mark.
+fn decode_quicktime_text(text: &[u8]) -> Option<String> {
+    let decoded = match text {
+        [0xFE, 0xFF, rest @ ..] | [0xFF, 0xFE, rest @ ..] => {
+            let big_endian = text[0] == 0xFE;
+            let units: Vec<u16> = rest
+                .chunks_exact(2)
+                .map(|pair| {
+                    if big_endian {
+                        u16::from_be_bytes([pair[0], pair[1]])
+                    } else {
+                        u16::from_le_bytes([pair[0], pair[1]])
+                    }
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(text).into_owned(),
+    };
+    let decoded = decoded.trim_matches('\0').trim();
+    (!decoded.is_empty()).then(|| decoded.to_string())
+}
+
+/// Finds the box `name` among the boxes in `range` and returns its payload range.
+fn find_box(file: &mut File, range: (u64, u64), name: &[u8; 4]) -> Option<(u64, u64)> {
+    child_boxes(file, range)
+        .into_iter()
+        .find(|(box_name, _)| box_name == name)
+        .map(|(_, payload)| payload)
+}
+
+/// The names and payload ranges of the boxes in `range`, up to the first one that
+/// is malformed.
+fn child_boxes(file: &mut File, range: (u64, u64)) -> Vec<([u8; 4], (u64, u64))> {
+    let (mut offset, end) = range;
+    let mut boxes = Vec::new();
+    while offset + 8 <= end {
+        let mut header = [0u8; 8];
+        if file.seek(SeekFrom::Start(offset)).is_err() || file.read_exact(&mut header).is_err() {
+            break;
+        }
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            let mut large = [0u8; 8];
+            if file.read_exact(&mut large).is_err() {
+                break;
+            }
+            size = u64::from_be_bytes(large);
+            header_len = 16;
+        } else if size == 0 {
+            size = end - offset;
+        }
+        if size < header_len {
+            break;
+        }
+        let name = [header[4], header[5], header[6], header[7]];
+        let box_end = offset.saturating_add(size).min(end);
+        boxes.push((name, (offset + header_len, box_end)));
+        offset = box_end;
+    }
+    boxes
+}
+
+/// The bytes of `range`.
+fn read_range(file: &mut File, (start, end): (u64, u64)) -> Option<Vec<u8>> {
+    let mut data = vec![0; usize::try_from(end.checked_sub(start)?).ok()?];
+    file.seek(SeekFrom::Start(start)).ok()?;
+    file.read_exact(&mut data).ok()?;
+    Some(data)
+}
+
+/// Chapters of the default edition of a Matroska file. Top-level elements of the
+/// segment are skipped by size until the Chapters element turns up.
+fn matroska_chapters(path: &Path) -> Option<Vec<Chapter>> {
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+
+    let mut offset = 0;
+    let segment_end = loop {
+        let (id, size, header_len) = read_element_header(&mut file, offset)?;
+        offset += header_len;
+        if id == EBML_SEGMENT {
+            break size.map_or(len, |size| (offset + size).min(len));
+        }
+        offset += size?;
+    };
+
+    while offset < segment_end {
+        let (id, size, header_len) = read_element_header(&mut file, offset)?;
+        offset += header_len;
+        if id == EBML_CHAPTERS {
+            let end = offset.checked_add(size?)?;
+            if end > segment_end {
+                return None;
+            }
+            let data = read_range(&mut file, (offset, end))?;
+            return Some(parse_matroska_chapters(&data));
+        }
+        // A cluster of unknown size cannot be skipped.
+        if id == EBML_CLUSTER && size.is_none() {
+            return None;
+        }
+        offset += size?;
+    }
+    None
+}
+
+fn parse_matroska_chapters(data: &[u8]) -> Vec<Chapter> {
+    let editions: Vec<&[u8]> = ebml_children(data)
+        .into_iter()
+        .filter(|(id, _)| *id == EBML_EDITION_ENTRY)
+        .map(|(_, body)| body)
+        .collect();
+    let edition = editions
+        .iter()
+        .find(|edition| {
+            ebml_children(edition)
+                .iter()
+                .any(|(id, body)| *id == EBML_EDITION_FLAG_DEFAULT && ebml_uint(body) == 1)
+        })
+        .or(editions.first());
+    let Some(edition) = edition else {
+        return Vec::new();
+    };
+
+    ebml_children(edition)
+        .into_iter()
+        .filter(|(id, _)| *id == EBML_CHAPTER_ATOM)
+        .filter_map(|(_, atom)| {
+            let mut start = None;
+            let mut end = None;
+            let mut title = None;
+            for (id, body) in ebml_children(atom) {
+                match id {
+                    EBML_CHAPTER_TIME_START => start = Some(ebml_uint(body)),
+                    EBML_CHAPTER_TIME_END => end = Some(ebml_uint(body)),
+                    EBML_CHAPTER_FLAG_HIDDEN if ebml_uint(body) == 1 => return None,
+                    EBML_CHAPTER_DISPLAY if title.is_none() => {
+                        title = ebml_children(body)
+                            .into_iter()
+                            .find(|(id, _)| *id == EBML_CHAP_STRING)
+                            .map(|(_, text)| {
+                                String::from_utf8_lossy(text)
+                                    .trim_end_matches('\0')
+                                    .to_string()
+                            });
+                    }
+                    _ => {}
+                }
+            }
+            let start = start? as f64 / MATROSKA_UNITS_PER_SECOND;
+            let end = end.map(|end| end as f64 / MATROSKA_UNITS_PER_SECOND);
+            Some((start, end, title))
+        })
+        .enumerate()
+        .map(|(index, (start, end, title))| {
+            let title = title.unwrap_or_else(|| format!("Chapter {}", index + 1));
+            Chapter::new(title, start, end)
+        })
+        .collect()
+}
+
+/// Reads the id, size (`None` when unknown) and header length of the element at
+/// `offset`.
+fn read_element_header(file: &mut File, offset: u64) -> Option<(u64, Option<u64>, u64)> {
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    let mut header = [0u8; 12];
+    let read = file.read(&mut header).ok()?;
+    let header = &header[..read];
+    let (id, id_len) = ebml_vint(header, true)?;
+    let (size, size_len) = ebml_vint(&header[id_len..], false)?;
+    let unknown = size == (1u64 << (7 * size_len)) - 1;
+    Some((id, (!unknown).then_some(size), (id_len + size_len) as u64))
+}
+
+/// The child elements of an element body, as ids and bodies.
+fn ebml_children(mut data: &[u8]) -> Vec<(u64, &[u8])> {
+    let mut children = Vec::new();
+    while let Some((id, id_len)) = ebml_vint(data, true)
+        && let Some((size, size_len)) = ebml_vint(&data[id_len..], false)
+    {
+        let start = id_len + size_len;
+        let Some(body) = usize::try_from(size)
+            .ok()
+            .and_then(|size| data.get(start..start.checked_add(size)?))
+        else {
+            break;
+        };
+        children.push((id, body));
+        data = &data[start + body.len()..];
+    }
+    children
+}
+
+/// An EBML variable-length integer; element ids keep their length marker.
+fn ebml_vint(data: &[u8], keep_marker: bool) -> Option<(u64, usize)> {
+    let first = *data.first()?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 || data.len() < len {
+        return None;
+    }
+    let first = if keep_marker {
+        first
+    } else {
+        first & 0xFFu8.checked_shr(len as u32).unwrap_or(0)
+    };
+    let value = data[1..len]
+        .iter()
+        .fold(first as u64, |value, byte| (value << 8) | *byte as u64);
+    Some((value, len))
+}
+
+fn ebml_uint(body: &[u8]) -> u64 {
+    body.iter()
+        .take(8)
+        .fold(0, |value, byte| (value << 8) | *byte as u64)
+}
@@ -0,0 +1,359 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use lofty::{prelude::*, tag::ItemKey};
+use uuid::Uuid;
+
+use crate::{
+    file_reader,
+    music::{MusicFile, TrackSegment},
+    playlist_file,
+};
+
+/// CUE timestamps count frames of 1/75 s.
+const FRAMES_PER_SECOND: f64 = 75.0;
+
+/// Formats whose tags commonly carry the whole cue sheet.
+const EMBEDDED_CUE_EXTENSIONS: [&str; 4] = ["flac", "ape", "wv", "tta"];
+
+/// Tag names of an embedded cue sheet, in Vorbis comments and APE tags.
+const CUESHEET_KEYS: [&str; 2] = ["CUESHEET", "Cuesheet"];
+
+/// One audio track of a cue sheet.
+#[derive(Clone, Debug)]
+pub struct CueTrack {
+    pub file: PathBuf,
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub album: Option<String>,
+    pub segment: TrackSegment,
+}
+
+impl CueTrack {
+    pub fn into_music_file(self, id: String) -> MusicFile {
+        let path = file_reader::path_string(&self.file);
+        let name = self
+            .title
+            .unwrap_or_else(|| format!("Track {:02}", self.number));
+        MusicFile::new(id, name, path, None, self.performer, self.album)
+            .with_segment(Some(self.segment))
+    }
+}
+
+/// A track while its cue sheet is being read; tracks without an INDEX 01 are
+/// dropped.
+struct SheetTrack {
+    file: PathBuf,
+    number: u32,
+    title: Option<String>,
+    performer: Option<String>,
+    start: Option<f64>,
+}
+
+/// The entries `path` adds to a track list: one per track of `cue_tracks`, or the
+/// file itself, named `name`, when no cue sheet describes it.
+pub fn expand(path: String, name: String, cue_tracks: Option<Vec<CueTrack>>) -> Vec<MusicFile> {
+    match cue_tracks {
+        Some(tracks) => tracks
+            .into_iter()
+            .map(|track| track.into_music_file(Uuid::new_v4().to_string()))
+            .collect(),
+        None => vec![MusicFile::new(
+            Uuid::new_v4().to_string(),
+            name,
+            path,
+            None,
+            None,
+            None,
+        )],
+    }
+}
+
+/// Virtual tracks for `path`: the tracks of a `.cue` file, or of the cue sheet
+/// describing an audio file, whether it sits next to the file or is embedded in
+/// its tags. `None` when there is no cue sheet.
+pub fn tracks_for(path: &Path) -> Option<Vec<CueTrack>> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    let tracks = if extension == "cue" {
+        read_sheet(path)?
+    } else if let Some(tracks) = sidecar_tracks(path) {
+        tracks
+    } else if EMBEDDED_CUE_EXTENSIONS.contains(&extension.as_str()) {
+        embedded_tracks(path)?
+    } else {
+        return None;
+    };
+    (!tracks.is_empty()).then_some(tracks)
+}
+
+fn read_sheet(path: &Path) -> Option<Vec<CueTrack>> {
+    let bytes = fs::read(path).ok()?;
+    let text = playlist_file::decode(&bytes);
+    Some(parse(&text, path.parent()?, None))
+}
+
+/// `album.cue` or `album.flac.cue` next to `album.flac`, keeping only the tracks
+/// that play from it.
+fn sidecar_tracks(audio_path: &Path) -> Option<Vec<CueTrack>> {
+    let dir = audio_path.parent()?;
+    let candidates = [
+        audio_path.with_extension("cue"),
+        dir.join(format!("{}.cue", audio_path.file_name()?.to_str()?)),
+    ];
+    candidates
+        .iter()
+        .filter(|candidate| candidate.is_file())
+        .filter_map(|candidate| read_sheet(candidate))
+        .map(|tracks| {
+            tracks
+                .into_iter()
+                .filter(|track| track.file == audio_path)
+                .collect::<Vec<_>>()
+        })
+        .find(|tracks| !tracks.is_empty())
+}
+
+/// A CUESHEET tag describes the file it is stored in, whatever its FILE lines say.
+fn embedded_tracks(audio_path: &Path) -> Option<Vec<CueTrack>> {
+    let tagged_file = lofty::read_from_path(audio_path).ok()?;
+    let sheet = tagged_file.tags().iter().find_map(|tag| {
+        CUESHEET_KEYS
+            .iter()
+            .find_map(|key| tag.get_string(&ItemKey::Unknown(key.to_string())))
+            .map(str::to_string)
+    })?;
+    Some(parse(&sheet, audio_path.parent()?, Some(audio_path)))
+}
+
+/// Parses the audio tracks of a cue sheet. A track starts at its INDEX 01 and ends
+/// where the next track of the same file starts, so consecutive tracks join up
+/// without gaps; the last one runs to the end of the file.
+fn parse(text: &str, base_dir: &Path, audio_override: Option<&Path>) -> Vec<CueTrack> {
+    let mut album = None;
+    let mut album_performer = None;
+    let mut file: Option<PathBuf> = audio_override.map(Path::to_path_buf);
+    let mut sheet_tracks: Vec<SheetTrack> = Vec::new();
+    let mut in_audio_track = false;
+    let mut in_track = false;
+
+    for line in text.lines() {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let mut words = rest.split_whitespace();
+        match command.to_uppercase().as_str() {
+            "FILE" if audio_override.is_none() => {
+                file = file_name(rest).map(|name| resolve_audio(base_dir, &name));
+            }
+            "TRACK" => {
+                in_track = true;
+                let number = words.next();
+                in_audio_track = words
+                    .next()
+                    .is_some_and(|kind| kind.eq_ignore_ascii_case("AUDIO"));
+                let (Some(file), true) = (file.clone(), in_audio_track) else {
+                    continue;
+                };
+                sheet_tracks.push(SheetTrack {
+                    file,
+                    number: number.and_then(|n| n.parse().ok()).unwrap_or(0),
+                    title: None,
+                    performer: album_performer.clone(),
+                    start: None,
+                });
+            }
+            "TITLE" if !in_track => album = text_value(rest),
+            "PERFORMER" if !in_track => album_performer = text_value(rest),
+            "TITLE" if in_audio_track => {
+                if let Some(track) = sheet_tracks.last_mut() {
+                    track.title = text_value(rest);
+                }
+            }
+            "PERFORMER" if in_audio_track => {
+                if let Some(track) = sheet_tracks.last_mut() {
+                    track.performer = text_value(rest);
+                }
+            }
+            "INDEX" if in_audio_track => {
+                if words.next() == Some("01")
+                    && let Some(track) = sheet_tracks.last_mut()
+                {
+                    track.start = words.next().and_then(parse_time);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut tracks: Vec<CueTrack> = sheet_tracks
+        .into_iter()
+        .filter_map(|track| {
+            Some(CueTrack {
+                segment: TrackSegment::new(track.start?, None),
+                file: track.file,
+                number: track.number,
+                title: track.title,
+                performer: track.performer,
+                album: album.clone(),
+            })
+        })
+        .collect();
+    for index in 1..tracks.len() {
+        if tracks[index].file == tracks[index - 1].file {
+            tracks[index - 1].segment.end = Some(tracks[index].segment.start);
+        }
+    }
+    tracks
+}
+
+/// The value of a TITLE or PERFORMER line: the quoted text, or the rest of the line
+/// when it is not quoted.
+fn text_value(rest: &str) -> Option<String> {
+    let value = match rest.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next().unwrap_or(quoted),
+        None => rest,
+    };
+    Some(value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+/// The file name of a FILE line, leaving out the file type that follows it.
+fn file_name(rest: &str) -> Option<String> {
+    if rest.starts_with('"') {
+        return text_value(rest);
+    }
+    let name = rest
+        .rsplit_once(char::is_whitespace)
+        .map_or(rest, |(name, _)| name);
+    text_value(name)
+}
+
+/// Reads `mm:ss:ff` into seconds.
+fn parse_time(time: &str) -> Option<f64> {
+    let mut parts = time.split(':').map(|part| part.parse::<u32>().ok());
+    let (Some(Some(minutes)), Some(Some(seconds)), Some(Some(frames)), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    Some(minutes as f64 * 60.0 + seconds as f64 + frames as f64 / FRAMES_PER_SECOND)
+}
+
+/// Cue sheets often still name the WAV the album was ripped to; fall back to a
+/// file with the same stem when the named one is missing.
+fn resolve_audio(base_dir: &Path, name: &str) -> PathBuf {
+    let path = base_dir.join(name.replace('\\', "/"));
+    if path.is_file() {
+        return path;
+    }
+    let Some(stem) = path.file_stem().map(|stem| stem.to_os_string()) else {
+        return path;
+    };
+    fs::read_dir(path.parent().unwrap_or(base_dir))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|candidate| {
+            candidate.file_stem() == Some(stem.as_os_str())
+                && candidate
+                    .extension()
+                    .is_some_and(|ext| !ext.eq_ignore_ascii_case("cue"))
+                && candidate.is_file()
+        })
+        .unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    const SHEET: &str = r#"REM GENRE Rock
+PERFORMER "Album Artist"
+TITLE Great Album Title
+FILE "Disc One.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "Opening Song"
+    INDEX 00 00:00:00
+    INDEX 01 00:00:30
+  TRACK 02 AUDIO
+    TITLE Second Song Here
+    PERFORMER Guest Singer
+    INDEX 01 03:00:00
+FILE disc two.flac WAVE
+  TRACK 03 AUDIO
+    INDEX 01 00:00:00
+  TRACK 04 AUDIO
+    TITLE "No Index"
+  TRACK 05 MODE1/2352
+    INDEX 01 01:00:00
+"#;
+
+    #[test]
+    fn reads_quoted_and_unquoted_fields() {
+        let dir = TempDir::new();
+        let tracks = parse(SHEET, dir.path(), None);
+        assert_eq!(tracks.len(), 3);
+
+        let first = &tracks[0];
+        assert_eq!(first.file, dir.path().join("Disc One.wav"));
+        assert_eq!(first.number, 1);
+        assert_eq!(first.title.as_deref(), Some("Opening Song"));
+        assert_eq!(first.performer.as_deref(), Some("Album Artist"));
+        assert_eq!(first.album.as_deref(), Some("Great Album Title"));
+
+        let second = &tracks[1];
+        assert_eq!(second.title.as_deref(), Some("Second Song Here"));
+        assert_eq!(second.performer.as_deref(), Some("Guest Singer"));
+    }
+
+    #[test]
+    fn tracks_end_where_the_next_one_of_their_file_starts() {
+        let dir = TempDir::new();
+        let tracks = parse(SHEET, dir.path(), None);
+        assert_eq!(tracks[0].segment, TrackSegment::new(0.4, Some(180.0)));
+        assert_eq!(tracks[1].segment, TrackSegment::new(180.0, None));
+        assert_eq!(tracks[2].file, dir.path().join("disc two.flac"));
+        assert_eq!(tracks[2].segment, TrackSegment::new(0.0, None));
+    }
+
+    #[test]
+    fn drops_tracks_without_an_index() {
+        let dir = TempDir::new();
+        let sheet = "FILE \"a.wav\" WAVE\n  TRACK 01 AUDIO\n    TITLE Lost\n  TRACK 02 AUDIO\n    INDEX 01 00:10:00\n";
+        let tracks = parse(sheet, dir.path(), None);
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].number, 2);
+        assert_eq!(tracks[0].segment, TrackSegment::new(10.0, None));
+    }
+
+    #[test]
+    fn embedded_sheets_describe_their_own_file() {
+        let dir = TempDir::new();
+        let audio = dir.path().join("album.flac");
+        let tracks = parse(SHEET, dir.path(), Some(&audio));
+        assert_eq!(tracks.len(), 3);
+        assert!(tracks.iter().all(|track| track.file == audio));
+    }
+
+    #[test]
+    fn untitled_tracks_are_numbered() {
+        let dir = TempDir::new();
+        let track = parse(SHEET, dir.path(), None).swap_remove(2);
+        let music = track.into_music_file("id".to_string());
+        assert_eq!(music.name, "Track 03");
+        assert_eq!(music.segment, Some(TrackSegment::new(0.0, None)));
+    }
+
+    #[test]
+    fn parses_frame_times() {
+        assert_eq!(parse_time("01:02:75"), Some(63.0));
+        assert_eq!(parse_time("00:00:15"), Some(0.2));
+        assert_eq!(parse_time("01:02"), None);
+        assert_eq!(parse_time("01:02:03:04"), None);
+        assert_eq!(parse_time("aa:02:03"), None);
+    }
+}
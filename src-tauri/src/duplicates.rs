@@ -0,0 +1,379 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Read,
+    path::Path,
+};
+
+use log::warn;
+use rayon::prelude::*;
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{
+        CODEC_TYPE_ALAC, CODEC_TYPE_FLAC, CODEC_TYPE_MONKEYS_AUDIO, CODEC_TYPE_NULL,
+        CODEC_TYPE_TTA, CODEC_TYPE_WAVPACK, CodecType,
+    },
+    errors::Error,
+    formats::FormatReader,
+    io::MediaSourceStream,
+    probe::Hint,
+};
+
+use crate::{
+    file_reader, fingerprint,
+    music::{DuplicateGroup, DuplicateKind, DuplicateReport, DuplicateTrack, StoredFingerprint},
+    player,
+};
+
+/// Default allowed difference in seconds between tracks matched by title and artist.
+pub const DEFAULT_DURATION_TOLERANCE: f64 = 2.0;
+
+/// Tracks whose durations differ by more than this are never decoded for comparison.
+const AUDIO_DURATION_SLACK: f64 = 1.0;
+
+struct Candidate {
+    track: DuplicateTrack,
+    content_hash: Option<String>,
+    title: String,
+    artist: String,
+}
+
+/// Groups `paths` into duplicates, from strictest to loosest match.
+///
+/// Each level only compares one representative of every group found by the level
+/// before it, so a copy shows up once, in the strictest group that explains it.
+/// Fingerprints computed along the way are added to `fingerprints`.
+pub fn find_duplicates(
+    paths: Vec<String>,
+    tolerance: f64,
+    strip_patterns: &[String],
+    fingerprints: &mut HashMap<String, StoredFingerprint>,
+) -> DuplicateReport {
+    let candidates: Vec<Candidate> = paths
+        .into_par_iter()
+        .filter_map(|path| inspect(path, strip_patterns))
+        .collect();
+    let mut groups = Vec::new();
+
+    let mut by_content: HashMap<String, Vec<Candidate>> = HashMap::new();
+    let mut representatives = Vec::new();
+    for candidate in candidates {
+        match candidate.content_hash.clone() {
+            Some(hash) => by_content.entry(hash).or_default().push(candidate),
+            None => representatives.push(candidate),
+        }
+    }
+    for (_, mut members) in by_content {
+        if members.len() > 1 {
+            sort_by_quality(&mut members);
+            groups.push(new_group(DuplicateKind::File, &members));
+        }
+        representatives.push(members.swap_remove(0));
+    }
+
+    let decode_targets: Vec<bool> = representatives
+        .iter()
+        .map(|candidate| has_duration_partner(candidate, &representatives, AUDIO_DURATION_SLACK))
+        .collect();
+    let audio_hashes: Vec<Option<String>> = representatives
+        .par_iter()
+        .zip(decode_targets)
+        .map(|(candidate, decode)| decode.then(|| hash_audio(&candidate.track.path)).flatten())
+        .collect();
+
+    let mut by_audio: HashMap<String, Vec<Candidate>> = HashMap::new();
+    let mut remaining = Vec::new();
+    for (candidate, hash) in representatives.into_iter().zip(audio_hashes) {
+        match hash {
+            Some(hash) => by_audio.entry(hash).or_default().push(candidate),
+            None => remaining.push(candidate),
+        }
+    }
+    for (_, mut members) in by_audio {
+        sort_by_quality(&mut members);
+        if members.len() > 1 {
+            groups.push(new_group(DuplicateKind::Audio, &members));
+        }
+        remaining.push(members.swap_remove(0));
+    }
+
+    let remaining = group_by_fingerprint(remaining, tolerance, fingerprints, &mut groups);
+
+    let mut by_tags: HashMap<(String, String), Vec<Candidate>> = HashMap::new();
+    for candidate in remaining {
+        if candidate.title.is_empty() {
+            continue;
+        }
+        let key = (candidate.title.clone(), candidate.artist.clone());
+        by_tags.entry(key).or_default().push(candidate);
+    }
+    for (_, mut members) in by_tags {
+        members.sort_by(|a, b| a.track.duration.total_cmp(&b.track.duration));
+        for mut cluster in cluster_by_duration(members, tolerance) {
+            if cluster.len() > 1 {
+                sort_by_quality(&mut cluster);
+                groups.push(new_group(DuplicateKind::Metadata, &cluster));
+            }
+        }
+    }
+
+    groups.sort_by(|a, b| a.kind.cmp(&b.kind).then_with(|| a.keeper.cmp(&b.keeper)));
+    DuplicateReport { groups }
+}
+
+fn inspect(path: String, strip_patterns: &[String]) -> Option<Candidate> {
+    let size = fs::metadata(file_reader::local_path(&path)).ok()?.len();
+    let format = open_format(&path)?;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)?;
+    let params = &track.codec_params;
+
+    let duration = match (params.time_base, params.n_frames) {
+        (Some(tb), Some(n_frames)) => {
+            let time = tb.calc_time(n_frames);
+            time.seconds as f64 + time.frac
+        }
+        _ => 0.0,
+    };
+    let codec = symphonia::default::get_codecs()
+        .get_codec(params.codec)
+        .map(|descriptor| descriptor.short_name.to_string())
+        .unwrap_or_default();
+    let bitrate = (duration > 0.0).then(|| (size as f64 * 8.0 / duration / 1000.0) as u32);
+    let lossless = is_lossless(params.codec, &codec);
+    let sample_rate = params.sample_rate.unwrap_or(0);
+    let bits_per_sample = params.bits_per_sample.unwrap_or(0);
+
+    let meta = player::load_metadata(&path, strip_patterns);
+    let name = crate::extract_name_from_path(&path);
+    let (title, artist) = match meta {
+        Some(meta) if !meta.title.is_empty() => (meta.title, meta.artist),
+        _ => (name.clone(), String::new()),
+    };
+
+    Some(Candidate {
+        content_hash: hash_file(&path),
+        title: normalize(&title),
+        artist: normalize(&artist),
+        track: DuplicateTrack {
+            path,
+            name,
+            codec,
+            lossless,
+            bitrate,
+            sample_rate,
+            bits_per_sample,
+            duration,
+            size,
+        },
+    })
+}
+
+fn open_format(path: &str) -> Option<Box<dyn FormatReader>> {
+    let file = File::open(file_reader::local_path(path)).ok()?;
+    let mut hint = Hint::new();
+    if let Some(extension) = Path::new(path).extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    symphonia::default::get_probe()
+        .format(&hint, mss, &Default::default(), &Default::default())
+        .ok()
+        .map(|probed| probed.format)
+}
+
+fn hash_file(path: &str) -> Option<String> {
+    let mut file = File::open(file_reader::local_path(path)).ok()?;
+    let mut context = md5::Context::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => context.consume(&buf[..n]),
+            Err(err) => {
+                warn!("failed to hash {}: {}", path, err);
+                return None;
+            }
+        }
+    }
+    Some(format!("{:x}", context.compute()))
+}
+
+/// Hashes the decoded samples of the first audio track, so files that only differ in
+/// their tags or container hash the same.
+fn hash_audio(path: &str) -> Option<String> {
+    let mut format = open_format(path)?;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &Default::default())
+        .ok()?;
+
+    let mut context = md5::Context::new();
+    let mut sample_buf: Option<SampleBuffer<i32>> = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(err) => {
+                warn!("failed to read {} for audio hash: {}", path, err);
+                return None;
+            }
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(Error::DecodeError(_)) => continue,
+            Err(_) => return None,
+        };
+        let buf = sample_buf
+            .get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, *decoded.spec()));
+        buf.copy_interleaved_ref(decoded);
+        for sample in buf.samples() {
+            context.consume(sample.to_le_bytes());
+        }
+    }
+    Some(format!("{:x}", context.compute()))
+}
+
+/// Matches recordings that decode differently, such as the same song in another
+/// codec or bitrate, and returns one representative per match plus everything that
+/// could not be fingerprinted.
+fn group_by_fingerprint(
+    candidates: Vec<Candidate>,
+    tolerance: f64,
+    fingerprints: &mut HashMap<String, StoredFingerprint>,
+    groups: &mut Vec<DuplicateGroup>,
+) -> Vec<Candidate> {
+    let targets: Vec<bool> = candidates
+        .iter()
+        .map(|candidate| has_duration_partner(candidate, &candidates, tolerance))
+        .collect();
+    let stored: &HashMap<String, StoredFingerprint> = fingerprints;
+    let computed: Vec<Option<StoredFingerprint>> = candidates
+        .par_iter()
+        .zip(targets)
+        .map(|(candidate, target)| {
+            if !target {
+                return None;
+            }
+            let path = &candidate.track.path;
+            fingerprint::stored_or_compute(path, stored.get(path))
+                .map_err(|err| warn!("failed to fingerprint {}: {}", path, err))
+                .ok()
+        })
+        .collect();
+
+    let mut remaining = Vec::new();
+    let mut fingerprinted = Vec::new();
+    for (candidate, stored) in candidates.into_iter().zip(computed) {
+        let raw = stored
+            .as_ref()
+            .and_then(|stored| fingerprint::decode(&stored.fingerprint));
+        if let Some(stored) = stored {
+            fingerprints.insert(candidate.track.path.clone(), stored);
+        }
+        match raw {
+            Some(raw) => fingerprinted.push((candidate, raw)),
+            None => remaining.push(candidate),
+        }
+    }
+    fingerprinted.sort_by(|a, b| a.0.track.duration.total_cmp(&b.0.track.duration));
+
+    let mut clusters: Vec<(Vec<u32>, f64, Vec<Candidate>)> = Vec::new();
+    for (candidate, raw) in fingerprinted {
+        let duration = candidate.track.duration;
+        let cluster = clusters.iter_mut().find(|(first, first_duration, _)| {
+            duration - first_duration <= tolerance
+                && fingerprint::similarity(first, &raw) >= fingerprint::MATCH_THRESHOLD
+        });
+        match cluster {
+            Some((_, _, members)) => members.push(candidate),
+            None => clusters.push((raw, duration, vec![candidate])),
+        }
+    }
+    for (_, _, mut members) in clusters {
+        sort_by_quality(&mut members);
+        if members.len() > 1 {
+            groups.push(new_group(DuplicateKind::Acoustic, &members));
+        }
+        remaining.push(members.swap_remove(0));
+    }
+    remaining
+}
+
+fn has_duration_partner(candidate: &Candidate, others: &[Candidate], slack: f64) -> bool {
+    others.iter().any(|other| {
+        !std::ptr::eq(other, candidate)
+            && (other.track.duration - candidate.track.duration).abs() <= slack
+    })
+}
+
+/// Splits tracks sorted by duration wherever neighbours are further apart than `tolerance`.
+fn cluster_by_duration(members: Vec<Candidate>, tolerance: f64) -> Vec<Vec<Candidate>> {
+    let mut clusters: Vec<Vec<Candidate>> = Vec::new();
+    for candidate in members {
+        match clusters.last_mut() {
+            Some(cluster)
+                if cluster.last().is_some_and(|last| {
+                    candidate.track.duration - last.track.duration <= tolerance
+                }) =>
+            {
+                cluster.push(candidate)
+            }
+            _ => clusters.push(vec![candidate]),
+        }
+    }
+    clusters
+}
+
+/// Orders the best copy first: lossless before lossy, then by bitrate, bit depth and
+/// sample rate.
+fn sort_by_quality(members: &mut [Candidate]) {
+    members.sort_by(|a, b| {
+        let (a, b) = (&a.track, &b.track);
+        b.lossless
+            .cmp(&a.lossless)
+            .then_with(|| b.bitrate.cmp(&a.bitrate))
+            .then_with(|| b.bits_per_sample.cmp(&a.bits_per_sample))
+            .then_with(|| b.sample_rate.cmp(&a.sample_rate))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+}
+
+fn new_group(kind: DuplicateKind, members: &[Candidate]) -> DuplicateGroup {
+    DuplicateGroup {
+        kind,
+        keeper: members[0].track.path.clone(),
+        tracks: members.iter().map(|m| m.track.clone()).collect(),
+    }
+}
+
+fn is_lossless(codec: CodecType, short_name: &str) -> bool {
+    let is_linear_pcm =
+        short_name.starts_with("pcm_") && short_name != "pcm_alaw" && short_name != "pcm_mulaw";
+    is_linear_pcm
+        || [
+            CODEC_TYPE_FLAC,
+            CODEC_TYPE_ALAC,
+            CODEC_TYPE_WAVPACK,
+            CODEC_TYPE_MONKEYS_AUDIO,
+            CODEC_TYPE_TTA,
+        ]
+        .contains(&codec)
+}
+
+fn normalize(value: &str) -> String {
+    value
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
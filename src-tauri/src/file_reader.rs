@@ -1,69 +1,685 @@
-use std::fs;
-use std::io::Error;
-use std::path::Path;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-const SUPPORTED_AUDIO_EXTENSIONS: [&str; 12] = [
-    "mp3", "flac", "wav", "ogg", "m4a", "wma", "aac", "riff", "aiff", "mp2", "mp4", "mkv",
+use rayon::prelude::*;
+use symphonia::core::codecs::{
+    CODEC_TYPE_ATRAC1, CODEC_TYPE_ATRAC3, CODEC_TYPE_ATRAC3PLUS, CODEC_TYPE_ATRAC9, CODEC_TYPE_DCA,
+    CODEC_TYPE_EAC3, CODEC_TYPE_MONKEYS_AUDIO, CODEC_TYPE_MUSEPACK, CODEC_TYPE_NULL,
+    CODEC_TYPE_OPUS, CODEC_TYPE_SPEEX, CODEC_TYPE_TTA, CODEC_TYPE_WAVPACK, CODEC_TYPE_WMA,
+    CodecParameters, CodecType,
+};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::TimeBase;
+
+use crate::music::{ScanProgress, ScanReport, SkippedEntry};
+use crate::scan_rules::{IgnoreFile, ScanRules};
+
+/// Extensions that are never audio; files carrying them are skipped without being opened.
+const NON_AUDIO_EXTENSIONS: [&str; 33] = [
+    "jpg", "jpeg", "png", "gif", "webp", "bmp", "tif", "tiff", "txt", "nfo", "log", "cue", "lrc",
+    "m3u", "m3u8", "pls", "xspf", "pdf", "md", "json", "xml", "html", "htm", "ini", "db", "sfv",
+    "md5", "ffp", "accurip", "zip", "rar", "7z", "url",
 ];
 
-pub fn read_directory_files(dirs: Vec<String>) -> Result<Vec<String>, Error> {
-    let mut audio_files = Vec::new();
+/// Extensions that name audio files. Such files are reported as unsupported when they
+/// cannot be decoded, everything else is silently ignored unless its content sniffs as audio.
+const AUDIO_EXTENSIONS: [&str; 33] = [
+    "mp1", "mp2", "mp3", "flac", "wav", "wave", "ogg", "oga", "opus", "spx", "m4a", "m4b", "mp4",
+    "aac", "alac", "caf", "aif", "aiff", "aifc", "webm", "mka", "mkv", "wv", "wma", "ape", "mpc",
+    "tta", "dsf", "dff", "ac3", "dts", "amr", "riff",
+];
 
-    for dir in dirs {
-        let sub_files = read_dir_files(&dir)?;
-        let filtered_files = filter_audio_files(&sub_files);
-        audio_files.extend(filtered_files);
-    }
+/// How many packets are read when confirming a file without an audio extension.
+const SNIFF_PACKET_LIMIT: usize = 16;
+
+/// How many audio packets identify a file; enough to tell tracks apart without
+/// reading them whole.
+const IDENTITY_PACKET_COUNT: usize = 32;
 
-    Ok(audio_files)
+/// Start of the private use characters that stand in for the parts of a file name
+/// that are not valid Unicode: bytes on Unix, unpaired surrogates on Windows.
+const ESCAPE_BASE: u32 = 0x10F800;
+const ESCAPE_COUNT: u32 = 0x800;
+
+/// Outcome of inspecting a file's content with symphonia.
+pub enum AudioSniff {
+    /// Decodable, with the duration in seconds when the container reports one.
+    Playable(Option<f64>),
+    Unsupported(String),
+    NotAudio,
+}
+
+/// A directory to scan together with the library folder whose rules apply to it.
+pub struct ScanRoot {
+    pub path: PathBuf,
+    pub base: PathBuf,
+    pub rules: Arc<ScanRules>,
 }
 
-fn read_dir_files(dir: &str) -> Result<Vec<String>, Error> {
-    let paths = fs::read_dir(dir)?;
-    let mut files = Vec::new();
+impl ScanRoot {
+    pub fn new(path: PathBuf, rules: Arc<ScanRules>) -> Self {
+        Self {
+            base: path.clone(),
+            path,
+            rules,
+        }
+    }
+
+    /// A directory below the library folder `base`, matched against `base`'s rules.
+    pub fn within(base: PathBuf, path: PathBuf, rules: Arc<ScanRules>) -> Self {
+        Self { path, base, rules }
+    }
+
+    fn excludes(&self, path: &Path, is_dir: bool, ignores: &[Arc<IgnoreFile>]) -> bool {
+        let relative = path.strip_prefix(&self.base).unwrap_or(path);
+        self.rules.excludes(relative, is_dir)
+            || ignores.iter().any(|ignore| ignore.ignores(path, is_dir))
+    }
 
-    for entry in paths {
-        let entry = entry?;
-        let path = entry.path();
+    /// Applies the rules and ignore files to every path component between the library
+    /// folder and `path`. Returns the ignore files in effect for `path`'s directory, or
+    /// `None` when `path` or one of its parents is filtered out.
+    fn walk_to(&self, path: &Path, is_dir: bool) -> Option<Vec<Arc<IgnoreFile>>> {
+        let mut ignores: Vec<Arc<IgnoreFile>> = IgnoreFile::load(&self.base)
+            .map(Arc::new)
+            .into_iter()
+            .collect();
+        let mut components: Vec<&Path> = path
+            .ancestors()
+            .take_while(|ancestor| ancestor.starts_with(&self.base) && *ancestor != self.base)
+            .collect();
+        components.reverse();
 
-        if path.is_file() {
-            if let Some(path_str) = path.to_str() {
-                files.push(path_str.to_string());
+        let last = components.len().saturating_sub(1);
+        for (index, component) in components.into_iter().enumerate() {
+            let component_is_dir = index != last || is_dir;
+            if self.excludes(component, component_is_dir, &ignores) {
+                return None;
+            }
+            if index != last
+                && let Some(ignore) = IgnoreFile::load(component)
+            {
+                ignores.push(Arc::new(ignore));
             }
-        } else if path.is_dir() {
-            // Recursively read subdirectories
-            let sub_files = read_dir_files(path.to_str().unwrap_or_default())?;
-            files.extend(sub_files);
         }
+        Some(ignores)
     }
+}
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Recursively collects audio files below `roots` in parallel, applying each root's
+/// scan rules and any `.anchorignore` files met on the way.
+///
+/// Symlinks are followed, but every directory is visited at most once (keyed by its
+/// canonical path), so link loops terminate. Entries that cannot be read are recorded
+/// in the report instead of aborting the scan. Setting `cancel` stops the scan as soon
+/// as the workers notice it; whatever was found up to that point is still returned.
+pub fn scan_directories(
+    roots: Vec<ScanRoot>,
+    cancel: &AtomicBool,
+    on_progress: &(dyn Fn(ScanProgress) + Sync),
+) -> ScanReport {
+    let scanner = Scanner {
+        cancel,
+        on_progress,
+        visited: Mutex::new(HashSet::new()),
+        files: Mutex::new(Vec::new()),
+        skipped: Mutex::new(Vec::new()),
+        unsupported: Mutex::new(Vec::new()),
+        scanned_dirs: AtomicUsize::new(0),
+        last_progress: Mutex::new(Instant::now()),
+    };
 
-    Ok(files)
+    rayon::scope(|scope| {
+        for root in roots {
+            let Some(ignores) = root.walk_to(&root.path, true) else {
+                continue;
+            };
+            // The root's own ignore file is picked up by `scan_dir`.
+            let ignores: Vec<Arc<IgnoreFile>> = if root.path == root.base {
+                Vec::new()
+            } else {
+                ignores
+            };
+            let task = DirTask {
+                dir: root.path.clone(),
+                root: Arc::new(root),
+                ignores: Arc::new(ignores),
+            };
+            let scanner = &scanner;
+            scope.spawn(move |scope| scanner.scan_dir(scope, task));
+        }
+    });
+
+    scanner.report_progress(None, true);
+    let mut files = scanner.files.into_inner().unwrap_or_default();
+    files.sort();
+    let mut unsupported = scanner.unsupported.into_inner().unwrap_or_default();
+    unsupported.sort_by(|a, b| a.path.cmp(&b.path));
+    ScanReport {
+        files,
+        skipped: scanner.skipped.into_inner().unwrap_or_default(),
+        unsupported,
+        cancelled: cancel.load(Ordering::Relaxed),
+    }
+}
+
+/// Whether `path` should be sniffed at all: known non-audio extensions are rejected
+/// without opening the file.
+pub fn is_audio_candidate(path: &Path) -> bool {
+    !lowercase_extension(path).is_some_and(|ext| NON_AUDIO_EXTENSIONS.contains(&ext.as_str()))
 }
 
-fn filter_audio_files(paths: &[String]) -> Vec<String> {
-    paths
+/// Runs a single file through the same rules, ignore files and content checks that a
+/// scan of `root` would apply to it. Filtered files are reported as `NotAudio`.
+pub fn inspect_file(root: &ScanRoot, path: &Path) -> AudioSniff {
+    if !is_audio_candidate(path) {
+        return AudioSniff::NotAudio;
+    }
+    if root.walk_to(path, false).is_none() {
+        return AudioSniff::NotAudio;
+    }
+    let size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    if size < root.rules.min_size {
+        return AudioSniff::NotAudio;
+    }
+    apply_min_duration(sniff_audio_file(path), root.rules.min_duration)
+}
+
+fn apply_min_duration(sniff: AudioSniff, min_duration: f64) -> AudioSniff {
+    match sniff {
+        AudioSniff::Playable(Some(duration)) if duration < min_duration => AudioSniff::NotAudio,
+        sniff => sniff,
+    }
+}
+
+/// Decides from the file's content whether symphonia can play it.
+///
+/// The container is probed and every audio track is checked against the registered
+/// codecs. Files that do not carry an audio extension must also decode their first
+/// packet, so stray data that happens to contain a sync word is not mistaken for audio.
+pub fn sniff_audio_file(path: &Path) -> AudioSniff {
+    sniff_audio_codec(path).0
+}
+
+/// Like `sniff_audio_file`, and also names the codec of playable files, so callers
+/// that need both probe the file once.
+pub fn sniff_audio_codec(path: &Path) -> (AudioSniff, Option<String>) {
+    let extension = lowercase_extension(path);
+    let known_audio = extension
+        .as_deref()
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext));
+    let unsupported = |reason: String| {
+        let sniff = if known_audio {
+            AudioSniff::Unsupported(reason)
+        } else {
+            AudioSniff::NotAudio
+        };
+        (sniff, None)
+    };
+
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) => return unsupported(format!("failed to open file: {}", err)),
+    };
+    let mut hint = Hint::new();
+    if let Some(extension) = extension.as_deref() {
+        hint.with_extension(extension);
+    }
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut probed = match symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &Default::default(),
+        &Default::default(),
+    ) {
+        Ok(probed) => probed,
+        Err(_) => return unsupported("container format is not supported".to_string()),
+    };
+
+    let codecs = symphonia::default::get_codecs();
+    let audio_tracks: Vec<_> = probed
+        .format
+        .tracks()
         .iter()
-        .filter(|path| {
-            let path_obj = Path::new(path);
-            !path_obj
-                .file_name()
-                .and_then(|name| name.to_str())
-                .unwrap()
-                .starts_with(".")
-        }) // filter out hidden files
-        .filter_map(|path| {
-            let path_obj = Path::new(path);
-            let extension = path_obj
-                .extension()
-                .and_then(|ext| ext.to_str())
-                .map(|ext| ext.to_lowercase());
-
-            match extension {
-                Some(ext) if SUPPORTED_AUDIO_EXTENSIONS.contains(&ext.as_str()) => {
-                    Some(path.clone())
+        .filter(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .collect();
+    let Some(first_track) = audio_tracks.first() else {
+        return unsupported("file has no audio tracks".to_string());
+    };
+    let decodable = audio_tracks.iter().find_map(|track| {
+        codecs
+            .make(&track.codec_params, &Default::default())
+            .ok()
+            .map(|decoder| (track, decoder))
+    });
+    let Some((track, mut decoder)) = decodable else {
+        let codec = first_track.codec_params.codec;
+        return unsupported(format!("{} codec is not supported", codec_name(codec)));
+    };
+    let track_id = track.id;
+    let duration = track_duration(&track.codec_params);
+    let codec = codec_name(track.codec_params.codec);
+
+    if known_audio {
+        return (AudioSniff::Playable(duration), Some(codec));
+    }
+
+    for _ in 0..SNIFF_PACKET_LIMIT {
+        match probed.format.next_packet() {
+            Ok(packet) if packet.track_id() == track_id => {
+                return match decoder.decode(&packet) {
+                    Ok(_) => (AudioSniff::Playable(duration), Some(codec)),
+                    Err(_) => (AudioSniff::NotAudio, None),
+                };
+            }
+            Ok(_) => continue,
+            Err(_) => break,
+        }
+    }
+    (AudioSniff::NotAudio, None)
+}
+
+/// Hashes the first packets of the file's default track. Unlike the path or the whole
+/// file, this survives renames, moves and tag edits.
+pub fn track_identity(path: &Path) -> Option<String> {
+    let mut hint = Hint::new();
+    if let Some(extension) = lowercase_extension(path) {
+        hint.with_extension(&extension);
+    }
+    let file = File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut probed = symphonia::default::get_probe()
+        .format(&hint, mss, &Default::default(), &Default::default())
+        .ok()?;
+    let track = probed.format.default_track()?;
+    let track_id = track.id;
+
+    let mut context = md5::Context::new();
+    context.consume(
+        track
+            .codec_params
+            .n_frames
+            .unwrap_or_default()
+            .to_le_bytes(),
+    );
+    let mut hashed = 0;
+    while hashed < IDENTITY_PACKET_COUNT {
+        match probed.format.next_packet() {
+            Ok(packet) if packet.track_id() == track_id => {
+                context.consume(&packet.data);
+                hashed += 1;
+            }
+            Ok(_) => continue,
+            Err(_) => break,
+        }
+    }
+    (hashed > 0).then(|| format!("{:x}", context.compute()))
+}
+
+/// The path as a string, the form paths take in the library, playlists and the
+/// frontend. Names that are not valid Unicode are kept losslessly: the offending
+/// bytes or UTF-16 units become private use characters, and `local_path` turns
+/// them back.
+pub fn path_string(path: &Path) -> String {
+    if let Some(path) = path.to_str() {
+        return path.to_string();
+    }
+    let mut escaped = String::new();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+            for c in chunk.valid().chars() {
+                if is_escape(c) {
+                    escaped.extend(c.to_string().bytes().map(|byte| escape(byte as u32)));
+                } else {
+                    escaped.push(c);
+                }
+            }
+            escaped.extend(chunk.invalid().iter().map(|byte| escape(*byte as u32)));
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        for decoded in char::decode_utf16(path.as_os_str().encode_wide()) {
+            match decoded {
+                Ok(c) if is_escape(c) => escaped.extend(
+                    c.encode_utf16(&mut [0; 2])
+                        .iter()
+                        .map(|unit| escape(*unit as u32 - 0xD800)),
+                ),
+                Ok(c) => escaped.push(c),
+                Err(err) => escaped.push(escape(err.unpaired_surrogate() as u32 - 0xD800)),
+            }
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    escaped.push_str(&path.to_string_lossy());
+    escaped
+}
+
+/// The file a path string from `path_string` names.
+///
+/// A string that holds escape characters but turns into valid Unicode once they
+/// are undone was never escaped, and names the file spelled that way.
+pub fn local_path(path: &str) -> PathBuf {
+    if !path.chars().any(is_escape) {
+        return PathBuf::from(path);
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        let mut bytes = Vec::with_capacity(path.len());
+        for c in path.chars() {
+            match unescape(c) {
+                Some(value) if value <= u8::MAX as u32 => bytes.push(value as u8),
+                _ => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+        if std::str::from_utf8(&bytes).is_err() {
+            return PathBuf::from(std::ffi::OsString::from_vec(bytes));
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStringExt;
+        let mut units = Vec::with_capacity(path.len());
+        for c in path.chars() {
+            match unescape(c) {
+                Some(value) => units.push((0xD800 + value) as u16),
+                None => units.extend_from_slice(c.encode_utf16(&mut [0; 2])),
+            }
+        }
+        if String::from_utf16(&units).is_err() {
+            return PathBuf::from(std::ffi::OsString::from_wide(&units));
+        }
+    }
+    PathBuf::from(path)
+}
+
+fn escape(value: u32) -> char {
+    char::from_u32(ESCAPE_BASE + value).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+fn is_escape(c: char) -> bool {
+    unescape(c).is_some()
+}
+
+fn unescape(c: char) -> Option<u32> {
+    (c as u32)
+        .checked_sub(ESCAPE_BASE)
+        .filter(|value| *value < ESCAPE_COUNT)
+}
+
+fn track_duration(params: &CodecParameters) -> Option<f64> {
+    let n_frames = params.n_frames?;
+    let time = match params.time_base {
+        Some(tb) => tb.calc_time(n_frames),
+        None => TimeBase::new(1, params.sample_rate?).calc_time(n_frames),
+    };
+    Some(time.seconds as f64 + time.frac)
+}
+
+fn lowercase_extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+}
+
+fn codec_name(codec: CodecType) -> String {
+    if let Some(descriptor) = symphonia::default::get_codecs().get_codec(codec) {
+        return descriptor.short_name.to_string();
+    }
+    let name = match codec {
+        CODEC_TYPE_OPUS => "opus",
+        CODEC_TYPE_SPEEX => "speex",
+        CODEC_TYPE_MUSEPACK => "musepack",
+        CODEC_TYPE_WMA => "wma",
+        CODEC_TYPE_WAVPACK => "wavpack",
+        CODEC_TYPE_MONKEYS_AUDIO => "monkey's audio",
+        CODEC_TYPE_TTA => "tta",
+        CODEC_TYPE_EAC3 => "e-ac-3",
+        CODEC_TYPE_DCA => "dts",
+        CODEC_TYPE_ATRAC1 | CODEC_TYPE_ATRAC3 | CODEC_TYPE_ATRAC3PLUS | CODEC_TYPE_ATRAC9 => {
+            "atrac"
+        }
+        _ => return format!("codec {}", codec),
+    };
+    name.to_string()
+}
+
+struct DirTask {
+    dir: PathBuf,
+    root: Arc<ScanRoot>,
+    ignores: Arc<Vec<Arc<IgnoreFile>>>,
+}
+
+struct Scanner<'a> {
+    cancel: &'a AtomicBool,
+    on_progress: &'a (dyn Fn(ScanProgress) + Sync),
+    visited: Mutex<HashSet<PathBuf>>,
+    files: Mutex<Vec<String>>,
+    skipped: Mutex<Vec<SkippedEntry>>,
+    unsupported: Mutex<Vec<SkippedEntry>>,
+    scanned_dirs: AtomicUsize,
+    last_progress: Mutex<Instant>,
+}
+
+impl<'a> Scanner<'a> {
+    fn scan_dir<'s>(&'s self, scope: &rayon::Scope<'s>, task: DirTask) {
+        let DirTask { dir, root, ignores } = task;
+        if self.cancel.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let canonical = match fs::canonicalize(&dir) {
+            Ok(canonical) => canonical,
+            Err(err) => return self.skip(&dir, err.to_string()),
+        };
+        let first_visit = self
+            .visited
+            .lock()
+            .map(|mut visited| visited.insert(canonical))
+            .unwrap_or(false);
+        if !first_visit {
+            return;
+        }
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) => return self.skip(&dir, err.to_string()),
+        };
+
+        let ignores = match IgnoreFile::load(&dir) {
+            Some(ignore) => {
+                let mut extended = ignores.as_ref().clone();
+                extended.push(Arc::new(ignore));
+                Arc::new(extended)
+            }
+            None => ignores,
+        };
+
+        let mut candidates = Vec::new();
+        for entry in entries {
+            if self.cancel.load(Ordering::Relaxed) {
+                break;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    self.skip(&dir, err.to_string());
+                    continue;
                 }
-                _ => None,
+            };
+            let path = entry.path();
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(err) => {
+                    self.skip(&path, err.to_string());
+                    continue;
+                }
+            };
+
+            let (is_dir, is_file) = if file_type.is_symlink() {
+                match fs::metadata(&path) {
+                    Ok(metadata) => (metadata.is_dir(), metadata.is_file()),
+                    Err(err) => {
+                        self.skip(&path, format!("broken symlink: {}", err));
+                        continue;
+                    }
+                }
+            } else {
+                (file_type.is_dir(), file_type.is_file())
+            };
+
+            if (!is_dir && !is_file) || root.excludes(&path, is_dir, &ignores) {
+                continue;
             }
-        })
-        .collect()
+            if is_dir {
+                let task = DirTask {
+                    dir: path,
+                    root: root.clone(),
+                    ignores: ignores.clone(),
+                };
+                scope.spawn(move |scope| self.scan_dir(scope, task));
+            } else if is_audio_candidate(&path) {
+                let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                if size >= root.rules.min_size {
+                    candidates.push(path);
+                }
+            }
+        }
+
+        let sniffed: Vec<(PathBuf, AudioSniff)> = candidates
+            .into_par_iter()
+            .filter(|_| !self.cancel.load(Ordering::Relaxed))
+            .map(|path| {
+                let sniff = apply_min_duration(sniff_audio_file(&path), root.rules.min_duration);
+                (path, sniff)
+            })
+            .collect();
+
+        let mut audio_files = Vec::new();
+        for (path, sniff) in sniffed {
+            let reason = match sniff {
+                AudioSniff::Playable(_) => None,
+                AudioSniff::Unsupported(reason) => Some(reason),
+                AudioSniff::NotAudio => continue,
+            };
+            let path = path_string(&path);
+            if let Some(reason) = reason
+                && let Ok(mut unsupported) = self.unsupported.lock()
+            {
+                unsupported.push(SkippedEntry::new(path.clone(), reason));
+            }
+            audio_files.push(path);
+        }
+
+        if let Ok(mut files) = self.files.lock() {
+            files.extend(audio_files);
+        }
+        self.scanned_dirs.fetch_add(1, Ordering::Relaxed);
+        self.report_progress(Some(&dir), false);
+    }
+
+    fn skip(&self, path: &Path, reason: String) {
+        if let Ok(mut skipped) = self.skipped.lock() {
+            skipped.push(SkippedEntry::new(path_string(path), reason));
+        }
+    }
+
+    fn report_progress(&self, current: Option<&Path>, force: bool) {
+        {
+            let Ok(mut last_progress) = self.last_progress.lock() else {
+                return;
+            };
+            if !force && last_progress.elapsed() < PROGRESS_INTERVAL {
+                return;
+            }
+            *last_progress = Instant::now();
+        }
+
+        let found_files = self.files.lock().map(|files| files.len()).unwrap_or(0);
+        let skipped = self.skipped.lock().map(|s| s.len()).unwrap_or(0);
+        (self.on_progress)(ScanProgress {
+            scanned_dirs: self.scanned_dirs.load(Ordering::Relaxed),
+            found_files,
+            skipped,
+            current: current.map(|path| path.to_string_lossy().to_string()),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TempDir, write_melody};
+
+    #[test]
+    fn unicode_paths_are_kept_as_they_are() {
+        let path = Path::new("/music/Sigur Rós/Ágætis byrjun.flac");
+        assert_eq!(path_string(path), "/music/Sigur Rós/Ágætis byrjun.flac");
+        assert_eq!(local_path(&path_string(path)), path);
+    }
+
+    #[test]
+    fn strings_that_only_look_escaped_name_themselves() {
+        // Undone, these are "=" and a character on Unix, and a surrogate pair on
+        // Windows.
+        let spelled = format!("/music/{}{}.mp3", escape(0x3D), escape(0x600));
+        assert_eq!(local_path(&spelled), PathBuf::from(&spelled));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_bytes_round_trip() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(std::ffi::OsStr::from_bytes(b"/music/caf\xE9/\xFF\xFE.mp3"));
+        let string = path_string(path);
+        assert!(string.starts_with("/music/caf"));
+        assert!(string.ends_with(".mp3"));
+        assert_eq!(local_path(&string), path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escape_characters_next_to_invalid_bytes_round_trip() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut bytes = b"/music/\xFF".to_vec();
+        bytes.extend_from_slice(escape(0x41).to_string().as_bytes());
+        let path = Path::new(std::ffi::OsStr::from_bytes(&bytes));
+        assert_eq!(local_path(&path_string(path)), path);
+    }
+
+    #[test]
+    fn wav_files_sniff_as_playable() {
+        let dir = TempDir::new();
+        let path = dir.path().join("tone.wav");
+        write_melody(&path, &[440.0], 2.0, 8000);
+        let (sniff, codec) = sniff_audio_codec(&path);
+        assert!(
+            matches!(sniff, AudioSniff::Playable(Some(duration)) if (duration - 2.0).abs() < 0.01)
+        );
+        assert!(codec.is_some());
+    }
+
+    #[test]
+    fn other_files_do_not_sniff_as_audio() {
+        let dir = TempDir::new();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, b"not audio at all").unwrap();
+        assert!(matches!(sniff_audio_file(&path), AudioSniff::NotAudio));
+    }
 }
@@ -0,0 +1,642 @@
+//! Chromaprint-compatible acoustic fingerprints.
+//!
+//! This follows Chromaprint's default `TEST2` algorithm: audio is mixed down to mono,
+//! resampled to 11025 Hz and cut into overlapping 4096-sample frames. Each frame's
+//! spectrum is folded into 12 chroma bands, smoothed over time, normalised and fed
+//! through 16 Haar-like classifiers that each contribute two bits of a 32-bit
+//! sub-fingerprint. The compressed form is the same base64 string `fpcalc` prints, so
+//! it can be sent to AcoustID as is.
+
+use std::{
+    f64::consts::PI,
+    fs::{self, File},
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use rustfft::{FftPlanner, num_complex::Complex};
+use symphonia::core::{
+    audio::SampleBuffer, codecs::CODEC_TYPE_NULL, errors::Error, io::MediaSourceStream, probe::Hint,
+};
+
+use crate::{file_reader, music::StoredFingerprint};
+
+const SAMPLE_RATE: u32 = 11025;
+const FRAME_SIZE: usize = 4096;
+const FRAME_STEP: usize = FRAME_SIZE / 3;
+const MIN_FREQ: f64 = 28.0;
+const MAX_FREQ: f64 = 3520.0;
+const NUM_BANDS: usize = 12;
+const MAX_FILTER_WIDTH: usize = 16;
+const CHROMA_FILTER: [f64; 5] = [0.25, 0.75, 1.0, 0.75, 0.25];
+const NORMALIZE_THRESHOLD: f64 = 0.01;
+const ALGORITHM_TEST2: u8 = 1;
+
+/// Only the start of a track is fingerprinted, matching `fpcalc`'s default length.
+const MAX_SECONDS: usize = 120;
+
+/// Minimum [`similarity`] for two fingerprints to be considered the same recording.
+pub const MATCH_THRESHOLD: f64 = 0.8;
+
+/// Number of sub-fingerprints a match may be shifted by, roughly ten seconds.
+const MAX_ALIGN_OFFSET: isize = 80;
+
+const RESAMPLE_TAPS: usize = 16;
+const RESAMPLE_PHASES: usize = 256;
+const RESAMPLE_CUTOFF: f64 = 0.8;
+const RESAMPLE_KAISER_BETA: f64 = 9.0;
+
+/// `(filter type, chroma offset, chroma height, time width)` and quantizer thresholds.
+const CLASSIFIERS: [(u8, usize, usize, usize, [f64; 3]); 16] = [
+    (0, 4, 3, 15, [1.98215, 2.35817, 2.63523]),
+    (4, 4, 6, 15, [-1.03809, -0.651211, -0.282167]),
+    (1, 0, 4, 16, [-0.298702, 0.119262, 0.558497]),
+    (3, 8, 2, 12, [-0.105439, 0.0153946, 0.135898]),
+    (3, 4, 4, 8, [-0.142891, 0.0258736, 0.200632]),
+    (4, 0, 3, 5, [-0.826319, -0.590612, -0.368214]),
+    (1, 2, 2, 9, [-0.557409, -0.233035, 0.0534525]),
+    (2, 7, 3, 4, [-0.0646826, 0.00620476, 0.0784847]),
+    (2, 6, 2, 16, [-0.192387, -0.029699, 0.215855]),
+    (2, 1, 3, 2, [-0.0397818, -0.00568076, 0.0292026]),
+    (5, 10, 1, 15, [-0.53823, -0.369934, -0.190235]),
+    (3, 6, 2, 10, [-0.124877, 0.0296483, 0.139239]),
+    (2, 1, 1, 14, [-0.101475, 0.0225617, 0.231971]),
+    (3, 5, 6, 4, [-0.0799915, -0.00729616, 0.063262]),
+    (1, 9, 2, 12, [-0.272556, 0.019424, 0.302559]),
+    (3, 4, 2, 14, [-0.164292, -0.0321188, 0.08463]),
+];
+
+pub struct Fingerprint {
+    pub raw: Vec<u32>,
+    /// Duration of the whole track in seconds, as AcoustID expects it.
+    pub duration: f64,
+}
+
+/// Decodes `path` with symphonia and computes its fingerprint.
+pub fn fingerprint_file(path: &str) -> Result<Fingerprint, String> {
+    let (samples, sample_rate, duration) = decode_mono(path)?;
+    let samples = resample(&samples, sample_rate, SAMPLE_RATE);
+    let raw = compute(&samples);
+    if raw.is_empty() {
+        return Err("track is too short to fingerprint".to_string());
+    }
+    Ok(Fingerprint { raw, duration })
+}
+
+/// Returns `stored` if it was computed from the file as it is now, otherwise
+/// fingerprints the file again.
+pub fn stored_or_compute(
+    path: &str,
+    stored: Option<&StoredFingerprint>,
+) -> Result<StoredFingerprint, String> {
+    let metadata = fs::metadata(file_reader::local_path(path))
+        .map_err(|e| format!("failed to read metadata: {}", e))?;
+    let size = metadata.len();
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|time| time.as_secs())
+        .unwrap_or(0);
+    if let Some(stored) = stored
+        && stored.size == size
+        && stored.modified == modified
+    {
+        return Ok(stored.clone());
+    }
+    let fingerprint = fingerprint_file(path)?;
+    Ok(StoredFingerprint::new(
+        encode(&fingerprint.raw),
+        fingerprint.duration,
+        size,
+        modified,
+    ))
+}
+
+/// Similarity of two fingerprints between 0 and 1, taken at the best alignment.
+/// Unrelated recordings score around 0.5; the same recording in different encodings
+/// typically scores above 0.85.
+pub fn similarity(a: &[u32], b: &[u32]) -> f64 {
+    let min_overlap = a.len().min(b.len()) / 2;
+    let mut best = 0.0;
+    for offset in -MAX_ALIGN_OFFSET..=MAX_ALIGN_OFFSET {
+        let (a_start, b_start) = if offset < 0 {
+            (offset.unsigned_abs(), 0)
+        } else {
+            (0, offset as usize)
+        };
+        if a_start >= a.len() || b_start >= b.len() {
+            continue;
+        }
+        let overlap = (a.len() - a_start).min(b.len() - b_start);
+        if overlap == 0 || overlap < min_overlap {
+            continue;
+        }
+        let errors: u32 = a[a_start..a_start + overlap]
+            .iter()
+            .zip(&b[b_start..b_start + overlap])
+            .map(|(x, y)| (x ^ y).count_ones())
+            .sum();
+        let score = 1.0 - f64::from(errors) / (overlap as f64 * 32.0);
+        if score > best {
+            best = score;
+        }
+    }
+    best
+}
+
+/// Compresses a raw fingerprint into Chromaprint's URL-safe base64 format.
+pub fn encode(raw: &[u32]) -> String {
+    let mut normal_bits = Vec::new();
+    let mut previous = 0u32;
+    for (index, &value) in raw.iter().enumerate() {
+        let mut x = if index == 0 { value } else { value ^ previous };
+        previous = value;
+        let (mut bit, mut last_bit) = (1u8, 0u8);
+        while x != 0 {
+            if x & 1 != 0 {
+                normal_bits.push(bit - last_bit);
+                last_bit = bit;
+            }
+            x >>= 1;
+            bit += 1;
+        }
+        normal_bits.push(0);
+    }
+
+    let size = raw.len();
+    let mut out = vec![
+        ALGORITHM_TEST2,
+        (size >> 16) as u8,
+        (size >> 8) as u8,
+        size as u8,
+    ];
+    pack_bits(&mut out, normal_bits.iter().map(|&bit| bit.min(7)), 3);
+    pack_bits(
+        &mut out,
+        normal_bits
+            .iter()
+            .filter(|&&bit| bit >= 7)
+            .map(|&bit| bit - 7),
+        5,
+    );
+    URL_SAFE_NO_PAD.encode(out)
+}
+
+/// Reverses [`encode`].
+pub fn decode(encoded: &str) -> Option<Vec<u32>> {
+    let bytes = URL_SAFE_NO_PAD.decode(encoded.trim_end_matches('=')).ok()?;
+    if bytes.len() < 4 {
+        return None;
+    }
+    let size = (usize::from(bytes[1]) << 16) | (usize::from(bytes[2]) << 8) | usize::from(bytes[3]);
+    let body = &bytes[4..];
+
+    let mut normal_bits = Vec::new();
+    let mut zeros = 0;
+    let mut reader = BitReader::new(body);
+    while zeros < size {
+        let value = reader.read(3)?;
+        if value == 0 {
+            zeros += 1;
+        }
+        normal_bits.push(value);
+    }
+
+    let exception_start = (normal_bits.len() * 3).div_ceil(8);
+    let mut exceptions = BitReader::new(body.get(exception_start..)?);
+    for bit in normal_bits.iter_mut().filter(|bit| **bit == 7) {
+        *bit += exceptions.read(5)?;
+    }
+
+    let mut raw = Vec::with_capacity(size);
+    let mut value = 0u32;
+    let mut last_bit = 0u32;
+    for bit in normal_bits {
+        if bit == 0 {
+            let previous = raw.last().copied().unwrap_or(0);
+            raw.push(value ^ previous);
+            value = 0;
+            last_bit = 0;
+        } else {
+            last_bit += u32::from(bit);
+            value |= 1u32.checked_shl(last_bit - 1)?;
+        }
+    }
+    Some(raw)
+}
+
+fn decode_mono(path: &str) -> Result<(Vec<f32>, u32, f64), String> {
+    let file = File::open(file_reader::local_path(path))
+        .map_err(|e| format!("failed to open file: {}", e))?;
+    let mut hint = Hint::new();
+    if let Some(extension) = Path::new(path).extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut format = symphonia::default::get_probe()
+        .format(&hint, mss, &Default::default(), &Default::default())
+        .map_err(|e| e.to_string())?
+        .format;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| "file has no audio tracks".to_string())?;
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&params, &Default::default())
+        .map_err(|e| e.to_string())?;
+
+    let sample_rate = params
+        .sample_rate
+        .ok_or_else(|| "unknown sample rate".to_string())?;
+    let duration = match (params.time_base, params.n_frames) {
+        (Some(tb), Some(n_frames)) => {
+            let time = tb.calc_time(n_frames);
+            time.seconds as f64 + time.frac
+        }
+        (None, Some(n_frames)) => n_frames as f64 / f64::from(sample_rate),
+        _ => 0.0,
+    };
+
+    let max_samples = MAX_SECONDS * sample_rate as usize;
+    let mut mono = Vec::new();
+    let mut sample_buf: Option<SampleBuffer<f32>> = None;
+    while mono.len() < max_samples {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.to_string()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(Error::DecodeError(_)) => continue,
+            Err(err) => return Err(err.to_string()),
+        };
+        let channels = decoded.spec().channels.count().max(1);
+        let buf = sample_buf
+            .get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, *decoded.spec()));
+        buf.copy_interleaved_ref(decoded);
+        mono.extend(
+            buf.samples()
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        );
+    }
+    mono.truncate(max_samples);
+    Ok((mono, sample_rate, duration))
+}
+
+/// Windowed-sinc resampler modelled on the libavcodec resampler Chromaprint uses:
+/// 16 taps, 256 phases, a Kaiser window and the cutoff at 80% of the target Nyquist.
+fn resample(input: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || input.is_empty() {
+        return input.to_vec();
+    }
+    let factor = (f64::from(to) / f64::from(from)).min(1.0);
+    let cutoff = RESAMPLE_CUTOFF * factor;
+    let half = RESAMPLE_TAPS as isize / 2;
+
+    let filters: Vec<[f64; RESAMPLE_TAPS]> = (0..=RESAMPLE_PHASES)
+        .map(|phase| {
+            let mut taps = [0.0; RESAMPLE_TAPS];
+            let frac = phase as f64 / RESAMPLE_PHASES as f64;
+            for (i, tap) in taps.iter_mut().enumerate() {
+                let x = (i as isize - half + 1) as f64 - frac;
+                let window = kaiser(x / half as f64, RESAMPLE_KAISER_BETA);
+                *tap = cutoff * sinc(cutoff * x) * window;
+            }
+            let sum: f64 = taps.iter().sum();
+            if sum != 0.0 {
+                taps.iter_mut().for_each(|tap| *tap /= sum);
+            }
+            taps
+        })
+        .collect();
+
+    let step = f64::from(from) / f64::from(to);
+    let out_len = (input.len() as f64 / step) as usize;
+    (0..out_len)
+        .map(|n| {
+            let position = n as f64 * step;
+            let index = position.floor() as isize;
+            let phase = ((position - index as f64) * RESAMPLE_PHASES as f64).round() as usize;
+            filters[phase]
+                .iter()
+                .enumerate()
+                .map(|(i, tap)| {
+                    let k = index + i as isize - half + 1;
+                    let sample = if k < 0 {
+                        0.0
+                    } else {
+                        f64::from(input.get(k as usize).copied().unwrap_or(0.0))
+                    };
+                    sample * tap
+                })
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn kaiser(x: f64, beta: f64) -> f64 {
+    if x.abs() > 1.0 {
+        return 0.0;
+    }
+    bessel_i0(beta * (1.0 - x * x).sqrt()) / bessel_i0(beta)
+}
+
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..32 {
+        term *= (half / f64::from(k)) * (half / f64::from(k));
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+fn compute(samples: &[f32]) -> Vec<u32> {
+    let window: Vec<f64> = (0..FRAME_SIZE)
+        .map(|i| 0.54 - 0.46 * (2.0 * PI * i as f64 / (FRAME_SIZE - 1) as f64).cos())
+        .collect();
+    let fft = FftPlanner::<f64>::new().plan_fft_forward(FRAME_SIZE);
+
+    let min_index =
+        ((FRAME_SIZE as f64 * MIN_FREQ / f64::from(SAMPLE_RATE)).round() as usize).max(1);
+    let max_index = ((FRAME_SIZE as f64 * MAX_FREQ / f64::from(SAMPLE_RATE)).round() as usize)
+        .min(FRAME_SIZE / 2);
+    let notes: Vec<usize> = (0..max_index)
+        .map(|i| {
+            let freq = i as f64 * f64::from(SAMPLE_RATE) / FRAME_SIZE as f64;
+            let octave = (freq / (440.0 / 16.0)).log2();
+            (NUM_BANDS as f64 * (octave - octave.floor())) as usize % NUM_BANDS
+        })
+        .collect();
+
+    let mut chroma_frames = Vec::new();
+    let mut buffer = vec![Complex::new(0.0, 0.0); FRAME_SIZE];
+    let mut start = 0;
+    while start + FRAME_SIZE <= samples.len() {
+        for (i, value) in buffer.iter_mut().enumerate() {
+            *value = Complex::new(f64::from(samples[start + i]) * window[i], 0.0);
+        }
+        fft.process(&mut buffer);
+        let mut features = [0.0; NUM_BANDS];
+        for i in min_index..max_index {
+            features[notes[i]] += buffer[i].norm_sqr();
+        }
+        chroma_frames.push(features);
+        start += FRAME_STEP;
+    }
+
+    let filtered: Vec<[f64; NUM_BANDS]> = chroma_frames
+        .windows(CHROMA_FILTER.len())
+        .map(|window| {
+            let mut result = [0.0; NUM_BANDS];
+            for (frame, coefficient) in window.iter().zip(CHROMA_FILTER) {
+                for (band, value) in result.iter_mut().enumerate() {
+                    *value += frame[band] * coefficient;
+                }
+            }
+            let norm = result.iter().map(|v| v * v).sum::<f64>().sqrt();
+            if norm < NORMALIZE_THRESHOLD {
+                [0.0; NUM_BANDS]
+            } else {
+                result.map(|v| v / norm)
+            }
+        })
+        .collect();
+
+    if filtered.len() < MAX_FILTER_WIDTH {
+        return Vec::new();
+    }
+    let image = IntegralImage::new(&filtered);
+    (0..=filtered.len() - MAX_FILTER_WIDTH)
+        .map(|offset| {
+            CLASSIFIERS
+                .iter()
+                .fold(0u32, |bits, &(kind, y, height, width, thresholds)| {
+                    let value = apply_filter(&image, kind, offset, y, width, height);
+                    (bits << 2) | gray_code(quantize(value, thresholds))
+                })
+        })
+        .collect()
+}
+
+struct IntegralImage {
+    rows: Vec<[f64; NUM_BANDS]>,
+}
+
+impl IntegralImage {
+    fn new(features: &[[f64; NUM_BANDS]]) -> Self {
+        let mut rows: Vec<[f64; NUM_BANDS]> = Vec::with_capacity(features.len());
+        for (x, row) in features.iter().enumerate() {
+            let mut integral = [0.0; NUM_BANDS];
+            let mut running = 0.0;
+            for y in 0..NUM_BANDS {
+                running += row[y];
+                integral[y] = running + if x > 0 { rows[x - 1][y] } else { 0.0 };
+            }
+            rows.push(integral);
+        }
+        Self { rows }
+    }
+
+    /// Sum over rows `x1..x2` and bands `y1..y2`.
+    fn area(&self, x1: usize, y1: usize, x2: usize, y2: usize) -> f64 {
+        if x2 <= x1 || y2 <= y1 {
+            return 0.0;
+        }
+        let mut area = self.rows[x2 - 1][y2 - 1];
+        if x1 > 0 {
+            area -= self.rows[x1 - 1][y2 - 1];
+            if y1 > 0 {
+                area += self.rows[x1 - 1][y1 - 1];
+            }
+        }
+        if y1 > 0 {
+            area -= self.rows[x2 - 1][y1 - 1];
+        }
+        area
+    }
+}
+
+fn apply_filter(image: &IntegralImage, kind: u8, x: usize, y: usize, w: usize, h: usize) -> f64 {
+    let (a, b) = match kind {
+        0 => (image.area(x, y, x + w, y + h), 0.0),
+        1 => {
+            let h_2 = h / 2;
+            (
+                image.area(x, y + h_2, x + w, y + h),
+                image.area(x, y, x + w, y + h_2),
+            )
+        }
+        2 => {
+            let w_2 = w / 2;
+            (
+                image.area(x + w_2, y, x + w, y + h),
+                image.area(x, y, x + w_2, y + h),
+            )
+        }
+        3 => {
+            let (w_2, h_2) = (w / 2, h / 2);
+            (
+                image.area(x, y + h_2, x + w_2, y + h) + image.area(x + w_2, y, x + w, y + h_2),
+                image.area(x, y, x + w_2, y + h_2) + image.area(x + w_2, y + h_2, x + w, y + h),
+            )
+        }
+        4 => {
+            let h_3 = h / 3;
+            (
+                image.area(x, y + h_3, x + w, y + 2 * h_3),
+                image.area(x, y, x + w, y + h_3) + image.area(x, y + 2 * h_3, x + w, y + h),
+            )
+        }
+        _ => {
+            let w_3 = w / 3;
+            (
+                image.area(x + w_3, y, x + 2 * w_3, y + h),
+                image.area(x, y, x + w_3, y + h) + image.area(x + 2 * w_3, y, x + w, y + h),
+            )
+        }
+    };
+    (1.0 + a).ln() - (1.0 + b).ln()
+}
+
+fn quantize(value: f64, [t0, t1, t2]: [f64; 3]) -> u32 {
+    if value < t1 {
+        if value < t0 { 0 } else { 1 }
+    } else if value < t2 {
+        2
+    } else {
+        3
+    }
+}
+
+fn gray_code(value: u32) -> u32 {
+    [0, 1, 3, 2][value as usize]
+}
+
+fn pack_bits(out: &mut Vec<u8>, values: impl Iterator<Item = u8>, width: u32) {
+    let mut accumulator = 0u32;
+    let mut filled = 0u32;
+    for value in values {
+        accumulator |= u32::from(value) << filled;
+        filled += width;
+        while filled >= 8 {
+            out.push(accumulator as u8);
+            accumulator >>= 8;
+            filled -= 8;
+        }
+    }
+    if filled > 0 {
+        out.push(accumulator as u8);
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn read(&mut self, width: usize) -> Option<u8> {
+        let mut value = 0u8;
+        for i in 0..width {
+            let bit_position = self.position + i;
+            let byte = self.bytes.get(bit_position / 8)?;
+            value |= ((byte >> (bit_position % 8)) & 1) << i;
+        }
+        self.position += width;
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TempDir, write_melody};
+
+    const MELODY: [f64; 6] = [261.63, 329.63, 392.0, 523.25, 440.0, 349.23];
+
+    fn melody_file(dir: &TempDir) -> String {
+        let path = dir.path().join("melody.wav");
+        write_melody(&path, &MELODY, 12.0, SAMPLE_RATE);
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn fingerprint_is_stable() {
+        let dir = TempDir::new();
+        let path = melody_file(&dir);
+
+        let first = fingerprint_file(&path).unwrap();
+        let second = fingerprint_file(&path).unwrap();
+        assert!(!first.raw.is_empty());
+        assert_eq!(first.raw, second.raw);
+        assert!((first.duration - 12.0).abs() < 0.01);
+        assert_eq!(similarity(&first.raw, &second.raw), 1.0);
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let dir = TempDir::new();
+        let raw = fingerprint_file(&melody_file(&dir)).unwrap().raw;
+        assert_eq!(decode(&encode(&raw)), Some(raw));
+
+        // Bit gaps of 7 and more go through the exception stream.
+        let sparse = vec![0, 1 << 31, 1 | (1 << 20), u32::MAX, 0];
+        assert_eq!(decode(&encode(&sparse)), Some(sparse));
+    }
+
+    #[test]
+    fn decode_rejects_truncated_input() {
+        assert_eq!(decode("AQ"), None);
+    }
+
+    #[test]
+    fn stored_fingerprint_is_reused_while_the_file_is_unchanged() {
+        let dir = TempDir::new();
+        let path = melody_file(&dir);
+        let computed = stored_or_compute(&path, None).unwrap();
+        assert_eq!(
+            decode(&computed.fingerprint).map(|raw| raw.is_empty()),
+            Some(false)
+        );
+
+        let stored = StoredFingerprint::new(
+            "stored".to_string(),
+            computed.duration,
+            computed.size,
+            computed.modified,
+        );
+        let reused = stored_or_compute(&path, Some(&stored)).unwrap();
+        assert_eq!(reused.fingerprint, "stored");
+
+        let stale = StoredFingerprint::new("stale".to_string(), 0.0, computed.size + 1, 0);
+        let recomputed = stored_or_compute(&path, Some(&stale)).unwrap();
+        assert_eq!(recomputed.fingerprint, computed.fingerprint);
+    }
+}
@@ -0,0 +1,214 @@
+use std::{
+    collections::HashMap,
+    io::Error,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use rand::Rng;
+use tauri_plugin_http::reqwest::{self, Client, Response, StatusCode, header::RETRY_AFTER};
+use tokio::sync::{Mutex, Semaphore};
+
+/// Sent with every request; MusicBrainz and the Cover Art Archive reject anonymous
+/// clients.
+pub const USER_AGENT: &str = concat!(
+    "AnchorPlayer/",
+    env!("CARGO_PKG_VERSION"),
+    " ( https://github.com/crazytravel/anchor-player )"
+);
+
+/// Requests in flight at once, across all hosts.
+const MAX_CONCURRENT_REQUESTS: usize = 4;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Attempts per request, including the first one.
+const MAX_ATTEMPTS: u32 = 4;
+
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Longest wait honoured from a `Retry-After` header.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Requests per second and burst size for hosts with published limits.
+const HOST_RATES: [(&str, f64, f64); 4] = [
+    ("musicbrainz.org", 1.0, 1.0),
+    ("api.acoustid.org", 3.0, 3.0),
+    ("itunes.apple.com", 0.33, 3.0),
+    ("coverartarchive.org", 1.0, 2.0),
+];
+
+/// Rate for every other host.
+const DEFAULT_RATE: (f64, f64) = (4.0, 8.0);
+
+/// The one HTTP client all metadata and artwork fetches go through. It caps the
+/// number of requests in flight, rate limits each host with a token bucket and
+/// retries transient failures with exponential backoff.
+pub struct HttpClient {
+    client: Client,
+    permits: Semaphore,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl HttpClient {
+    pub fn new() -> Result<Self, Error> {
+        let client = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| Error::other(format!("failed to build client: {}", e)))?;
+        Ok(Self {
+            client,
+            permits: Semaphore::new(MAX_CONCURRENT_REQUESTS),
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Fetches `url` as text; `None` means the server has nothing at that address.
+    pub async fn get_text(&self, url: &str) -> Result<Option<String>, Error> {
+        let Some(response) = self.get(url).await? else {
+            return Ok(None);
+        };
+        let text = response
+            .text()
+            .await
+            .map_err(|e| Error::other(format!("failed to read response: {}", e)))?;
+        Ok(Some(text))
+    }
+
+    pub async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, Error> {
+        let response = self
+            .get(url)
+            .await?
+            .ok_or_else(|| Error::other(format!("not found: {}", url)))?;
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| Error::other(format!("failed to read bytes: {}", e)))?;
+        Ok(bytes.to_vec())
+    }
+
+    /// Sends a GET request, retrying timeouts, connection failures, throttling and
+    /// server errors. A 404 is returned as `None`. A permit is only held while a
+    /// request is in flight, not while waiting for a token or between retries.
+    async fn get(&self, url: &str) -> Result<Option<Response>, Error> {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            self.wait_for_token(&host).await;
+            let permit = self
+                .permits
+                .acquire()
+                .await
+                .map_err(|e| Error::other(format!("client closed: {}", e)))?;
+            let result = self.client.get(url).send().await;
+            drop(permit);
+            let retry_after = match result {
+                Ok(response) if response.status() == StatusCode::NOT_FOUND => return Ok(None),
+                Ok(response) if response.status().is_success() => return Ok(Some(response)),
+                Ok(response) if retryable(response.status()) && attempt < MAX_ATTEMPTS => {
+                    retry_after(&response)
+                }
+                Ok(response) => {
+                    return Err(Error::other(format!(
+                        "request failed: {} returned {}",
+                        url,
+                        response.status()
+                    )));
+                }
+                Err(err) if (err.is_timeout() || err.is_connect()) && attempt < MAX_ATTEMPTS => {
+                    None
+                }
+                Err(err) => return Err(Error::other(format!("request failed: {}", err))),
+            };
+            let delay = retry_after.unwrap_or_else(|| backoff(attempt));
+            warn!(
+                "request to {} failed, retrying in {:.1}s",
+                host,
+                delay.as_secs_f64()
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn wait_for_token(&self, host: &str) {
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().await;
+                let bucket = buckets
+                    .entry(host.to_string())
+                    .or_insert_with(|| TokenBucket::for_host(host));
+                match bucket.take() {
+                    Ok(()) => return,
+                    Err(wait) => wait,
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn for_host(host: &str) -> Self {
+        let (rate, capacity) = HOST_RATES
+            .iter()
+            .find(|(suffix, _, _)| host == *suffix || host.ends_with(&format!(".{}", suffix)))
+            .map(|(_, rate, capacity)| (*rate, *capacity))
+            .unwrap_or(DEFAULT_RATE);
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Takes a token, or returns how long until one is available.
+    fn take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+fn retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn retry_after(response: &Response) -> Option<Duration> {
+    let seconds = response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(Duration::from_secs(seconds).min(MAX_RETRY_AFTER))
+}
+
+/// Doubles with every attempt, with up to 50% jitter so parallel retries spread out.
+fn backoff(attempt: u32) -> Duration {
+    let base = BASE_BACKOFF * 2u32.pow(attempt - 1);
+    let jitter = rand::thread_rng().gen_range(0.0..0.5);
+    base.mul_f64(1.0 + jitter)
+}
@@ -1,31 +1,72 @@
-use log::{debug, error};
+use file_reader::{AudioSniff, ScanRoot};
+use http_client::HttpClient;
+use log::{debug, error, warn};
 use rand::Rng;
+use rand::distributions::{Distribution, WeightedIndex};
+use rayon::prelude::*;
+use scan_rules::ScanRules;
 use state::{
-    EventSource, IdState, MusicFilesState, PauseState, Payload, SequenceType, SequenceTypeState,
-    TimePositionState, VolumeState,
+    ChapterState, DurationState, EventSource, FileInfoState, FingerprintState, IdState,
+    LibraryState, LibraryWatcherState, ListeningState, LookupQueueState, LyricsState,
+    MatchChoiceState, MusicFilesState, PauseState, Payload, ScanState, SequenceType,
+    SequenceTypeState, SmartPlaylistState, TimePositionState, VolumeState,
 };
 use std::{
-    path::PathBuf,
-    sync::{Mutex, mpsc::channel},
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, mpsc::channel},
     thread,
+    time::Duration,
+};
+use store::{
+    LIBRARY_STORE_FILENAME, PLAY_STATE_STORE_FILENAME, PLAYLIST_STORE_FILENAME,
+    SETTINGS_STORE_FILENAME,
 };
-use store::{PLAY_STATE_STORE_FILENAME, PLAYLIST_STORE_FILENAME, SETTINGS_STORE_FILENAME};
 use symphonia::core::units::Time;
 use tauri_plugin_store::StoreExt;
-use uuid::Uuid;
 
-use music::{MusicError, MusicFile, MusicImage, MusicInfo, MusicMap, MusicSetting, PlayState};
+use music::{
+    AudioStream, Chapter, DuplicateGroup, DuplicateReport, FileMove, Library, LibraryFolder,
+    ListeningStats, Lyrics, MatchChoice, MusicError, MusicFile, MusicInfo, MusicMap, MusicSetting,
+    NetworkPolicy, PathTags, PlayState, Playlist, PlaylistImport, PlaylistSummary, ProviderSetting,
+    RenameReport, ResumePoint, ScanReport, SmartRules, SortField, StatsPeriod, StoredFingerprint,
+    TagEdit, TagEditReport, TrackMatch, TrackResume, TrackStats, TrackTags,
+};
+use path_tags::PathPattern;
 use tauri::{AppHandle, Emitter, Manager, State};
 
+mod artwork;
 mod cache;
+mod chapters;
+mod cue;
+mod duplicates;
 mod file_reader;
+mod fingerprint;
+mod http_client;
+mod library;
+mod lyrics;
+mod matching;
 mod music;
 mod output;
+mod path_tags;
 mod player;
+mod playlist_file;
+mod playlists;
+mod provider;
+mod ratings;
 #[cfg(not(target_os = "linux"))]
 mod resampler;
+mod scan_rules;
+mod smart;
 mod state;
+mod stats;
 mod store;
+mod tag_editor;
+#[cfg(test)]
+mod test_support;
+
+/// How often the resume point of a playing track is saved.
+const RESUME_STORE_INTERVAL: Duration = Duration::from_secs(30);
 
 fn play_music(id: String, position: Option<Time>, app: AppHandle) {
     debug!("play_music id={}", id);
@@ -43,12 +84,11 @@ fn play_music(id: String, position: Option<Time>, app: AppHandle) {
     let app_info = app.clone();
     let app_play_state = app.clone();
     let app_store = app.clone();
-    let app_image = app.clone();
     let app_cache = app.clone();
+    let app_lyrics = app.clone();
     let (play_state_tx, play_state_rx) = channel::<PlayState>();
     let (store_state_tx, store_state_rx) = channel::<PlayState>();
     let (music_info_tx, music_info_rx) = channel::<MusicInfo>();
-    let (music_image_tx, music_image_rx) = channel::<MusicImage>();
 
     let music_file = {
         let music_files_state = app.state::<Mutex<MusicFilesState>>();
@@ -62,19 +102,69 @@ fn play_music(id: String, position: Option<Time>, app: AppHandle) {
         return;
     };
 
+    if let Some(reason) = music_file.unsupported.clone() {
+        if let Ok(mut pause_state) = app.state::<Mutex<PauseState>>().lock() {
+            pause_state.set(true, None, None);
+        }
+        let _ = app.emit("error", MusicError::new(Some(id), music_file.name, reason));
+        return;
+    }
+
+    // Long tracks continue where they were left off unless told where to start.
+    let resume_key = resumable_key(&app, &music_file);
+    let position = position.or_else(|| {
+        let point = store::load_resume_points(&app).remove(resume_key.as_ref()?)?;
+        (!point.finished).then(|| convert_to_time(point.position))
+    });
+    let store_resume_key = resume_key.clone();
+    let store_id = id.clone();
+
     let path = music_file.path.clone();
+    let segment = music_file.segment;
+    let track_num = store::load_audio_streams(&app).get(&path).copied();
     let needs_cache = music_file.image_path.is_none();
     let cache_music_file = music_file.clone();
+    spawn_lyrics_update(app_lyrics, path.clone());
+    spawn_chapter_update(app.clone(), &music_file);
+
+    // The resume point is saved when playback stops, pauses or seeks, and every
+    // RESUME_STORE_INTERVAL in between in case the app does not exit cleanly.
+    let store_thread = thread::spawn(move || {
+        let mut last_store_time = std::time::Instant::now();
+        let mut last_resume_time = std::time::Instant::now();
+        let mut resume_position = None;
+        for play_state in store_state_rx {
+            if play_state.id.as_ref() == Some(&store_id) {
+                resume_position = play_state.position.or(resume_position);
+            }
+            let now = std::time::Instant::now();
+            if now.duration_since(last_resume_time) >= RESUME_STORE_INTERVAL
+                && let (Some(key), Some(position)) = (&store_resume_key, resume_position)
+            {
+                let point = ResumePoint::new(position, false);
+                store::store_resume_point(&app_store, key, Some(point));
+                last_resume_time = now;
+            }
+            if now.duration_since(last_store_time).as_secs() >= 1 {
+                store::store_play_state(&app_store, Some(play_state));
+                last_store_time = now;
+            }
+        }
+        if let (Some(key), Some(position)) = (&store_resume_key, resume_position) {
+            store::store_resume_point(&app_store, key, Some(ResumePoint::new(position, false)));
+        }
+    });
 
     thread::spawn(move || {
         let code = player::start_play(
             &app,
             position,
+            segment,
+            track_num,
             path.as_str(),
             &play_state_tx,
             &store_state_tx,
             &music_info_tx,
-            &music_image_tx,
         )
         .unwrap_or_else(|err| {
             let msg = err.to_string().to_lowercase();
@@ -85,38 +175,30 @@ fn play_music(id: String, position: Option<Time>, app: AppHandle) {
             );
             -1
         });
+        // Lets the store thread save where playback stopped before a finished
+        // track is marked as such.
+        drop(store_state_tx);
+        let _ = store_thread.join();
 
         if code == 100 {
-            let _ = app.emit("finished", id.clone());
+            if let Some(key) = &resume_key {
+                store::store_resume_point(&app, key, Some(ResumePoint::new(0.0, true)));
+            }
+            // Cue sheet tracks may have carried playback on to later tracks of the file.
+            let finished_id = app
+                .state::<Mutex<IdState>>()
+                .lock()
+                .ok()
+                .and_then(|s| s.get())
+                .unwrap_or_else(|| id.clone());
+            stats::finish(&app, true);
+            let _ = app.emit("finished", finished_id.clone());
             if let Ok(mut time_pos) = app.state::<Mutex<TimePositionState>>().lock() {
                 time_pos.set(None);
             }
 
-            let next_id = {
-                let seq_state = app.state::<Mutex<SequenceTypeState>>();
-                let sequence_type = seq_state.lock().map(|s| s.get()).unwrap_or_default();
-                let mfs_state = app.state::<Mutex<MusicFilesState>>();
-                let Ok(state) = mfs_state.lock() else {
-                    return;
-                };
-                let music_files = state.get();
-
-                if music_files.is_empty() {
-                    return;
-                }
-
-                match sequence_type {
-                    SequenceType::RepeatOne => id.clone(),
-                    SequenceType::Random => {
-                        let index = rand::thread_rng().gen_range(0..music_files.len());
-                        music_files[index].id.clone()
-                    }
-                    SequenceType::Repeat => {
-                        let index = music_files.iter().position(|f| f.id == id).unwrap_or(0);
-                        let next_index = (index + 1) % music_files.len();
-                        music_files[next_index].id.clone()
-                    }
-                }
+            let Some(next_id) = next_track_id(&app, &finished_id) else {
+                return;
             };
             play_music(next_id, None, app);
         } else if code == 0 {
@@ -128,7 +210,7 @@ fn play_music(id: String, position: Option<Time>, app: AppHandle) {
     });
 
     if needs_cache {
-        spawn_cache_update(app_cache, cache_music_file);
+        spawn_cache_update(app_cache, vec![cache_music_file], false);
     }
 
     thread::spawn(move || {
@@ -154,61 +236,306 @@ fn play_music(id: String, position: Option<Time>, app: AppHandle) {
             let _ = app_play_state.emit("play-state", state);
         }
     });
+}
 
-    thread::spawn(move || {
-        for music_image in music_image_rx {
-            let _ = app_image.emit("music-image", music_image);
-        }
-    });
+/// The resume point key of `music_file` when it is long enough to remember where it
+/// was left off.
+fn resumable_key(app: &AppHandle, music_file: &MusicFile) -> Option<String> {
+    let threshold_mins = store::load_settings(app).resume_threshold_mins;
+    if threshold_mins == 0 {
+        return None;
+    }
+    let start = music_file.segment.map_or(0.0, |segment| segment.start);
+    let end = match music_file.segment.and_then(|segment| segment.end) {
+        Some(end) => end,
+        None => file_duration(app, &music_file.path)?,
+    };
+    (end - start >= f64::from(threshold_mins) * 60.0).then(|| music_file.resume_key())
+}
 
-    thread::spawn(move || {
-        let mut last_store_time = std::time::Instant::now();
-        for play_state in store_state_rx {
-            let now = std::time::Instant::now();
-            if now.duration_since(last_store_time).as_secs() >= 1 {
-                store::store_play_state(&app_store, Some(play_state));
-                last_store_time = now;
-            }
+/// The duration of the file at `music_path`, probed once per session.
+fn file_duration(app: &AppHandle, music_path: &str) -> Option<f64> {
+    let duration_state = app.state::<Mutex<DurationState>>();
+    if let Some(duration) = duration_state.lock().ok()?.get(music_path) {
+        return Some(duration);
+    }
+    let AudioSniff::Playable(Some(duration)) =
+        file_reader::sniff_audio_file(&file_reader::local_path(music_path))
+    else {
+        return None;
+    };
+    if let Ok(mut state) = duration_state.lock() {
+        state.insert(music_path.to_string(), duration);
+    }
+    Some(duration)
+}
+
+/// The track that follows `id` under the current sequence type.
+fn next_track_id(app: &AppHandle, id: &str) -> Option<String> {
+    let seq_state = app.state::<Mutex<SequenceTypeState>>();
+    let sequence_type = seq_state.lock().map(|s| s.get()).unwrap_or_default();
+    let weighted = store::load_settings(app).weighted_shuffle;
+    let mfs_state = app.state::<Mutex<MusicFilesState>>();
+    let state = mfs_state.lock().ok()?;
+    let music_files = state.get();
+
+    if music_files.is_empty() {
+        return None;
+    }
+
+    let next_id = match sequence_type {
+        SequenceType::RepeatOne => id.to_string(),
+        SequenceType::Random => {
+            let mut rng = rand::thread_rng();
+            let distribution = weighted
+                .then(|| WeightedIndex::new(music_files.iter().map(ratings::shuffle_weight)).ok())
+                .flatten();
+            let index = match distribution {
+                Some(distribution) => distribution.sample(&mut rng),
+                None => rng.gen_range(0..music_files.len()),
+            };
+            music_files[index].id.clone()
         }
-    });
+        SequenceType::Repeat => {
+            let index = music_files.iter().position(|f| f.id == id).unwrap_or(0);
+            let next_index = (index + 1) % music_files.len();
+            music_files[next_index].id.clone()
+        }
+    };
+    Some(next_id)
 }
 
-fn spawn_cache_update(app: AppHandle, music_file: MusicFile) {
+/// Looks `playlists` up in the background. `requested` marks lookups the user asked
+/// for, which the network policy may allow when it blocks automatic ones.
+fn spawn_cache_update(app: AppHandle, playlists: Vec<MusicFile>, requested: bool) {
     let (tx, rx) = channel::<MusicMap>();
-    let playlists = vec![music_file];
+    update_lookup_queue(&app, |queue| queue.push(&playlists));
 
     let cloned_app = app.clone();
     tauri::async_runtime::spawn(async move {
-        if let Ok(cache_dir) = init_cache_dir(&cloned_app)
-            && let Err(err) = cache::init_cache(cache_dir, playlists, &tx).await
-        {
-            let _ = cloned_app.emit("error", err);
+        let config = lookup_config(&cloned_app, requested);
+        let Ok(cache_dir) = init_cache_dir(&cloned_app) else {
+            return;
+        };
+        let paths: HashSet<String> = playlists.iter().map(|music| music.path.clone()).collect();
+        match cache::init_cache(cache_dir, playlists, config, &tx).await {
+            Ok(report) => {
+                let unfinished: HashSet<String> =
+                    report.failed.into_iter().chain(report.skipped).collect();
+                update_lookup_queue(&cloned_app, |queue| {
+                    queue.retain(|music| {
+                        !paths.contains(&music.path) || unfinished.contains(&music.path)
+                    })
+                });
+                if !report.file_infos.is_empty()
+                    && let Ok(mut state) = cloned_app.state::<Mutex<FileInfoState>>().lock()
+                {
+                    state.extend(report.file_infos);
+                    store::store_file_infos(&cloned_app, &state.get_cloned());
+                }
+                if !report.choices.is_empty() {
+                    if let Ok(mut state) = cloned_app.state::<Mutex<MatchChoiceState>>().lock() {
+                        state.extend(report.choices.clone());
+                    }
+                    let _ = cloned_app.emit("match-ambiguous", report.choices);
+                }
+            }
+            Err(err) => {
+                let _ = cloned_app.emit("error", err);
+            }
         }
+        let _ =
+            tauri::async_runtime::spawn_blocking(move || enforce_cache_limit(&cloned_app)).await;
     });
 
     thread::spawn(move || {
-        process_cache_results(rx, &app);
+        for music_map in rx {
+            apply_music_map(&app, music_map);
+        }
     });
 }
 
-fn process_cache_results(rx: std::sync::mpsc::Receiver<MusicMap>, app: &AppHandle) {
-    for music_map in rx {
-        let music_files_state = app.state::<Mutex<MusicFilesState>>();
-        let Ok(mut state) = music_files_state.lock() else {
+fn update_lookup_queue<F: FnOnce(&mut LookupQueueState)>(app: &AppHandle, update: F) {
+    if let Ok(mut state) = app.state::<Mutex<LookupQueueState>>().lock() {
+        update(&mut state);
+        store::store_lookup_queue(app, &state.get_cloned());
+    }
+}
+
+fn apply_music_map(app: &AppHandle, music_map: MusicMap) {
+    let music_files_state = app.state::<Mutex<MusicFilesState>>();
+    let Ok(mut state) = music_files_state.lock() else {
+        return;
+    };
+    let mut music_files = state.get_cloned();
+
+    let mut completed = Vec::new();
+    for music in music_files
+        .iter_mut()
+        .filter(|m| m.resume_key() == music_map.key)
+    {
+        music.artist = Some(music_map.artist.clone());
+        music.album = Some(music_map.album.clone());
+        music.image_path = Some(music_map.image_path.clone());
+        music.thumbnail_path = music_map.thumbnail_path.clone();
+        completed.push(music.clone());
+    }
+    if completed.is_empty() {
+        return;
+    }
+    state.set(music_files.clone());
+    store::store_playlist(app, &music_files);
+    for music in completed {
+        let _ = app.emit("music_data_completion", music);
+    }
+}
+
+/// Evicts least recently used cache entries beyond the configured limit and drops
+/// evicted artwork from the playlist.
+fn enforce_cache_limit(app: &AppHandle) {
+    let limit_mb = store::load_settings(app).cache_limit_mb;
+    if limit_mb == 0 {
+        return;
+    }
+    let Ok(cache_dir) = init_cache_dir(app) else {
+        return;
+    };
+    if cache::enforce_limit(cache_dir, limit_mb * 1024 * 1024) > 0 {
+        forget_evicted_artwork(app);
+    }
+}
+
+fn forget_evicted_artwork(app: &AppHandle) {
+    let music_files_state = app.state::<Mutex<MusicFilesState>>();
+    let Ok(mut state) = music_files_state.lock() else {
+        return;
+    };
+    let mut music_files = state.get_cloned();
+    let mut changed = Vec::new();
+    for music in music_files.iter_mut() {
+        let Some(image_path) = music.image_path.clone() else {
             continue;
         };
-        let mut music_files = state.get_cloned();
+        if !Path::new(&image_path).exists() {
+            music.image_path = None;
+            music.thumbnail_path = None;
+            changed.push(music.clone());
+        } else if music
+            .thumbnail_path
+            .as_deref()
+            .is_some_and(|thumbnail| !Path::new(thumbnail).exists())
+        {
+            music.thumbnail_path =
+                artwork::thumbnail(Path::new(&image_path), artwork::LIST_THUMBNAIL_SIZE)
+                    .map(|path| path.display().to_string());
+            changed.push(music.clone());
+        }
+    }
+    if changed.is_empty() {
+        return;
+    }
+    state.set(music_files.clone());
+    store::store_playlist(app, &music_files);
+    drop(state);
+    for music in changed {
+        let _ = app.emit("music_data_completion", music);
+    }
+}
+
+fn lookup_config(app: &AppHandle, requested: bool) -> cache::LookupConfig {
+    let settings = store::load_settings(app);
+    let acoustid = settings.acoustid_key.map(|client_key| {
+        let fingerprints = app
+            .state::<Mutex<FingerprintState>>()
+            .lock()
+            .map(|state| state.get_cloned())
+            .unwrap_or_default();
+        cache::AcoustIdConfig::new(settings.acoustid_url, client_key, fingerprints)
+    });
+    cache::LookupConfig {
+        online: settings.network_policy.allows(requested),
+        client: app.state::<Arc<HttpClient>>().inner().clone(),
+        providers: provider::from_settings(&settings.providers),
+        acoustid,
+        strip_patterns: settings.strip_patterns,
+        path_patterns: path_tags::parse_patterns(&settings.path_patterns),
+        file_infos: app
+            .state::<Mutex<FileInfoState>>()
+            .lock()
+            .map(|state| state.get_cloned())
+            .unwrap_or_default(),
+    }
+}
+
+/// Loads the lyrics of the track that just started, unless they are loaded already,
+/// and emits them as `lyrics`.
+fn spawn_lyrics_update(app: AppHandle, music_path: String) {
+    {
+        let lyrics_state = app.state::<Mutex<LyricsState>>();
+        let Ok(mut state) = lyrics_state.lock() else {
+            return;
+        };
+        if state.is_loaded(&music_path) {
+            state.reset_position();
+            return;
+        }
+        state.set(music_path.clone(), None);
+    }
+    tauri::async_runtime::spawn(async move {
+        let Ok(cache_dir) = init_cache_dir(&app) else {
+            return;
+        };
+        let online = lyrics_lookup(&app, false);
+        let lyrics = lyrics::find_lyrics(music_path.clone(), cache_dir, online).await;
+        if let Ok(mut state) = app.state::<Mutex<LyricsState>>().lock() {
+            // Another track may have started while these were looked for.
+            if !state.is_loaded(&music_path) {
+                return;
+            }
+            state.set(music_path, lyrics.clone());
+        }
+        let _ = app.emit("lyrics", lyrics);
+    });
+}
 
-        if let Some(music) = music_files.iter_mut().find(|m| m.name == music_map.name) {
-            music.artist = Some(music_map.artist.clone());
-            music.album = Some(music_map.album.clone());
-            music.image_path = Some(music_map.image_path.clone());
-            let returned_music_file = music.clone();
-            state.set(music_files.clone());
-            store::store_playlist(app, &music_files);
-            let _ = app.emit("music_data_completion", returned_music_file);
+/// Reads the chapters of the track that just started, unless they are loaded
+/// already. Cue sheet tracks have none of their own.
+fn spawn_chapter_update(app: AppHandle, music_file: &MusicFile) {
+    {
+        let chapter_state = app.state::<Mutex<ChapterState>>();
+        let Ok(mut state) = chapter_state.lock() else {
+            return;
+        };
+        if state.is_loaded(&music_file.id) {
+            return;
         }
+        state.set(music_file.id.clone(), Vec::new());
+    }
+    if music_file.segment.is_some() {
+        return;
     }
+    let id = music_file.id.clone();
+    let path = music_file.path.clone();
+    thread::spawn(move || {
+        let chapters = chapters::read_chapters(&file_reader::local_path(&path));
+        if let Ok(mut state) = app.state::<Mutex<ChapterState>>().lock()
+            && state.is_loaded(&id)
+        {
+            state.set(id, chapters);
+        }
+    });
+}
+
+fn lyrics_lookup(app: &AppHandle, requested: bool) -> Option<lyrics::LyricsLookup> {
+    let settings = store::load_settings(app);
+    settings
+        .network_policy
+        .allows(requested)
+        .then(|| lyrics::LyricsLookup {
+            client: app.state::<Arc<HttpClient>>().inner().clone(),
+            providers: provider::from_settings(&settings.providers),
+            strip_patterns: settings.strip_patterns,
+        })
 }
 
 fn init_cache_dir(app: &AppHandle) -> Result<PathBuf, ()> {
@@ -233,6 +560,7 @@ fn clear_cache(music_files_state: State<'_, Mutex<MusicFilesState>>, app: AppHan
             let mut playlist = state.get_cloned();
             for music in playlist.iter_mut() {
                 music.image_path = None;
+                music.thumbnail_path = None;
             }
             state.set(playlist.clone());
             store::store_playlist(&app, &playlist);
@@ -361,6 +689,7 @@ fn play_next(
     if let Ok(mut time_pos) = time_position_state.lock() {
         time_pos.set(None);
     }
+    stats::finish(&app, false);
 
     let pause = pause_state.lock().map(|s| s.pause).unwrap_or(true);
     if pause {
@@ -412,6 +741,7 @@ fn play_previous(
     if let Ok(mut time_pos) = time_position_state.lock() {
         time_pos.set(None);
     }
+    stats::finish(&app, false);
 
     let pause = pause_state.lock().map(|s| s.pause).unwrap_or(true);
     if pause {
@@ -435,144 +765,892 @@ fn pause(pause_state: State<'_, Mutex<PauseState>>) {
 }
 
 #[tauri::command]
-fn list_files(dirs: Vec<String>) -> Vec<String> {
-    file_reader::read_directory_files(dirs).unwrap_or_else(|err| {
-        error!("failed to read directory: {}", err);
-        Vec::new()
+async fn list_files(dirs: Vec<String>, app: AppHandle) -> Result<ScanReport, String> {
+    let cancel = app
+        .state::<Mutex<ScanState>>()
+        .lock()
+        .map_err(|e| format!("Failed to access scan state: {}", e))?
+        .begin();
+
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        let rules = Arc::new(ScanRules::default());
+        let roots = dirs
+            .into_iter()
+            .map(|dir| ScanRoot::new(PathBuf::from(dir), rules.clone()))
+            .collect();
+        file_reader::scan_directories(roots, &cancel, &|progress| {
+            let _ = app.emit("scan-progress", progress);
+        })
     })
+    .await
+    .map_err(|e| format!("Failed to scan directories: {}", e))?;
+
+    for skipped in &report.skipped {
+        warn!("skipped {}: {}", skipped.path, skipped.reason);
+    }
+    Ok(report)
 }
 
 #[tauri::command]
-fn set_volume(volume: f32, app: AppHandle, volume_state: State<'_, Mutex<VolumeState>>) {
-    let clamped = volume.clamp(0.0, 1.0);
-    if let Ok(mut vs) = volume_state.lock() {
-        vs.set(clamped);
+fn cancel_scan(scan_state: State<'_, Mutex<ScanState>>) {
+    if let Ok(scan_state) = scan_state.lock() {
+        scan_state.cancel();
     }
-    let current_settings = store::load_settings(&app);
-    store::store_settings(&app, current_settings.with_volume(clamped));
 }
 
 #[tauri::command]
-fn change_sequence_type(
-    sequence_type: u32,
+fn load_library(library_state: State<'_, Mutex<LibraryState>>) -> Library {
+    match library_state.lock() {
+        Ok(library) => Library::new(library.folders().to_vec(), library.tracks().to_vec()),
+        Err(_) => Library::new(Vec::new(), Vec::new()),
+    }
+}
+
+#[tauri::command]
+fn library_add_folder(
+    path: String,
     app: AppHandle,
-    sequence_type_state: State<'_, Mutex<SequenceTypeState>>,
-) {
-    let seq_type = SequenceType::from_u32(sequence_type);
-    if let Ok(mut st) = sequence_type_state.lock() {
-        st.set(seq_type);
+    library_state: State<'_, Mutex<LibraryState>>,
+    watcher_state: State<'_, Mutex<LibraryWatcherState>>,
+) -> Result<Library, String> {
+    let folder = PathBuf::from(&path);
+    if !folder.is_dir() {
+        return Err(format!("{} is not a directory", path));
     }
-    let current_settings = store::load_settings(&app);
-    store::store_settings(&app, current_settings.with_sequence_type(sequence_type));
+
+    let library = {
+        let mut library = library_state
+            .lock()
+            .map_err(|e| format!("Failed to access library: {}", e))?;
+        let mut folders = library.folders().to_vec();
+        if !folders.iter().any(|f| f.path == path) {
+            folders.push(LibraryFolder::new(path.clone()));
+            library.set_folders(folders.clone());
+            store::store_library_folders(&app, &folders);
+        }
+        Library::new(folders, library.tracks().to_vec())
+    };
+
+    watcher_state
+        .lock()
+        .map_err(|e| format!("Failed to access library watcher: {}", e))?
+        .watch(&folder)
+        .map_err(|e| format!("Failed to watch {}: {}", path, e))?;
+
+    thread::spawn(move || {
+        library::sync_folders(&app, vec![folder]);
+    });
+
+    Ok(library)
 }
 
 #[tauri::command]
-fn playlist_add(
-    files: Vec<String>,
+fn library_update_folder(
+    folder: LibraryFolder,
     app: AppHandle,
-    music_files_state: State<'_, Mutex<MusicFilesState>>,
-) -> Result<Vec<MusicFile>, ()> {
-    let new_files: Vec<MusicFile> = files
-        .into_iter()
-        .map(|file| {
-            let name = extract_name_from_path(&file);
-            MusicFile::new(Uuid::new_v4().to_string(), name, file, None, None, None)
-        })
-        .collect();
+    library_state: State<'_, Mutex<LibraryState>>,
+) -> Result<Library, String> {
+    ScanRules::from_folder(&folder)?;
 
-    let mut playlist = store::load_playlist(&app);
-    playlist.extend(new_files);
+    let library = {
+        let mut library = library_state
+            .lock()
+            .map_err(|e| format!("Failed to access library: {}", e))?;
+        let mut folders = library.folders().to_vec();
+        let existing = folders
+            .iter_mut()
+            .find(|f| f.path == folder.path)
+            .ok_or_else(|| format!("{} is not a library folder", folder.path))?;
+        *existing = folder.clone();
+        library.set_folders(folders.clone());
+        store::store_library_folders(&app, &folders);
+        Library::new(folders, library.tracks().to_vec())
+    };
 
-    if let Ok(mut state) = music_files_state.lock() {
-        state.set(playlist.clone());
-    }
-    store::store_playlist(&app, &playlist);
+    thread::spawn(move || {
+        library::sync_folders(&app, vec![PathBuf::from(folder.path)]);
+    });
 
-    Ok(playlist)
+    Ok(library)
 }
 
 #[tauri::command]
-fn delete_from_playlist(
-    id: String,
+fn library_remove_folder(
+    path: String,
     app: AppHandle,
-    music_files_state: State<'_, Mutex<MusicFilesState>>,
-) {
-    if let Ok(mut state) = music_files_state.lock() {
-        let mut music_files = state.get_cloned();
-        music_files.retain(|f| f.id != id);
-        state.set(music_files.clone());
-        store::store_playlist(&app, &music_files);
+    library_state: State<'_, Mutex<LibraryState>>,
+    watcher_state: State<'_, Mutex<LibraryWatcherState>>,
+) -> Result<Library, String> {
+    let folder = PathBuf::from(&path);
+    let remaining: Vec<PathBuf> = {
+        let mut library = library_state
+            .lock()
+            .map_err(|e| format!("Failed to access library: {}", e))?;
+        let mut folders = library.folders().to_vec();
+        folders.retain(|f| f.path != path);
+        library.set_folders(folders.clone());
+        store::store_library_folders(&app, &folders);
+        folders.iter().map(|f| PathBuf::from(&f.path)).collect()
+    };
+
+    if let Ok(mut watcher) = watcher_state.lock()
+        && let Err(err) = watcher.unwatch(&folder)
+    {
+        debug!("failed to unwatch {}: {}", path, err);
     }
+
+    library::remove_folder_tracks(&app, &folder, &remaining);
+
+    let library = library_state
+        .lock()
+        .map_err(|e| format!("Failed to access library: {}", e))?;
+    Ok(Library::new(
+        library.folders().to_vec(),
+        library.tracks().to_vec(),
+    ))
 }
 
+/// Groups the library and now playing tracks into duplicates. Files split by a cue
+/// sheet are left out, since their tracks share the file and its album-wide tags.
 #[tauri::command]
-fn clear_playlist(
+async fn find_duplicates(
+    tolerance: Option<f64>,
     app: AppHandle,
-    id_state: State<'_, Mutex<IdState>>,
-    pause_state: State<'_, Mutex<PauseState>>,
-    time_position_state: State<'_, Mutex<TimePositionState>>,
-    music_files_state: State<'_, Mutex<MusicFilesState>>,
-) {
-    if let Ok(mut ps) = pause_state.lock() {
-        ps.set(true, None, None);
-    }
-    if let Ok(mut tp) = time_position_state.lock() {
-        tp.set(None);
+) -> Result<DuplicateReport, String> {
+    let mut tracks: Vec<MusicFile> = Vec::new();
+    if let Ok(library) = app.state::<Mutex<LibraryState>>().lock() {
+        tracks.extend(library.tracks().iter().cloned());
     }
-    if let Ok(mut ids) = id_state.lock() {
-        ids.set(None);
+    if let Ok(state) = app.state::<Mutex<MusicFilesState>>().lock() {
+        tracks.extend(state.get().iter().cloned());
     }
-    if let Ok(mut mfs) = music_files_state.lock() {
-        mfs.set(vec![]);
+    let cue_files: HashSet<&str> = tracks
+        .iter()
+        .filter(|f| f.segment.is_some())
+        .map(|f| f.path.as_str())
+        .collect();
+    let mut paths: Vec<String> = tracks
+        .iter()
+        .filter(|f| !cue_files.contains(f.path.as_str()))
+        .map(|f| f.path.clone())
+        .collect();
+    paths.sort();
+    paths.dedup();
+
+    let tolerance = tolerance.unwrap_or(duplicates::DEFAULT_DURATION_TOLERANCE);
+    let strip_patterns = store::load_settings(&app).strip_patterns;
+    let mut fingerprints = app
+        .state::<Mutex<FingerprintState>>()
+        .lock()
+        .map(|state| state.get_cloned())
+        .unwrap_or_default();
+    let (report, fingerprints) = tauri::async_runtime::spawn_blocking(move || {
+        let report =
+            duplicates::find_duplicates(paths, tolerance, &strip_patterns, &mut fingerprints);
+        (report, fingerprints)
+    })
+    .await
+    .map_err(|e| format!("Failed to find duplicates: {}", e))?;
+
+    store::store_fingerprints(&app, &fingerprints);
+    if let Ok(mut state) = app.state::<Mutex<FingerprintState>>().lock() {
+        state.set(fingerprints);
     }
-    store::store_playlist(&app, &[]);
-    store::store_play_state(&app, None);
+    Ok(report)
 }
 
+/// Fingerprints every library track that has no up-to-date fingerprint yet and
+/// returns how many were computed.
 #[tauri::command]
-fn init_playlist(
-    app: AppHandle,
-    music_files_state: State<'_, Mutex<MusicFilesState>>,
-) -> Vec<MusicFile> {
-    let playlist = store::load_playlist(&app);
-    if let Ok(mut state) = music_files_state.lock() {
-        state.set(playlist.clone());
-    }
-
-    let filtered_playlist: Vec<MusicFile> = playlist
+async fn fingerprint_library(app: AppHandle) -> Result<usize, String> {
+    let paths: Vec<String> = app
+        .state::<Mutex<LibraryState>>()
+        .lock()
+        .map_err(|e| format!("Failed to access library: {}", e))?
+        .tracks()
         .iter()
-        .filter(|music| music.image_path.is_none())
-        .cloned()
+        .filter(|track| track.unsupported.is_none())
+        .map(|track| track.path.clone())
         .collect();
+    let stored = app
+        .state::<Mutex<FingerprintState>>()
+        .lock()
+        .map_err(|e| format!("Failed to access fingerprints: {}", e))?
+        .get_cloned();
 
-    let (tx, rx) = channel::<MusicMap>();
-    let cloned_app = app.clone();
-    tauri::async_runtime::spawn(async move {
-        if let Ok(cache_dir) = init_cache_dir(&cloned_app)
-            && let Err(err) = cache::init_cache(cache_dir, filtered_playlist, &tx).await
-        {
-            let _ = cloned_app.emit("error", err);
+    let (fingerprints, computed) = tauri::async_runtime::spawn_blocking(move || {
+        let results: Vec<(String, Option<StoredFingerprint>)> = paths
+            .into_par_iter()
+            .map(|path| {
+                let previous = stored.get(&path);
+                let result = fingerprint::stored_or_compute(&path, previous)
+                    .map_err(|err| warn!("failed to fingerprint {}: {}", path, err))
+                    .ok()
+                    .filter(|fingerprint| {
+                        previous.is_none_or(|p| p.fingerprint != fingerprint.fingerprint)
+                    });
+                (path, result)
+            })
+            .collect();
+        let mut fingerprints = stored;
+        fingerprints.retain(|path, _| file_reader::local_path(path).exists());
+        let mut computed = 0;
+        for (path, result) in results {
+            if let Some(fingerprint) = result {
+                fingerprints.insert(path, fingerprint);
+                computed += 1;
+            }
         }
-    });
-
-    let cloned_app = app.clone();
-    thread::spawn(move || {
-        process_cache_results(rx, &cloned_app);
-    });
+        (fingerprints, computed)
+    })
+    .await
+    .map_err(|e| format!("Failed to fingerprint library: {}", e))?;
 
-    playlist
+    store::store_fingerprints(&app, &fingerprints);
+    if let Ok(mut state) = app.state::<Mutex<FingerprintState>>().lock() {
+        state.set(fingerprints);
+    }
+    Ok(computed)
 }
 
+/// Removes every copy but the keeper of each group from the now playing list and
+/// the other playlists, leaving smart playlists to their rules. Returns the now
+/// playing list.
 #[tauri::command]
-fn load_playlist(
+fn drop_duplicates(
+    groups: Vec<DuplicateGroup>,
     app: AppHandle,
     music_files_state: State<'_, Mutex<MusicFilesState>>,
-) -> Vec<MusicFile> {
-    let playlist = store::load_playlist(&app);
-    if let Ok(mut state) = music_files_state.lock() {
-        state.set(playlist.clone());
-    }
-    playlist
+) -> Result<Vec<MusicFile>, String> {
+    playlists::ensure_editable(&app)?;
+    let extras: HashSet<&str> = groups
+        .iter()
+        .flat_map(|group| {
+            group
+                .tracks
+                .iter()
+                .map(|track| track.path.as_str())
+                .filter(move |path| *path != group.keeper)
+        })
+        .collect();
+
+    let remove_duplicates =
+        |tracks: &mut Vec<MusicFile>| tracks.retain(|f| !extras.contains(f.path.as_str()));
+
+    let mut state = music_files_state
+        .lock()
+        .map_err(|e| format!("Failed to access music files: {}", e))?;
+    let mut playlist = state.get_cloned();
+    remove_duplicates(&mut playlist);
+    state.set(playlist.clone());
+    store::store_playlist(&app, &playlist);
+
+    let current = store::load_current_playlist_id(&app);
+    store::update_playlists(&app, |playlists| {
+        for stored in playlists
+            .iter_mut()
+            .filter(|stored| Some(&stored.id) != current.as_ref() && stored.smart.is_none())
+        {
+            remove_duplicates(&mut stored.tracks);
+        }
+    });
+    Ok(playlist)
+}
+
+#[tauri::command]
+fn set_volume(volume: f32, app: AppHandle, volume_state: State<'_, Mutex<VolumeState>>) {
+    let clamped = volume.clamp(0.0, 1.0);
+    if let Ok(mut vs) = volume_state.lock() {
+        vs.set(clamped);
+    }
+    let current_settings = store::load_settings(&app);
+    store::store_settings(&app, current_settings.with_volume(clamped));
+}
+
+#[tauri::command]
+fn set_acoustid(url: Option<String>, key: Option<String>, app: AppHandle) -> MusicSetting {
+    let settings = store::load_settings(&app).with_acoustid(url, key);
+    store::store_settings(&app, settings.clone());
+    settings
+}
+
+/// Replaces the ordered list of metadata providers.
+#[tauri::command]
+fn set_providers(providers: Vec<ProviderSetting>, app: AppHandle) -> Result<MusicSetting, String> {
+    let mut kinds = HashSet::new();
+    for setting in &providers {
+        if !kinds.insert(setting.kind) {
+            return Err(format!("{:?} is listed more than once", setting.kind));
+        }
+        if !setting.base_url.starts_with("http://") && !setting.base_url.starts_with("https://") {
+            return Err(format!("invalid base url: {}", setting.base_url));
+        }
+    }
+    let settings = store::load_settings(&app).with_providers(providers);
+    store::store_settings(&app, settings.clone());
+    Ok(settings)
+}
+
+#[tauri::command]
+fn pending_matches(match_choice_state: State<'_, Mutex<MatchChoiceState>>) -> Vec<MatchChoice> {
+    match_choice_state
+        .lock()
+        .map(|state| state.get_cloned())
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+async fn pick_match(path: String, candidate: TrackMatch, app: AppHandle) -> Result<(), String> {
+    let choice = app
+        .state::<Mutex<MatchChoiceState>>()
+        .lock()
+        .ok()
+        .and_then(|state| state.get(&path))
+        .ok_or_else(|| format!("no pending match for {}", path))?;
+    let settings = store::load_settings(&app);
+    if !settings.network_policy.allows(true) {
+        return Err("network lookups are disabled".to_string());
+    }
+    let cache_dir = init_cache_dir(&app).map_err(|_| "cache dir is unavailable".to_string())?;
+    let providers = provider::from_settings(&settings.providers);
+    let client = app.state::<Arc<HttpClient>>().inner().clone();
+    let music_map = cache::pick_match(
+        cache_dir,
+        &client,
+        &providers,
+        choice.name,
+        choice.path,
+        candidate,
+    )
+    .await
+    .map_err(|err| err.message)?;
+    if let Ok(mut state) = app.state::<Mutex<MatchChoiceState>>().lock() {
+        state.remove(&path);
+    }
+    apply_music_map(&app, music_map);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_thumbnail(image_path: String, size: u32) -> Option<String> {
+    artwork::thumbnail(Path::new(&image_path), size).map(|path| path.display().to_string())
+}
+
+#[tauri::command]
+fn dismiss_match(path: String, match_choice_state: State<'_, Mutex<MatchChoiceState>>) {
+    if let Ok(mut state) = match_choice_state.lock() {
+        state.remove(&path);
+    }
+}
+
+#[tauri::command]
+fn set_strip_patterns(patterns: Vec<String>, app: AppHandle) -> MusicSetting {
+    let settings = store::load_settings(&app).with_strip_patterns(patterns);
+    store::store_settings(&app, settings.clone());
+    settings
+}
+
+#[tauri::command]
+fn read_tags(path: String) -> Result<TrackTags, String> {
+    tag_editor::read_tags(&path)
+}
+
+/// Writes the same edit to every selected file and returns which files were updated.
+#[tauri::command]
+async fn edit_tags(
+    paths: Vec<String>,
+    edit: TagEdit,
+    app: AppHandle,
+) -> Result<TagEditReport, String> {
+    let (report, edit) = tauri::async_runtime::spawn_blocking(move || {
+        let report = tag_editor::edit_tags(paths, &edit);
+        (report, edit)
+    })
+    .await
+    .map_err(|e| format!("Failed to edit tags: {}", e))?;
+    let report = report?;
+
+    for failed in &report.failed {
+        warn!("failed to edit tags of {}: {}", failed.path, failed.reason);
+    }
+    let edits: HashMap<&str, &TagEdit> = report
+        .updated
+        .iter()
+        .map(|path| (path.as_str(), &edit))
+        .collect();
+    library::apply_tag_edits(&app, &edits);
+    Ok(report)
+}
+
+/// Strips the configured cleanup patterns from the tags of the given files.
+#[tauri::command]
+async fn clean_tags(paths: Vec<String>, app: AppHandle) -> Result<TagEditReport, String> {
+    let strip_patterns = store::load_settings(&app).strip_patterns;
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        tag_editor::clean_tags(paths, &strip_patterns)
+    })
+    .await
+    .map_err(|e| format!("Failed to clean tags: {}", e))?;
+
+    for failed in &report.failed {
+        warn!("failed to clean tags of {}: {}", failed.path, failed.reason);
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+fn set_path_patterns(patterns: Vec<String>, app: AppHandle) -> Result<MusicSetting, String> {
+    let patterns: Vec<String> = patterns
+        .into_iter()
+        .map(|pattern| pattern.trim().to_string())
+        .filter(|pattern| !pattern.is_empty())
+        .collect();
+    for pattern in &patterns {
+        PathPattern::parse(pattern)?;
+    }
+    let settings = store::load_settings(&app).with_path_patterns(patterns);
+    store::store_settings(&app, settings.clone());
+    Ok(settings)
+}
+
+/// Parses tags out of each file's path with `pattern`, or with the configured patterns
+/// when none is given.
+fn parse_path_tags(
+    paths: &[String],
+    pattern: Option<String>,
+    app: &AppHandle,
+) -> Result<Vec<(String, Option<TagEdit>)>, String> {
+    let patterns = match pattern {
+        Some(pattern) => vec![PathPattern::parse(&pattern)?],
+        None => path_tags::parse_patterns(&store::load_settings(app).path_patterns),
+    };
+    Ok(path_tags::parse_paths(paths, &patterns))
+}
+
+#[tauri::command]
+fn preview_path_tags(
+    paths: Vec<String>,
+    pattern: Option<String>,
+    app: AppHandle,
+) -> Result<Vec<PathTags>, String> {
+    Ok(parse_path_tags(&paths, pattern, &app)?
+        .into_iter()
+        .map(|(path, tags)| PathTags::new(path, tags))
+        .collect())
+}
+
+/// Writes the tags parsed from each file's path into the file itself.
+#[tauri::command]
+async fn apply_path_tags(
+    paths: Vec<String>,
+    pattern: Option<String>,
+    app: AppHandle,
+) -> Result<TagEditReport, String> {
+    let edits: Vec<(String, TagEdit)> = parse_path_tags(&paths, pattern, &app)?
+        .into_iter()
+        .filter_map(|(path, tags)| tags.map(|tags| (path, tags)))
+        .collect();
+    let (report, edits) = tauri::async_runtime::spawn_blocking(move || {
+        let report = tag_editor::edit_each(edits.clone());
+        (report, edits)
+    })
+    .await
+    .map_err(|e| format!("Failed to write tags: {}", e))?;
+
+    for failed in &report.failed {
+        warn!("failed to write tags of {}: {}", failed.path, failed.reason);
+    }
+    let edits: HashMap<&str, &TagEdit> = edits
+        .iter()
+        .filter(|(path, _)| report.updated.contains(path))
+        .map(|(path, edit)| (path.as_str(), edit))
+        .collect();
+    library::apply_tag_edits(&app, &edits);
+    Ok(report)
+}
+
+/// Works out where each file would be moved to under `root` without moving anything.
+#[tauri::command]
+async fn preview_renames(
+    paths: Vec<String>,
+    root: String,
+    pattern: String,
+) -> Result<RenameReport, String> {
+    let pattern = PathPattern::parse(&pattern)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        path_tags::plan_renames(paths, Path::new(&root), &pattern)
+    })
+    .await
+    .map_err(|e| format!("Failed to plan renames: {}", e))
+}
+
+/// Moves files as previewed and remembers the moves so they can be undone.
+#[tauri::command]
+async fn apply_renames(moves: Vec<FileMove>, app: AppHandle) -> Result<RenameReport, String> {
+    let roots = library_roots(&app);
+    let report =
+        tauri::async_runtime::spawn_blocking(move || path_tags::apply_renames(moves, &roots))
+            .await
+            .map_err(|e| format!("Failed to move files: {}", e))?;
+
+    for skipped in &report.skipped {
+        warn!("failed to move {}: {}", skipped.path, skipped.reason);
+    }
+    store::store_rename_history(&app, &report.moves);
+    library::apply_moves(&app, &report.moves);
+    Ok(report)
+}
+
+/// Moves the files of the last applied rename back. Moves that cannot be undone
+/// stay in the history so they can be retried.
+#[tauri::command]
+async fn undo_renames(app: AppHandle) -> Result<RenameReport, String> {
+    let history = store::load_rename_history(&app);
+    let roots = library_roots(&app);
+    let (report, history) = tauri::async_runtime::spawn_blocking(move || {
+        let report = path_tags::undo_renames(history.clone(), &roots);
+        (report, history)
+    })
+    .await
+    .map_err(|e| format!("Failed to undo renames: {}", e))?;
+
+    let remaining: Vec<FileMove> = history
+        .into_iter()
+        .filter(|file_move| {
+            report
+                .skipped
+                .iter()
+                .any(|skipped| skipped.path == file_move.to)
+        })
+        .collect();
+    store::store_rename_history(&app, &remaining);
+    library::apply_moves(&app, &report.moves);
+    Ok(report)
+}
+
+/// The library folders, where moves stop removing emptied folders.
+fn library_roots(app: &AppHandle) -> Vec<PathBuf> {
+    app.state::<Mutex<LibraryState>>()
+        .lock()
+        .map(|library| {
+            library
+                .folders()
+                .iter()
+                .map(|folder| PathBuf::from(&folder.path))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+fn change_sequence_type(
+    sequence_type: u32,
+    app: AppHandle,
+    sequence_type_state: State<'_, Mutex<SequenceTypeState>>,
+) {
+    let seq_type = SequenceType::from_u32(sequence_type);
+    if let Ok(mut st) = sequence_type_state.lock() {
+        st.set(seq_type);
+    }
+    let current_settings = store::load_settings(&app);
+    store::store_settings(&app, current_settings.with_sequence_type(sequence_type));
+}
+
+#[tauri::command]
+fn playlist_add(files: Vec<String>, app: AppHandle) -> Result<Vec<MusicFile>, ()> {
+    if let Err(err) = playlists::ensure_editable(&app) {
+        warn!("not adding files: {}", err);
+        return Err(());
+    }
+    let entries = files
+        .into_iter()
+        .map(|file| {
+            let name = extract_name_from_path(&file);
+            (file, name)
+        })
+        .collect();
+    Ok(append_to_playlist(&app, entries))
+}
+
+/// Imports an M3U/M3U8, PLS or XSPF playlist into the now playing list.
+#[tauri::command]
+async fn import_playlist(path: String, app: AppHandle) -> Result<PlaylistImport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        playlists::ensure_editable(&app)?;
+        let (resolved, skipped) = playlist_file::read(Path::new(&path))?;
+        for entry in &skipped {
+            warn!("skipped {}: {}", entry.path, entry.reason);
+        }
+        let entries = resolved
+            .into_iter()
+            .map(|entry| {
+                let name = entry
+                    .title
+                    .unwrap_or_else(|| extract_name_from_path(&entry.path));
+                (entry.path, name)
+            })
+            .collect();
+        Ok(PlaylistImport::new(
+            append_to_playlist(&app, entries),
+            skipped,
+        ))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Writes a playlist, the now playing one unless `id` names another, to `path` in
+/// the format its extension names.
+#[tauri::command]
+async fn export_playlist(
+    path: String,
+    relative: bool,
+    id: Option<String>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let tracks = match id {
+        Some(id) => store::load_playlists(&app)
+            .into_iter()
+            .find(|playlist| playlist.id == id)
+            .map(|playlist| playlist.tracks)
+            .ok_or_else(|| format!("playlist {} not found", id))?,
+        None => app
+            .state::<Mutex<MusicFilesState>>()
+            .lock()
+            .map(|state| state.get_cloned())
+            .unwrap_or_default(),
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        playlist_file::write(Path::new(&path), &tracks, relative)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Appends files, given as path and display name, to the now playing playlist and
+/// returns the whole list. Files described by a cue sheet, and cue sheets
+/// themselves, are added as one virtual track per cue track.
+fn append_to_playlist(app: &AppHandle, entries: Vec<(String, String)>) -> Vec<MusicFile> {
+    let mut new_files: Vec<MusicFile> = Vec::new();
+    for (file, name) in entries {
+        let cue_tracks = cue::tracks_for(&file_reader::local_path(&file));
+        for music in cue::expand(file, name, cue_tracks) {
+            // A cue sheet added together with its audio file yields the same tracks twice.
+            let duplicate = new_files
+                .iter()
+                .any(|added| added.path == music.path && added.segment == music.segment);
+            if !duplicate {
+                new_files.push(music);
+            }
+        }
+    }
+    let mut sniffed: HashMap<String, Option<String>> = HashMap::new();
+    for music in new_files.iter_mut() {
+        let unsupported = sniffed
+            .entry(music.path.clone())
+            .or_insert_with(|| {
+                match file_reader::sniff_audio_file(&file_reader::local_path(&music.path)) {
+                    AudioSniff::Playable(_) => None,
+                    AudioSniff::Unsupported(reason) => Some(reason),
+                    AudioSniff::NotAudio => {
+                        Some("file is not a recognised audio format".to_string())
+                    }
+                }
+            })
+            .clone();
+        music.unsupported = unsupported;
+    }
+
+    ratings::apply_stored(app, &mut new_files);
+
+    let mut playlist = store::load_playlist(app);
+    playlist.extend(new_files.iter().cloned());
+
+    if let Ok(mut state) = app.state::<Mutex<MusicFilesState>>().lock() {
+        state.set(playlist.clone());
+    }
+    store::store_playlist(app, &playlist);
+
+    // Resolve artwork while importing so playback never has to decode it.
+    let supported: Vec<MusicFile> = new_files
+        .into_iter()
+        .filter(|music| music.unsupported.is_none())
+        .collect();
+    if !supported.is_empty() {
+        spawn_cache_update(app.clone(), supported, false);
+    }
+
+    playlist
+}
+
+#[tauri::command]
+fn delete_from_playlist(
+    id: String,
+    app: AppHandle,
+    music_files_state: State<'_, Mutex<MusicFilesState>>,
+) {
+    if let Err(err) = playlists::ensure_editable(&app) {
+        warn!("not deleting {}: {}", id, err);
+        return;
+    }
+    if let Ok(mut state) = music_files_state.lock() {
+        let mut music_files = state.get_cloned();
+        music_files.retain(|f| f.id != id);
+        state.set(music_files.clone());
+        store::store_playlist(&app, &music_files);
+    }
+}
+
+/// Sorts the now playing list, keeping the order of tracks that compare equal.
+#[tauri::command]
+fn sort_playlist(field: SortField, descending: bool, app: AppHandle) -> Vec<MusicFile> {
+    playlists::sort(&app, field, descending)
+}
+
+#[tauri::command]
+fn clear_playlist(
+    app: AppHandle,
+    id_state: State<'_, Mutex<IdState>>,
+    pause_state: State<'_, Mutex<PauseState>>,
+    time_position_state: State<'_, Mutex<TimePositionState>>,
+    music_files_state: State<'_, Mutex<MusicFilesState>>,
+) {
+    if let Err(err) = playlists::ensure_editable(&app) {
+        warn!("not clearing the playlist: {}", err);
+        return;
+    }
+    if let Ok(mut ps) = pause_state.lock() {
+        ps.set(true, None, None);
+    }
+    if let Ok(mut tp) = time_position_state.lock() {
+        tp.set(None);
+    }
+    if let Ok(mut ids) = id_state.lock() {
+        ids.set(None);
+    }
+    if let Ok(mut mfs) = music_files_state.lock() {
+        mfs.set(vec![]);
+    }
+    store::store_playlist(&app, &[]);
+    store::store_play_state(&app, None);
+}
+
+#[tauri::command]
+fn init_playlist(
+    app: AppHandle,
+    music_files_state: State<'_, Mutex<MusicFilesState>>,
+) -> Vec<MusicFile> {
+    let playlist = store::load_playlist(&app);
+    if let Ok(mut state) = music_files_state.lock() {
+        state.set(playlist.clone());
+    }
+
+    let mut filtered_playlist: Vec<MusicFile> = playlist
+        .iter()
+        .filter(|music| music.image_path.is_none())
+        .cloned()
+        .collect();
+    // Lookups left unfinished by the last session.
+    let queued = app
+        .state::<Mutex<LookupQueueState>>()
+        .lock()
+        .map(|state| state.get_cloned())
+        .unwrap_or_default();
+    for music in queued {
+        if !filtered_playlist.iter().any(|m| m.path == music.path) {
+            filtered_playlist.push(music);
+        }
+    }
+    spawn_cache_update(app, filtered_playlist, false);
+
+    playlist
+}
+
+#[tauri::command]
+fn load_playlist(
+    app: AppHandle,
+    music_files_state: State<'_, Mutex<MusicFilesState>>,
+) -> Vec<MusicFile> {
+    let playlist = store::load_playlist(&app);
+    if let Ok(mut state) = music_files_state.lock() {
+        state.set(playlist.clone());
+    }
+    playlist
+}
+
+#[tauri::command]
+fn list_playlists(app: AppHandle) -> Vec<PlaylistSummary> {
+    playlists::summaries(&app)
+}
+
+#[tauri::command]
+fn create_playlist(name: String, app: AppHandle) -> Result<PlaylistSummary, String> {
+    playlists::create(&app, &name)
+}
+
+/// Adds a playlist holding the library tracks that match `rules`. Rules on
+/// duration, codec or tags read every library file the first time.
+#[tauri::command]
+async fn create_smart_playlist(
+    name: String,
+    rules: SmartRules,
+    app: AppHandle,
+) -> Result<PlaylistSummary, String> {
+    tauri::async_runtime::spawn_blocking(move || playlists::create_smart(&app, &name, rules))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+async fn update_smart_playlist(
+    id: String,
+    rules: SmartRules,
+    app: AppHandle,
+) -> Result<Playlist, String> {
+    tauri::async_runtime::spawn_blocking(move || playlists::update_rules(&app, &id, rules))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// The tracks `rules` would pick, for editing them before saving.
+#[tauri::command]
+async fn preview_smart_playlist(
+    rules: SmartRules,
+    app: AppHandle,
+) -> Result<Vec<MusicFile>, String> {
+    tauri::async_runtime::spawn_blocking(move || smart::preview(&app, &rules))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn rename_playlist(id: String, name: String, app: AppHandle) -> Result<PlaylistSummary, String> {
+    playlists::rename(&app, &id, &name)
+}
+
+#[tauri::command]
+fn duplicate_playlist(
+    id: String,
+    name: Option<String>,
+    app: AppHandle,
+) -> Result<PlaylistSummary, String> {
+    playlists::duplicate(&app, &id, name.as_deref())
+}
+
+#[tauri::command]
+fn delete_playlist(id: String, app: AppHandle) -> Result<(), String> {
+    playlists::delete(&app, &id)
+}
+
+#[tauri::command]
+fn reorder_playlists(ids: Vec<String>, app: AppHandle) -> Result<Vec<PlaylistSummary>, String> {
+    playlists::reorder(&app, &ids)
+}
+
+/// Switches the now playing list; returns the selected playlist with its tracks.
+#[tauri::command]
+fn select_playlist(id: String, app: AppHandle) -> Result<Playlist, String> {
+    playlists::select(&app, &id)
 }
 
 #[tauri::command]
@@ -601,10 +1679,15 @@ fn load_play_state(
     if let Ok(mut ids) = id_state.lock() {
         ids.set(play_state.id.clone());
     }
-    if let Some(ref progress) = play_state.progress
+    // States saved by older versions only have the display string.
+    let position = play_state
+        .position
+        .map(convert_to_time)
+        .or_else(|| play_state.progress.as_deref().map(parse_str_time));
+    if let Some(position) = position
         && let Ok(mut tp) = time_position_state.lock()
     {
-        tp.set(Some(parse_str_time(progress)));
+        tp.set(Some(position));
     }
     Some(play_state)
 }
@@ -616,6 +1699,345 @@ fn show_main_window(window: tauri::Window) {
     }
 }
 
+#[tauri::command]
+fn set_cache_limit(limit_mb: u64, app: AppHandle) -> MusicSetting {
+    let settings = store::load_settings(&app).with_cache_limit_mb(limit_mb);
+    store::store_settings(&app, settings.clone());
+    tauri::async_runtime::spawn_blocking(move || enforce_cache_limit(&app));
+    settings
+}
+
+/// Looks the given tracks up again, ignoring cached metadata and earlier misses.
+#[tauri::command]
+async fn refresh_metadata(paths: Vec<String>, app: AppHandle) -> Result<(), String> {
+    let cache_dir = init_cache_dir(&app).map_err(|_| "cache dir is unavailable".to_string())?;
+    let forgotten = paths.clone();
+    tauri::async_runtime::spawn_blocking(move || cache::forget(cache_dir, &forgotten))
+        .await
+        .map_err(|e| e.to_string())?;
+    let music_files: Vec<MusicFile> = app
+        .state::<Mutex<MusicFilesState>>()
+        .lock()
+        .map(|state| {
+            state
+                .get()
+                .iter()
+                .filter(|music| paths.contains(&music.path))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+    spawn_cache_update(app, music_files, true);
+    Ok(())
+}
+
+#[tauri::command]
+fn set_network_policy(policy: NetworkPolicy, app: AppHandle) -> MusicSetting {
+    let settings = store::load_settings(&app).with_network_policy(policy);
+    store::store_settings(&app, settings.clone());
+    settings
+}
+
+#[tauri::command]
+fn get_lookup_queue(lookup_queue_state: State<'_, Mutex<LookupQueueState>>) -> Vec<MusicFile> {
+    lookup_queue_state
+        .lock()
+        .map(|state| state.get_cloned())
+        .unwrap_or_default()
+}
+
+/// Runs every queued lookup in one batch and returns how many were started.
+#[tauri::command]
+fn run_queued_lookups(app: AppHandle) -> Result<usize, String> {
+    if !store::load_settings(&app).network_policy.allows(true) {
+        return Err("network lookups are disabled".to_string());
+    }
+    let queued = app
+        .state::<Mutex<LookupQueueState>>()
+        .lock()
+        .map(|state| state.get_cloned())
+        .unwrap_or_default();
+    let count = queued.len();
+    if count > 0 {
+        spawn_cache_update(app, queued, true);
+    }
+    Ok(count)
+}
+
+/// Looks lyrics for `path` up again, online too unless the network policy is `NEVER`.
+#[tauri::command]
+async fn get_lyrics(path: String, app: AppHandle) -> Result<Option<Lyrics>, String> {
+    let cache_dir = init_cache_dir(&app).map_err(|_| "cache dir is unavailable".to_string())?;
+    let online = lyrics_lookup(&app, true);
+    let lyrics = lyrics::find_lyrics(path.clone(), cache_dir, online).await;
+    if let Ok(mut state) = app.state::<Mutex<LyricsState>>().lock()
+        && state.is_loaded(&path)
+    {
+        state.set(path, lyrics.clone());
+    }
+    Ok(lyrics)
+}
+
+/// Resume points of the tracks in the now playing list.
+#[tauri::command]
+fn get_resume_points(
+    music_files_state: State<'_, Mutex<MusicFilesState>>,
+    app: AppHandle,
+) -> Vec<TrackResume> {
+    let points = store::load_resume_points(&app);
+    let Ok(state) = music_files_state.lock() else {
+        return Vec::new();
+    };
+    state
+        .get()
+        .iter()
+        .filter_map(|music| {
+            let point = points.get(&music.resume_key())?;
+            Some(TrackResume {
+                id: music.id.clone(),
+                position: point.position,
+                finished: point.finished,
+            })
+        })
+        .collect()
+}
+
+#[tauri::command]
+fn mark_finished(id: String, app: AppHandle) -> Result<(), String> {
+    let music = playlist_track(&app, &id)?;
+    store::store_resume_point(&app, &music.resume_key(), Some(ResumePoint::new(0.0, true)));
+    Ok(())
+}
+
+/// Forgets where a track was left off, so it plays from the start next time.
+#[tauri::command]
+fn reset_position(id: String, app: AppHandle) -> Result<(), String> {
+    let music = playlist_track(&app, &id)?;
+    store::store_resume_point(&app, &music.resume_key(), None);
+    Ok(())
+}
+
+#[tauri::command]
+fn set_resume_threshold(minutes: u32, app: AppHandle) -> MusicSetting {
+    let settings = store::load_settings(&app).with_resume_threshold_mins(minutes);
+    store::store_settings(&app, settings.clone());
+    settings
+}
+
+/// Rates track `id` from 0 to 5 stars in half steps; `None` clears the rating.
+#[tauri::command]
+fn rate_track(id: String, rating: Option<f32>, app: AppHandle) -> Result<MusicFile, String> {
+    let rating = ratings::validate(rating)?;
+    ratings::rate(&app, &id, |current| current.rating = rating)
+}
+
+#[tauri::command]
+fn set_favourite(id: String, favourite: bool, app: AppHandle) -> Result<MusicFile, String> {
+    ratings::rate(&app, &id, |current| current.favourite = favourite)
+}
+
+/// Rates the track being played, for the global shortcut.
+#[tauri::command]
+fn rate_current_track(
+    rating: Option<f32>,
+    id_state: State<'_, Mutex<IdState>>,
+    app: AppHandle,
+) -> Result<MusicFile, String> {
+    let rating = ratings::validate(rating)?;
+    let id = current_track_id(&id_state)?;
+    ratings::rate(&app, &id, |current| current.rating = rating)
+}
+
+/// Toggles the favourite flag of the track being played, for the global shortcut.
+#[tauri::command]
+fn love_current_track(
+    id_state: State<'_, Mutex<IdState>>,
+    app: AppHandle,
+) -> Result<MusicFile, String> {
+    let id = current_track_id(&id_state)?;
+    ratings::rate(&app, &id, |current| current.favourite = !current.favourite)
+}
+
+#[tauri::command]
+fn set_write_rating_tags(enabled: bool, app: AppHandle) -> MusicSetting {
+    let settings = store::load_settings(&app).with_write_rating_tags(enabled);
+    store::store_settings(&app, settings.clone());
+    settings
+}
+
+#[tauri::command]
+fn set_weighted_shuffle(enabled: bool, app: AppHandle) -> MusicSetting {
+    let settings = store::load_settings(&app).with_weighted_shuffle(enabled);
+    store::store_settings(&app, settings.clone());
+    settings
+}
+
+fn current_track_id(id_state: &State<'_, Mutex<IdState>>) -> Result<String, String> {
+    id_state
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get()
+        .ok_or_else(|| "No track is playing".to_string())
+}
+
+/// Chapters of the current track.
+#[tauri::command]
+fn get_chapters(
+    id_state: State<'_, Mutex<IdState>>,
+    music_files_state: State<'_, Mutex<MusicFilesState>>,
+    app: AppHandle,
+) -> Vec<Chapter> {
+    let Some(id) = get_current_or_first_track_id(&id_state, &music_files_state) else {
+        return Vec::new();
+    };
+    current_chapters(&app, &id)
+}
+
+/// Seeks to the start of the next chapter.
+#[tauri::command]
+fn next_chapter(
+    id_state: State<'_, Mutex<IdState>>,
+    pause_state: State<'_, Mutex<PauseState>>,
+    music_files_state: State<'_, Mutex<MusicFilesState>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let id = get_current_or_first_track_id(&id_state, &music_files_state)
+        .ok_or_else(|| "No track available".to_string())?;
+    let position = current_position(&app);
+    let target = chapters::next_start(&current_chapters(&app, &id), position)
+        .ok_or_else(|| "No next chapter".to_string())?;
+    seek(target, id_state, pause_state, music_files_state, app)
+}
+
+/// Seeks to the start of the current chapter, or of the previous one when the
+/// current chapter only just started.
+#[tauri::command]
+fn previous_chapter(
+    id_state: State<'_, Mutex<IdState>>,
+    pause_state: State<'_, Mutex<PauseState>>,
+    music_files_state: State<'_, Mutex<MusicFilesState>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let id = get_current_or_first_track_id(&id_state, &music_files_state)
+        .ok_or_else(|| "No track available".to_string())?;
+    let position = current_position(&app);
+    let target = chapters::previous_start(&current_chapters(&app, &id), position)
+        .ok_or_else(|| "No previous chapter".to_string())?;
+    seek(target, id_state, pause_state, music_files_state, app)
+}
+
+/// Audio streams of the current track's file.
+#[tauri::command]
+fn get_audio_streams(
+    id_state: State<'_, Mutex<IdState>>,
+    music_files_state: State<'_, Mutex<MusicFilesState>>,
+    app: AppHandle,
+) -> Result<Vec<AudioStream>, String> {
+    let id = get_current_or_first_track_id(&id_state, &music_files_state)
+        .ok_or_else(|| "No track available".to_string())?;
+    let music = playlist_track(&app, &id)?;
+    let selected = store::load_audio_streams(&app).get(&music.path).copied();
+    player::audio_streams(&music.path, selected)
+        .ok_or_else(|| format!("cannot read the streams of {}", music.path))
+}
+
+/// Plays stream `index` of the current track's file from the current position,
+/// and keeps playing it for that file from now on.
+#[tauri::command]
+fn select_audio_stream(
+    index: usize,
+    id_state: State<'_, Mutex<IdState>>,
+    pause_state: State<'_, Mutex<PauseState>>,
+    music_files_state: State<'_, Mutex<MusicFilesState>>,
+    app: AppHandle,
+) -> Result<Vec<AudioStream>, String> {
+    let id = get_current_or_first_track_id(&id_state, &music_files_state)
+        .ok_or_else(|| "No track available".to_string())?;
+    let music = playlist_track(&app, &id)?;
+    let streams = player::audio_streams(&music.path, Some(index))
+        .ok_or_else(|| format!("cannot read the streams of {}", music.path))?;
+    if !streams.iter().any(|stream| stream.index == index) {
+        return Err(format!("{} has no audio stream {}", music.path, index));
+    }
+    store::store_audio_stream(&app, &music.path, Some(index));
+
+    let is_playing = pause_state.lock().map(|s| !s.pause).unwrap_or(false);
+    if is_playing {
+        seek(
+            current_position(&app),
+            id_state,
+            pause_state,
+            music_files_state,
+            app,
+        )?;
+    }
+    Ok(streams)
+}
+
+/// Play and skip counts and last-played time of every track ever played.
+#[tauri::command]
+fn get_track_stats(app: AppHandle) -> Vec<TrackStats> {
+    let mut stats: Vec<TrackStats> = stats::track_stats(&store::load_play_log(&app))
+        .into_values()
+        .collect();
+    stats.sort_by(|a, b| b.play_count.cmp(&a.play_count));
+    stats
+}
+
+/// Total listening time and most played tracks, artists and albums of a period.
+#[tauri::command]
+fn get_listening_stats(
+    period: StatsPeriod,
+    limit: Option<usize>,
+    app: AppHandle,
+) -> ListeningStats {
+    stats::listening_stats(
+        &store::load_play_log(&app),
+        period,
+        limit.unwrap_or(stats::DEFAULT_LIMIT),
+    )
+}
+
+/// Chapters of track `id`, read now if playback has not loaded them.
+fn current_chapters(app: &AppHandle, id: &str) -> Vec<Chapter> {
+    if let Ok(state) = app.state::<Mutex<ChapterState>>().lock()
+        && state.is_loaded(id)
+    {
+        return state.get_cloned();
+    }
+    let Ok(music) = playlist_track(app, id) else {
+        return Vec::new();
+    };
+    if music.segment.is_some() {
+        return Vec::new();
+    }
+    let chapters = chapters::read_chapters(&file_reader::local_path(&music.path));
+    if let Ok(mut state) = app.state::<Mutex<ChapterState>>().lock() {
+        state.set(id.to_string(), chapters.clone());
+    }
+    chapters
+}
+
+fn current_position(app: &AppHandle) -> f64 {
+    app.state::<Mutex<TimePositionState>>()
+        .lock()
+        .ok()
+        .and_then(|s| s.get())
+        .map(|time| time.seconds as f64 + time.frac)
+        .unwrap_or(0.0)
+}
+
+fn playlist_track(app: &AppHandle, id: &str) -> Result<MusicFile, String> {
+    app.state::<Mutex<MusicFilesState>>()
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get()
+        .iter()
+        .find(|music| music.id == id)
+        .cloned()
+        .ok_or_else(|| format!("track {} not found", id))
+}
+
 #[tauri::command]
 fn get_cache_size(app: AppHandle) -> String {
     match app.path().app_cache_dir() {
@@ -636,6 +2058,18 @@ pub fn run() {
         .manage(Mutex::new(MusicFilesState::default()))
         .manage(Mutex::new(SequenceTypeState::default()))
         .manage(Mutex::new(TimePositionState::default()))
+        .manage(Mutex::new(LibraryState::default()))
+        .manage(Mutex::new(LibraryWatcherState::default()))
+        .manage(Mutex::new(ScanState::default()))
+        .manage(Mutex::new(FingerprintState::default()))
+        .manage(Mutex::new(FileInfoState::default()))
+        .manage(Mutex::new(DurationState::default()))
+        .manage(Mutex::new(MatchChoiceState::default()))
+        .manage(Mutex::new(LookupQueueState::default()))
+        .manage(Mutex::new(LyricsState::default()))
+        .manage(Mutex::new(ChapterState::default()))
+        .manage(Mutex::new(ListeningState::default()))
+        .manage(Mutex::new(SmartPlaylistState::default()))
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
@@ -649,6 +2083,7 @@ pub fn run() {
             play_previous,
             switch,
             list_files,
+            cancel_scan,
             set_volume,
             change_sequence_type,
             delete_from_playlist,
@@ -658,12 +2093,77 @@ pub fn run() {
             load_playlist,
             load_settings,
             load_play_state,
-            get_cache_size
+            get_cache_size,
+            load_library,
+            library_add_folder,
+            library_update_folder,
+            library_remove_folder,
+            find_duplicates,
+            drop_duplicates,
+            fingerprint_library,
+            set_acoustid,
+            set_strip_patterns,
+            read_tags,
+            edit_tags,
+            clean_tags,
+            set_providers,
+            set_path_patterns,
+            preview_path_tags,
+            apply_path_tags,
+            preview_renames,
+            apply_renames,
+            undo_renames,
+            pending_matches,
+            pick_match,
+            dismiss_match,
+            get_thumbnail,
+            set_cache_limit,
+            refresh_metadata,
+            set_network_policy,
+            get_lookup_queue,
+            run_queued_lookups,
+            get_lyrics,
+            list_playlists,
+            create_playlist,
+            rename_playlist,
+            duplicate_playlist,
+            delete_playlist,
+            reorder_playlists,
+            select_playlist,
+            import_playlist,
+            export_playlist,
+            get_resume_points,
+            mark_finished,
+            reset_position,
+            set_resume_threshold,
+            get_chapters,
+            next_chapter,
+            previous_chapter,
+            get_audio_streams,
+            select_audio_stream,
+            get_track_stats,
+            get_listening_stats,
+            sort_playlist,
+            rate_track,
+            set_favourite,
+            rate_current_track,
+            love_current_track,
+            set_write_rating_tags,
+            set_weighted_shuffle,
+            create_smart_playlist,
+            update_smart_playlist,
+            preview_smart_playlist
         ])
         .setup(|app| {
             app.store(SETTINGS_STORE_FILENAME)?;
             app.store(PLAYLIST_STORE_FILENAME)?;
             app.store(PLAY_STATE_STORE_FILENAME)?;
+            app.store(LIBRARY_STORE_FILENAME)?;
+            app.manage(Arc::new(HttpClient::new()?));
+            if let Ok(mut state) = app.state::<Mutex<LookupQueueState>>().lock() {
+                state.set(store::load_lookup_queue(app.handle()));
+            }
+            init_library(app.handle());
             Ok(())
         })
         .on_window_event(|win, event| {
@@ -684,6 +2184,37 @@ pub fn run() {
         .expect("error while running tauri application");
 }
 
+fn init_library(app: &AppHandle) {
+    let folders = store::load_library_folders(app);
+    let tracks = store::load_library_tracks(app);
+    if let Ok(mut library) = app.state::<Mutex<LibraryState>>().lock() {
+        library.set_folders(folders.clone());
+        library.set_tracks(tracks);
+    }
+    if let Ok(mut state) = app.state::<Mutex<FingerprintState>>().lock() {
+        state.set(store::load_fingerprints(app));
+    }
+    if let Ok(mut state) = app.state::<Mutex<FileInfoState>>().lock() {
+        state.set(store::load_file_infos(app));
+    }
+
+    let folder_paths: Vec<PathBuf> = folders.iter().map(|f| PathBuf::from(&f.path)).collect();
+    if let Ok(mut watcher_state) = app.state::<Mutex<LibraryWatcherState>>().lock() {
+        watcher_state.set(library::watch_library(app));
+        for path in &folder_paths {
+            if let Err(err) = watcher_state.watch(path) {
+                error!("failed to watch {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    let app = app.clone();
+    thread::spawn(move || {
+        library::sync_folders(&app, folder_paths);
+        smart::refresh(&app, smart::RefreshScope::Library);
+    });
+}
+
 fn parse_str_time(time: &str) -> Time {
     let parts: Vec<&str> = time.split(':').collect();
     if parts.len() != 3 {
@@ -726,9 +2257,8 @@ fn get_current_or_first_track_id(
 }
 
 fn extract_name_from_path(path: &str) -> String {
-    PathBuf::from(path)
+    file_reader::local_path(path)
         .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown")
-        .to_string()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
 }
@@ -0,0 +1,550 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::AtomicBool,
+        mpsc::{Receiver, RecvTimeoutError, channel},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use log::{debug, error, warn};
+use notify::{
+    Event, EventKind, RecommendedWatcher,
+    event::{AccessKind, AccessMode, ModifyKind, RenameMode},
+};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{
+    cue::{self, CueTrack},
+    file_reader::{self, AudioSniff, ScanRoot},
+    music::{FileMove, LibraryChange, LibraryFolder, MusicFile, ScanReport, TagEdit},
+    ratings,
+    scan_rules::ScanRules,
+    smart,
+    state::{LibraryState, MusicFilesState},
+    store,
+};
+
+/// Quiet period after the last filesystem event before pending changes are applied.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(750);
+/// Upper bound on how long a continuous burst of events may postpone an update.
+const WATCH_MAX_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct PendingChanges {
+    renames: Vec<(PathBuf, PathBuf)>,
+    touched: Vec<PathBuf>,
+    since: Option<Instant>,
+}
+
+impl PendingChanges {
+    fn is_empty(&self) -> bool {
+        self.renames.is_empty() && self.touched.is_empty()
+    }
+
+    fn collect(&mut self, event: Event) {
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                let mut paths = event.paths.into_iter();
+                if let (Some(from), Some(to)) = (paths.next(), paths.next()) {
+                    self.touched.push(from.clone());
+                    self.touched.push(to.clone());
+                    self.renames.push((from, to));
+                }
+            }
+            EventKind::Access(AccessKind::Close(AccessMode::Write))
+            | EventKind::Create(_)
+            | EventKind::Remove(_)
+            | EventKind::Modify(ModifyKind::Data(_))
+            | EventKind::Modify(ModifyKind::Name(_))
+            | EventKind::Modify(ModifyKind::Any) => self.touched.extend(event.paths),
+            _ => return,
+        }
+        self.since.get_or_insert_with(Instant::now);
+    }
+}
+
+/// Creates the filesystem watcher for library folders and spawns the thread that
+/// debounces its events into library updates.
+pub fn watch_library(app: &AppHandle) -> Option<RecommendedWatcher> {
+    let (tx, rx) = channel::<notify::Result<Event>>();
+    let watcher = match notify::recommended_watcher(tx) {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            error!("failed to create library watcher: {}", err);
+            return None;
+        }
+    };
+
+    let app = app.clone();
+    thread::spawn(move || {
+        process_watch_events(rx, &app);
+    });
+    Some(watcher)
+}
+
+fn process_watch_events(rx: Receiver<notify::Result<Event>>, app: &AppHandle) {
+    let mut pending = PendingChanges::default();
+    loop {
+        let received = match pending.since {
+            Some(since) if since.elapsed() >= WATCH_MAX_DELAY => Err(RecvTimeoutError::Timeout),
+            Some(_) => rx.recv_timeout(WATCH_DEBOUNCE),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match received {
+            Ok(Ok(event)) => pending.collect(event),
+            Ok(Err(err)) => warn!("library watch error: {}", err),
+            Err(RecvTimeoutError::Timeout) => {
+                let changes = std::mem::take(&mut pending);
+                if !changes.is_empty() {
+                    apply_changes(app, changes.renames, changes.touched);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+/// Re-synchronises the given folders with the library, picking up anything that
+/// changed while they were not being watched.
+pub fn sync_folders(app: &AppHandle, folders: Vec<PathBuf>) {
+    apply_changes(app, Vec::new(), folders);
+}
+
+/// Removes every track below `folder` from the library, except those that are also
+/// below one of the `remaining` library folders.
+pub fn remove_folder_tracks(app: &AppHandle, folder: &Path, remaining: &[PathBuf]) {
+    let change = {
+        let library_state = app.state::<Mutex<LibraryState>>();
+        let Ok(mut library) = library_state.lock() else {
+            return;
+        };
+        let mut tracks = library.tracks_cloned();
+        let mut change = LibraryChange::default();
+        tracks.retain(|music| {
+            let path = file_reader::local_path(&music.path);
+            let keep = !path.starts_with(folder)
+                || remaining
+                    .iter()
+                    .any(|remaining| path.starts_with(remaining));
+            if !keep {
+                change.removed.push(music.id.clone());
+            }
+            keep
+        });
+        if change.is_empty() {
+            return;
+        }
+        library.set_tracks(tracks.clone());
+        store::store_library_tracks(app, &tracks);
+        change
+    };
+    let _ = app.emit("library-changed", change);
+    smart::spawn_refresh(app, smart::RefreshScope::Library);
+}
+
+fn apply_changes(app: &AppHandle, renames: Vec<(PathBuf, PathBuf)>, touched: Vec<PathBuf>) {
+    let Ok((folders, known)) = app.state::<Mutex<LibraryState>>().lock().map(|library| {
+        let known: HashSet<String> = library
+            .tracks()
+            .iter()
+            .map(|music| music.path.clone())
+            .collect();
+        (folder_rules(library.folders()), known)
+    }) else {
+        return;
+    };
+
+    // Scanning can take a while, so it happens before the library is locked.
+    let mut seen = HashSet::new();
+    let scans: Vec<(PathBuf, PathScan)> = touched
+        .into_iter()
+        .filter_map(|path| {
+            let (base, rules) = owning_folder(&folders, &path)?;
+            if !seen.insert(path.clone()) {
+                return None;
+            }
+            let root = ScanRoot::within(base.clone(), path.clone(), rules.clone());
+            let scan = scan_path(app, &root, &known);
+            Some((path, scan))
+        })
+        .collect();
+
+    let change = {
+        let library_state = app.state::<Mutex<LibraryState>>();
+        let Ok(mut library) = library_state.lock() else {
+            return;
+        };
+        let mut tracks = library.tracks_cloned();
+        let mut change = LibraryChange::default();
+
+        for (from, to) in &renames {
+            rename_tracks(&mut tracks, from, to, &mut change);
+        }
+        for (path, scan) in &scans {
+            sync_path(&mut tracks, path, scan, &mut change);
+        }
+
+        if !change.is_empty() {
+            let mut updated_ids = HashSet::new();
+            change
+                .updated
+                .retain(|music| updated_ids.insert(music.id.clone()));
+            library.set_tracks(tracks.clone());
+            store::store_library_tracks(app, &tracks);
+        }
+        change
+    };
+
+    if !renames.is_empty() {
+        rename_playlist_tracks(app, &renames);
+    }
+    if change.is_empty() {
+        return;
+    }
+    debug!(
+        "library changed: {} added, {} updated, {} removed",
+        change.added.len(),
+        change.updated.len(),
+        change.removed.len()
+    );
+    let _ = app.emit("library-changed", change);
+    smart::spawn_refresh(app, smart::RefreshScope::Library);
+}
+
+/// Points library and playlist entries at the new locations of files the app moved
+/// itself, keeping their ids and looked-up metadata.
+pub fn apply_moves(app: &AppHandle, moves: &[FileMove]) {
+    let renames = moves
+        .iter()
+        .map(|file_move| {
+            (
+                file_reader::local_path(&file_move.from),
+                file_reader::local_path(&file_move.to),
+            )
+        })
+        .collect();
+    apply_changes(app, renames, Vec::new());
+}
+
+/// Compiles the scan rules of every library folder, falling back to the defaults for
+/// folders whose patterns no longer parse.
+fn folder_rules(folders: &[LibraryFolder]) -> Vec<(PathBuf, Arc<ScanRules>)> {
+    folders
+        .iter()
+        .map(|folder| {
+            let rules = ScanRules::from_folder(folder).unwrap_or_else(|err| {
+                warn!("{}: {}", folder.path, err);
+                ScanRules::default()
+            });
+            (PathBuf::from(&folder.path), Arc::new(rules))
+        })
+        .collect()
+}
+
+/// The innermost library folder containing `path`.
+fn owning_folder<'a>(
+    folders: &'a [(PathBuf, Arc<ScanRules>)],
+    path: &Path,
+) -> Option<&'a (PathBuf, Arc<ScanRules>)> {
+    folders
+        .iter()
+        .filter(|(base, _)| path.starts_with(base))
+        .max_by_key(|(base, _)| base.components().count())
+}
+
+/// What a touched path holds on disk. Files new to the library come with their
+/// entries, built before the library is locked.
+enum PathScan {
+    Dir(ScanReport, HashMap<String, Vec<MusicFile>>),
+    /// A file, unsupported for the given reason.
+    File(Option<String>, Vec<MusicFile>),
+    /// Gone, or no longer audio.
+    Gone,
+}
+
+/// Reads the disk for `root`. Entries are only built for files missing from
+/// `known`: one per track of their cue sheet, if any, with the ratings stored for
+/// them or found in their tags.
+fn scan_path(app: &AppHandle, root: &ScanRoot, known: &HashSet<String>) -> PathScan {
+    let entries = |files: Vec<(&str, Option<String>)>| {
+        let mut added: Vec<MusicFile> = files
+            .into_iter()
+            .filter(|(path, _)| !known.contains(*path))
+            .flat_map(|(path, unsupported)| {
+                let cue_tracks = cue::tracks_for(&file_reader::local_path(path));
+                new_tracks(path.to_string(), unsupported, cue_tracks)
+            })
+            .collect();
+        ratings::apply_scanned(app, &mut added);
+        added
+    };
+    let path = root.path.as_path();
+    if path.is_dir() {
+        let scan_root = ScanRoot::within(root.base.clone(), root.path.clone(), root.rules.clone());
+        let report =
+            file_reader::scan_directories(vec![scan_root], &AtomicBool::new(false), &|_| {});
+        for skipped in &report.skipped {
+            warn!(
+                "skipped {} while scanning library: {}",
+                skipped.path, skipped.reason
+            );
+        }
+        let unsupported = unsupported_reasons(&report);
+        let files = report
+            .files
+            .iter()
+            .map(|path| (path.as_str(), unsupported.get(path.as_str()).cloned()))
+            .collect();
+        let mut added: HashMap<String, Vec<MusicFile>> = HashMap::new();
+        for music in entries(files) {
+            added.entry(music.path.clone()).or_default().push(music);
+        }
+        PathScan::Dir(report, added)
+    } else if path.is_file() {
+        let path_str = file_reader::path_string(path);
+        let unsupported = match file_reader::inspect_file(root, path) {
+            AudioSniff::Playable(_) => None,
+            AudioSniff::Unsupported(reason) => Some(reason),
+            AudioSniff::NotAudio => return PathScan::Gone,
+        };
+        let added = entries(vec![(path_str.as_str(), unsupported.clone())]);
+        PathScan::File(unsupported, added)
+    } else {
+        PathScan::Gone
+    }
+}
+
+/// Why each unsupported file of `report` cannot be played, by path.
+fn unsupported_reasons(report: &ScanReport) -> HashMap<&str, String> {
+    report
+        .unsupported
+        .iter()
+        .map(|entry| (entry.path.as_str(), entry.reason.clone()))
+        .collect()
+}
+
+fn sync_path(
+    tracks: &mut Vec<MusicFile>,
+    path: &Path,
+    scan: &PathScan,
+    change: &mut LibraryChange,
+) {
+    match scan {
+        PathScan::Dir(report, added) => sync_dir(tracks, path, report, added, change),
+        PathScan::File(unsupported, added) => {
+            let path_str = file_reader::path_string(path);
+            // Cue sheet tracks share their file's path, so every entry is updated.
+            let mut found = false;
+            for music in tracks.iter_mut().filter(|music| music.path == path_str) {
+                music.unsupported = unsupported.clone();
+                change.updated.push(music.clone());
+                found = true;
+            }
+            if !found {
+                for music in entries_of(Some(added), path_str, unsupported.clone()) {
+                    change.added.push(music.clone());
+                    tracks.push(music);
+                }
+            }
+        }
+        PathScan::Gone => remove_tracks_under(tracks, path, change),
+    }
+}
+
+fn sync_dir(
+    tracks: &mut Vec<MusicFile>,
+    dir: &Path,
+    report: &ScanReport,
+    added: &HashMap<String, Vec<MusicFile>>,
+    change: &mut LibraryChange,
+) {
+    // Tracks below entries that could not be read are kept; their absence from the
+    // scan says nothing about whether they still exist.
+    let found_paths: HashSet<&str> = report.files.iter().map(String::as_str).collect();
+    tracks.retain(|music| {
+        let path = file_reader::local_path(&music.path);
+        let keep = !path.starts_with(dir)
+            || found_paths.contains(music.path.as_str())
+            || report
+                .skipped
+                .iter()
+                .any(|skipped| path.starts_with(file_reader::local_path(&skipped.path)));
+        if !keep {
+            change.removed.push(music.id.clone());
+        }
+        keep
+    });
+
+    let unsupported = unsupported_reasons(report);
+    let known_paths: HashSet<String> = tracks.iter().map(|music| music.path.clone()).collect();
+    for path in &report.files {
+        if !known_paths.contains(path) {
+            let reason = unsupported.get(path.as_str()).cloned();
+            let entries = added.get(path).map(Vec::as_slice);
+            for music in entries_of(entries, path.clone(), reason) {
+                change.added.push(music.clone());
+                tracks.push(music);
+            }
+        }
+    }
+}
+
+/// The entries the scan built for a new file. A file that was in the library when
+/// the scan started but has gone since has none, and gets a plain entry.
+fn entries_of(
+    entries: Option<&[MusicFile]>,
+    path: String,
+    unsupported: Option<String>,
+) -> Vec<MusicFile> {
+    match entries {
+        Some(entries) if !entries.is_empty() => entries.to_vec(),
+        _ => new_tracks(path, unsupported, None),
+    }
+}
+
+fn rename_tracks(tracks: &mut [MusicFile], from: &Path, to: &Path, change: &mut LibraryChange) {
+    for music in tracks.iter_mut() {
+        let path = file_reader::local_path(&music.path);
+        let Ok(relative) = path.strip_prefix(from) else {
+            continue;
+        };
+        let renamed = if relative.as_os_str().is_empty() {
+            to.to_path_buf()
+        } else {
+            to.join(relative)
+        };
+        music.path = file_reader::path_string(&renamed);
+        music.name = crate::extract_name_from_path(&music.path);
+        change.updated.push(music.clone());
+    }
+}
+
+fn remove_tracks_under(tracks: &mut Vec<MusicFile>, path: &Path, change: &mut LibraryChange) {
+    tracks.retain(|music| {
+        let keep = !file_reader::local_path(&music.path).starts_with(path);
+        if !keep {
+            change.removed.push(music.id.clone());
+        }
+        keep
+    });
+}
+
+/// Follows renames in every playlist, not just the one playing.
+fn rename_playlist_tracks(app: &AppHandle, renames: &[(PathBuf, PathBuf)]) {
+    let music_files_state = app.state::<Mutex<MusicFilesState>>();
+    let Ok(mut state) = music_files_state.lock() else {
+        return;
+    };
+    let mut playlist = state.get_cloned();
+    let mut ignored = LibraryChange::default();
+    for (from, to) in renames {
+        rename_tracks(&mut playlist, from, to, &mut ignored);
+    }
+    if !ignored.updated.is_empty() {
+        state.set(playlist.clone());
+        store::store_playlist(app, &playlist);
+    }
+
+    let current = store::load_current_playlist_id(app);
+    store::update_playlists(app, |playlists| {
+        for stored in playlists
+            .iter_mut()
+            .filter(|stored| Some(&stored.id) != current.as_ref())
+        {
+            let mut ignored = LibraryChange::default();
+            for (from, to) in renames {
+                rename_tracks(&mut stored.tracks, from, to, &mut ignored);
+            }
+        }
+    });
+}
+
+/// Mirrors edited title, artist, album and cover tags into the library and playlist
+/// entries of the edited files, so the lists show the new values without a rescan.
+pub fn apply_tag_edits(app: &AppHandle, edits: &HashMap<&str, &TagEdit>) {
+    let update = |music: &mut MusicFile| {
+        let Some(edit) = edits.get(music.path.as_str()) else {
+            return false;
+        };
+        let cover_changed = edit.cover.is_some() || edit.remove_cover;
+        if edit.title.is_none() && edit.artist.is_none() && edit.album.is_none() && !cover_changed {
+            return false;
+        }
+        if cover_changed {
+            // Resolved again from the file the next time the track is looked up.
+            music.image_path = None;
+            music.thumbnail_path = None;
+        }
+        // Cue sheet tracks take their titles from the sheet, not the file's tag.
+        if let Some(title) = &edit.title
+            && music.segment.is_none()
+        {
+            music.name = Some(title.trim().to_string())
+                .filter(|title| !title.is_empty())
+                .unwrap_or_else(|| crate::extract_name_from_path(&music.path));
+        }
+        if let Some(artist) = &edit.artist {
+            music.artist = Some(artist.trim().to_string()).filter(|artist| !artist.is_empty());
+        }
+        if let Some(album) = &edit.album {
+            music.album = Some(album.trim().to_string()).filter(|album| !album.is_empty());
+        }
+        true
+    };
+
+    let mut change = LibraryChange::default();
+    if let Ok(mut library) = app.state::<Mutex<LibraryState>>().lock() {
+        let mut tracks = library.tracks_cloned();
+        for music in tracks.iter_mut() {
+            if update(music) {
+                change.updated.push(music.clone());
+            }
+        }
+        if !change.is_empty() {
+            library.set_tracks(tracks.clone());
+            store::store_library_tracks(app, &tracks);
+        }
+    }
+
+    if let Ok(mut state) = app.state::<Mutex<MusicFilesState>>().lock() {
+        let mut playlist = state.get_cloned();
+        let mut changed = false;
+        for music in playlist.iter_mut() {
+            changed |= update(music);
+        }
+        if changed {
+            state.set(playlist.clone());
+            store::store_playlist(app, &playlist);
+        }
+    }
+
+    if !change.is_empty() {
+        let _ = app.emit("library-changed", change);
+        smart::spawn_refresh(app, smart::RefreshScope::Library);
+    }
+}
+
+/// The library entries of a newly found file, one per cue sheet track if a cue
+/// sheet describes it.
+fn new_tracks(
+    path: String,
+    unsupported: Option<String>,
+    cue_tracks: Option<Vec<CueTrack>>,
+) -> Vec<MusicFile> {
+    let name = crate::extract_name_from_path(&path);
+    let added_at = Some(Utc::now().timestamp());
+    cue::expand(path, name, cue_tracks)
+        .into_iter()
+        .map(|music| {
+            music
+                .with_unsupported(unsupported.clone())
+                .with_added_at(added_at)
+        })
+        .collect()
+}
@@ -0,0 +1,462 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use lofty::{
+    config::ParseOptions,
+    id3::v2::{Frame, SynchronizedTextFrame, TimestampFormat},
+    mpeg::MpegFile,
+    prelude::*,
+};
+use log::warn;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{
+    cache, file_reader,
+    http_client::HttpClient,
+    music::{LyricLine, LyricWord, Lyrics, LyricsPosition, LyricsSource},
+    player,
+    provider::{MetadataProvider, TrackQuery},
+    state::LyricsState,
+};
+
+/// Vorbis comment some taggers use instead of LYRICS.
+const UNSYNCED_LYRICS_KEY: &str = "UNSYNCEDLYRICS";
+
+/// What an online lyrics lookup needs.
+pub struct LyricsLookup {
+    pub client: Arc<HttpClient>,
+    pub providers: Vec<Box<dyn MetadataProvider>>,
+    pub strip_patterns: Vec<String>,
+}
+
+/// Finds the best lyrics for a track. Synchronized lyrics stored with the file win,
+/// then synchronized lyrics found online, then whatever plain lyrics there are.
+/// Online results are cached; `online` is `None` when the network may not be used.
+pub async fn find_lyrics(
+    music_path: String,
+    cache_dir: PathBuf,
+    online: Option<LyricsLookup>,
+) -> Option<Lyrics> {
+    let path = music_path.clone();
+    let dir = cache_dir.clone();
+    let (stored, cached) = tauri::async_runtime::spawn_blocking(move || {
+        (
+            file_lyrics(&file_reader::local_path(&path)),
+            cached_lyrics(&path, &dir),
+        )
+    })
+    .await
+    .ok()?;
+    if stored.as_ref().is_some_and(|lyrics| lyrics.synced) {
+        return stored;
+    }
+
+    let mut fetched = cached;
+    if fetched.is_none()
+        && let Some(online) = online
+    {
+        fetched = fetch_lyrics(&music_path, &cache_dir, &online).await;
+    }
+    match (stored, fetched) {
+        (_, Some(fetched)) if fetched.synced => Some(fetched),
+        (Some(stored), _) => Some(stored),
+        (None, fetched) => fetched,
+    }
+}
+
+/// Lyrics stored with the track: a sidecar `.lrc` file, SYLT frames or a plain
+/// lyrics tag, synchronized ones preferred.
+pub fn file_lyrics(music_path: &Path) -> Option<Lyrics> {
+    let path = music_path.to_string_lossy();
+    let sidecar =
+        sidecar_lyrics(music_path).and_then(|text| parse_lrc(&path, LyricsSource::Sidecar, &text));
+    if sidecar.as_ref().is_some_and(|lyrics| lyrics.synced) {
+        return sidecar;
+    }
+    let embedded = synchronized_frame(music_path)
+        .map(|lines| Lyrics {
+            path: path.to_string(),
+            source: LyricsSource::Embedded,
+            synced: true,
+            lines,
+        })
+        .or_else(|| {
+            embedded_text(music_path)
+                .and_then(|text| parse_lrc(&path, LyricsSource::Embedded, &text))
+        });
+    match (sidecar, embedded) {
+        (_, Some(embedded)) if embedded.synced => Some(embedded),
+        (Some(sidecar), _) => Some(sidecar),
+        (None, embedded) => embedded,
+    }
+}
+
+fn cached_lyrics(music_path: &str, cache_dir: &Path) -> Option<Lyrics> {
+    let path = cache::lyrics_path(cache_dir, music_path);
+    let text = fs::read_to_string(&path).ok()?;
+    cache::touch(&path);
+    parse_lrc(music_path, LyricsSource::Online, &text)
+}
+
+async fn fetch_lyrics(music_path: &str, cache_dir: &Path, online: &LyricsLookup) -> Option<Lyrics> {
+    let path = music_path.to_string();
+    let dir = cache_dir.to_path_buf();
+    let strip_patterns = online.strip_patterns.clone();
+    let (query, cache_path) = tauri::async_runtime::spawn_blocking(move || {
+        (
+            lyrics_query(&path, &strip_patterns),
+            cache::lyrics_path(&dir, &path),
+        )
+    })
+    .await
+    .ok()?;
+
+    for provider in &online.providers {
+        match provider.lyrics(&online.client, &query).await {
+            Ok(Some(text)) => {
+                let Some(lyrics) = parse_lrc(music_path, LyricsSource::Online, &text) else {
+                    continue;
+                };
+                let saved = cache_path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(&cache_path, &text));
+                if let Err(err) = saved {
+                    warn!("failed to cache lyrics: {}", err);
+                }
+                return Some(lyrics);
+            }
+            Ok(None) => {}
+            Err(err) => warn!("{:?} lyrics lookup failed: {}", provider.kind(), err),
+        }
+    }
+    None
+}
+
+fn lyrics_query(music_path: &str, strip_patterns: &[String]) -> TrackQuery {
+    let non_empty = |value: String| Some(value).filter(|value| !value.is_empty());
+    let query = match player::load_metadata(music_path, strip_patterns) {
+        Some(meta) if !meta.title.is_empty() => TrackQuery::new(
+            Some(meta.title),
+            non_empty(meta.artist),
+            non_empty(meta.album),
+        ),
+        _ => TrackQuery::from_keyword(
+            Path::new(music_path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        ),
+    };
+    query.with_duration(cache::decoded_duration(music_path))
+}
+
+/// The `.lrc` file sharing the track's name, whatever the case of its extension.
+fn sidecar_lyrics(music_path: &Path) -> Option<String> {
+    let stem = music_path.file_stem()?;
+    let path = fs::read_dir(music_path.parent()?)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_stem() == Some(stem)
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("lrc"))
+        })?;
+    let bytes = fs::read(path).ok()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Millisecond-timed SYLT frames of an MP3's ID3v2 tag.
+fn synchronized_frame(music_path: &Path) -> Option<Vec<LyricLine>> {
+    let mut file = File::open(music_path).ok()?;
+    let mpeg = MpegFile::read_from(&mut file, ParseOptions::new()).ok()?;
+    let tag = mpeg.id3v2()?;
+    tag.into_iter().find_map(|frame| {
+        let Frame::Binary(binary) = frame else {
+            return None;
+        };
+        if frame.id_str() != "SYLT" {
+            return None;
+        }
+        let sylt = SynchronizedTextFrame::parse(&binary.data, frame.flags()).ok()?;
+        if !matches!(sylt.timestamp_format, TimestampFormat::MS) {
+            return None;
+        }
+        let lines: Vec<LyricLine> = sylt
+            .content
+            .into_iter()
+            .map(|(millis, text)| LyricLine {
+                time: Some(millis as f64 / 1000.0),
+                text: text.trim().to_string(),
+                words: Vec::new(),
+            })
+            .filter(|line| !line.text.is_empty())
+            .collect();
+        (!lines.is_empty()).then_some(lines)
+    })
+}
+
+/// USLT frames, LYRICS comments or MP4 lyrics atoms. Their text is often LRC itself.
+fn embedded_text(music_path: &Path) -> Option<String> {
+    let tagged_file = lofty::read_from_path(music_path).ok()?;
+    tagged_file.tags().iter().find_map(|tag| {
+        tag.get_string(&ItemKey::Lyrics)
+            .or_else(|| tag.get_string(&ItemKey::Unknown(UNSYNCED_LYRICS_KEY.to_string())))
+            .map(str::to_string)
+    })
+}
+
+/// Parses LRC, including `[offset:]` and enhanced `<mm:ss.xx>` word timing. Text
+/// without any timestamps is returned as plain lyrics.
+pub fn parse_lrc(path: &str, source: LyricsSource, text: &str) -> Option<Lyrics> {
+    let mut offset = 0.0;
+    let mut synced = Vec::new();
+    let mut plain = Vec::new();
+    for raw in text.trim_start_matches('\u{feff}').lines() {
+        let mut rest = raw.trim();
+        let mut times = Vec::new();
+        let mut tagged = false;
+        while let Some(inner) = rest.strip_prefix('[') {
+            let Some(end) = inner.find(']') else {
+                break;
+            };
+            let tag = &inner[..end];
+            rest = inner[end + 1..].trim_start();
+            tagged = true;
+            if let Some(time) = parse_timestamp(tag) {
+                times.push(time);
+            } else if let Some(value) = tag.strip_prefix("offset:") {
+                offset = value.trim().parse::<f64>().unwrap_or(0.0) / 1000.0;
+            }
+        }
+
+        let (line_text, words) = parse_words(rest);
+        if !times.is_empty() {
+            for time in times {
+                synced.push(LyricLine {
+                    time: Some(time),
+                    text: line_text.clone(),
+                    words: words.clone(),
+                });
+            }
+        } else if !tagged && !line_text.is_empty() {
+            plain.push(LyricLine {
+                time: None,
+                text: line_text,
+                words: Vec::new(),
+            });
+        }
+    }
+
+    if synced.is_empty() {
+        return (!plain.is_empty()).then(|| Lyrics {
+            path: path.to_string(),
+            source,
+            synced: false,
+            lines: plain,
+        });
+    }
+    // A positive offset shows the lyrics earlier.
+    for line in &mut synced {
+        line.time = line.time.map(|time| (time - offset).max(0.0));
+        for word in &mut line.words {
+            word.time = (word.time - offset).max(0.0);
+        }
+    }
+    synced.sort_by(|a, b| a.time.unwrap_or(0.0).total_cmp(&b.time.unwrap_or(0.0)));
+    Some(Lyrics {
+        path: path.to_string(),
+        source,
+        synced: true,
+        lines: synced,
+    })
+}
+
+/// Splits enhanced LRC into the line's text and its timed words.
+fn parse_words(text: &str) -> (String, Vec<LyricWord>) {
+    let mut plain = String::new();
+    let mut words = Vec::new();
+    let mut current = None;
+    let mut rest = text;
+    loop {
+        let (segment, next) = match rest.find('<') {
+            Some(start) => (&rest[..start], Some(&rest[start + 1..])),
+            None => (rest, None),
+        };
+        if let Some(time) = current
+            && !segment.trim().is_empty()
+        {
+            words.push(LyricWord {
+                time,
+                text: segment.to_string(),
+            });
+        }
+        plain.push_str(segment);
+        let Some(next) = next else {
+            break;
+        };
+        match next
+            .find('>')
+            .and_then(|end| parse_timestamp(&next[..end]).map(|time| (time, end)))
+        {
+            Some((time, end)) => {
+                current = Some(time);
+                rest = &next[end + 1..];
+            }
+            None => {
+                plain.push('<');
+                rest = next;
+            }
+        }
+    }
+    (plain.trim().to_string(), words)
+}
+
+/// Reads `mm:ss`, `mm:ss.xx` or `mm:ss:xx` into seconds.
+fn parse_timestamp(tag: &str) -> Option<f64> {
+    let digits = |value: &str| !value.is_empty() && value.chars().all(|c| c.is_ascii_digit());
+    let mut parts = tag.trim().split(':');
+    let minutes = parts.next().filter(|value| digits(value))?;
+    let seconds = parts.next()?;
+    let fraction = parts.next();
+    if parts.next().is_some() {
+        return None;
+    }
+    let (whole, decimals) = match (seconds.split_once('.'), fraction) {
+        (Some((whole, decimals)), None) => (whole, Some(decimals)),
+        (None, fraction) => (seconds, fraction),
+        (Some(_), Some(_)) => return None,
+    };
+    if !digits(whole) || decimals.is_some_and(|decimals| !digits(decimals)) {
+        return None;
+    }
+    let mut time = minutes.parse::<f64>().ok()? * 60.0 + whole.parse::<f64>().ok()?;
+    if let Some(decimals) = decimals {
+        time += format!("0.{}", decimals).parse::<f64>().ok()?;
+    }
+    Some(time)
+}
+
+/// Index of the line, and of the word within it, sung at `seconds`.
+pub fn position_at(lyrics: &Lyrics, seconds: f64) -> Option<(usize, Option<usize>)> {
+    if !lyrics.synced {
+        return None;
+    }
+    let line = lyrics
+        .lines
+        .partition_point(|line| line.time.is_some_and(|time| time <= seconds))
+        .checked_sub(1)?;
+    let word = lyrics.lines[line]
+        .words
+        .partition_point(|word| word.time <= seconds)
+        .checked_sub(1);
+    Some((line, word))
+}
+
+/// Called with every decoded position; emits `lyrics-line` whenever the current
+/// line or word changes.
+pub fn update_position(app: &AppHandle, music_path: &str, seconds: f64) {
+    let position = {
+        let lyrics_state = app.state::<Mutex<LyricsState>>();
+        let Ok(mut state) = lyrics_state.lock() else {
+            return;
+        };
+        if !state.is_loaded(music_path) {
+            return;
+        }
+        let Some((line, word)) = state
+            .lyrics()
+            .and_then(|lyrics| position_at(lyrics, seconds))
+        else {
+            return;
+        };
+        let position = LyricsPosition {
+            path: music_path.to_string(),
+            line,
+            word,
+        };
+        if !state.update_position(position.clone()) {
+            return;
+        }
+        position
+    };
+    let _ = app.emit("lyrics-line", position);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(lyrics: &Lyrics) -> Vec<Option<f64>> {
+        lyrics.lines.iter().map(|line| line.time).collect()
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("01:02"), Some(62.0));
+        assert_eq!(parse_timestamp("01:02.50"), Some(62.5));
+        assert_eq!(parse_timestamp("01:02:25"), Some(62.25));
+        assert_eq!(parse_timestamp(" 0:00.5 "), Some(0.5));
+        assert_eq!(parse_timestamp("ar:Artist"), None);
+        assert_eq!(parse_timestamp("01:02.5.0"), None);
+        assert_eq!(parse_timestamp("01:02.50:25"), None);
+        assert_eq!(parse_timestamp("01"), None);
+        assert_eq!(parse_timestamp(""), None);
+    }
+
+    #[test]
+    fn sorts_repeated_timestamps_and_skips_tags() {
+        let text = "\u{feff}[ar:Artist]\n[ti:Title]\n[00:10.00][00:30.00]Chorus\n[00:20.00]Verse\n";
+        let lyrics = parse_lrc("song.mp3", LyricsSource::Sidecar, text).unwrap();
+        assert!(lyrics.synced);
+        assert_eq!(times(&lyrics), [Some(10.0), Some(20.0), Some(30.0)]);
+        let texts: Vec<&str> = lyrics.lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, ["Chorus", "Verse", "Chorus"]);
+    }
+
+    #[test]
+    fn applies_the_offset() {
+        let text = "[offset:500]\n[00:00.25]First\n[00:02.00]Second";
+        let lyrics = parse_lrc("song.mp3", LyricsSource::Sidecar, text).unwrap();
+        assert_eq!(times(&lyrics), [Some(0.0), Some(1.5)]);
+    }
+
+    #[test]
+    fn reads_enhanced_word_timing() {
+        let text = "[00:01.00]<00:01.00>Hello <00:01.50>world\n[00:03.00]a <b";
+        let lyrics = parse_lrc("song.mp3", LyricsSource::Online, text).unwrap();
+        let line = &lyrics.lines[0];
+        assert_eq!(line.text, "Hello world");
+        assert_eq!(
+            line.words,
+            [
+                LyricWord {
+                    time: 1.0,
+                    text: "Hello ".to_string(),
+                },
+                LyricWord {
+                    time: 1.5,
+                    text: "world".to_string(),
+                },
+            ]
+        );
+        assert_eq!(lyrics.lines[1].text, "a <b");
+        assert!(lyrics.lines[1].words.is_empty());
+        assert_eq!(position_at(&lyrics, 1.6), Some((0, Some(1))));
+        assert_eq!(position_at(&lyrics, 0.5), None);
+    }
+
+    #[test]
+    fn untimed_text_is_plain() {
+        let lyrics = parse_lrc("song.mp3", LyricsSource::Embedded, "First\n\nSecond\n").unwrap();
+        assert!(!lyrics.synced);
+        assert_eq!(times(&lyrics), [None, None]);
+        assert_eq!(position_at(&lyrics, 10.0), None);
+        assert!(parse_lrc("song.mp3", LyricsSource::Embedded, " \n[ar:Artist]\n").is_none());
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{music::TrackMatch, provider::TrackQuery};
+
+/// Candidates scoring below this are never applied.
+pub const MIN_MATCH_SCORE: f64 = 0.6;
+
+/// Distinct candidates scoring within this of the best one make a match ambiguous.
+const AMBIGUITY_MARGIN: f64 = 0.05;
+
+/// How many candidates are offered when the user has to pick.
+const MAX_CHOICES: usize = 5;
+
+const TITLE_WEIGHT: f64 = 0.45;
+const ARTIST_WEIGHT: f64 = 0.3;
+const DURATION_WEIGHT: f64 = 0.15;
+const ALBUM_WEIGHT: f64 = 0.1;
+
+/// Durations this close (in seconds) count as identical; the score falls to zero at
+/// `DURATION_LIMIT`.
+const DURATION_TOLERANCE: f64 = 2.0;
+const DURATION_LIMIT: f64 = 30.0;
+
+pub enum MatchOutcome {
+    Accepted(TrackMatch),
+    /// Several distinct recordings fit about equally well, best first.
+    Ambiguous(Vec<TrackMatch>),
+    Rejected,
+}
+
+/// Scores every candidate against `query` and decides whether the best one can be
+/// applied without asking.
+pub fn rank(query: &TrackQuery, candidates: Vec<TrackMatch>) -> MatchOutcome {
+    let mut scored: Vec<TrackMatch> = candidates
+        .into_iter()
+        .map(|candidate| TrackMatch {
+            score: score(query, &candidate),
+            ..candidate
+        })
+        .filter(|candidate| candidate.score >= MIN_MATCH_SCORE)
+        .collect();
+    scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    // Releases of the same recording only compete when the album is part of the query.
+    let key = |candidate: &TrackMatch| {
+        let album = query
+            .album
+            .as_ref()
+            .map(|_| normalize(&candidate.album))
+            .unwrap_or_default();
+        (
+            normalize(&candidate.title),
+            normalize(&candidate.artist),
+            album,
+        )
+    };
+    let mut distinct: Vec<TrackMatch> = Vec::new();
+    for candidate in scored {
+        if !distinct.iter().any(|kept| key(kept) == key(&candidate)) {
+            distinct.push(candidate);
+        }
+    }
+
+    let mut distinct = distinct.into_iter();
+    let Some(best) = distinct.next() else {
+        return MatchOutcome::Rejected;
+    };
+    let rivals: Vec<TrackMatch> = distinct
+        .take_while(|candidate| best.score - candidate.score <= AMBIGUITY_MARGIN)
+        .take(MAX_CHOICES - 1)
+        .collect();
+    if rivals.is_empty() {
+        return MatchOutcome::Accepted(best);
+    }
+    let mut choices = vec![best];
+    choices.extend(rivals);
+    MatchOutcome::Ambiguous(choices)
+}
+
+/// Weighs title and artist similarity, duration distance and album match. Parts the
+/// query knows nothing about are left out and the remaining weights rescaled.
+pub fn score(query: &TrackQuery, candidate: &TrackMatch) -> f64 {
+    let mut parts = Vec::with_capacity(4);
+    match &query.title {
+        Some(title) => parts.push((TITLE_WEIGHT, similarity(title, &candidate.title))),
+        None if !query.keyword.is_empty() => {
+            parts.push((TITLE_WEIGHT, keyword_similarity(&query.keyword, candidate)))
+        }
+        None => {}
+    }
+    if let Some(artist) = &query.artist {
+        parts.push((ARTIST_WEIGHT, similarity(artist, &candidate.artist)));
+    }
+    if let (Some(duration), Some(found)) = (query.duration, candidate.duration) {
+        parts.push((DURATION_WEIGHT, duration_similarity(duration, found)));
+    }
+    if let Some(album) = &query.album {
+        parts.push((ALBUM_WEIGHT, similarity(album, &candidate.album)));
+    }
+
+    let total: f64 = parts.iter().map(|(weight, _)| weight).sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+    parts
+        .iter()
+        .map(|(weight, value)| weight * value)
+        .sum::<f64>()
+        / total
+}
+
+/// A bare keyword, usually the file name, may carry the artist on either side of
+/// the title.
+fn keyword_similarity(keyword: &str, candidate: &TrackMatch) -> f64 {
+    [
+        candidate.title.clone(),
+        format!("{} {}", candidate.artist, candidate.title),
+        format!("{} {}", candidate.title, candidate.artist),
+    ]
+    .iter()
+    .map(|value| similarity(keyword, value))
+    .fold(0.0, f64::max)
+}
+
+fn duration_similarity(a: f64, b: f64) -> f64 {
+    let distance = (a - b).abs();
+    if distance <= DURATION_TOLERANCE {
+        1.0
+    } else {
+        (1.0 - (distance - DURATION_TOLERANCE) / (DURATION_LIMIT - DURATION_TOLERANCE)).max(0.0)
+    }
+}
+
+/// Edit-distance similarity of the normalized strings, from 0 to 1.
+fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = normalize(a).chars().collect();
+    let b: Vec<char> = normalize(b).chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 0.0;
+    }
+    1.0 - levenshtein(&a, &b) as f64 / longest as f64
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Lowercases and drops punctuation so "AC/DC" and "ac dc" compare equal.
+fn normalize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
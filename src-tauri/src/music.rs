@@ -1,5 +1,8 @@
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 
+use crate::{path_tags, provider, scan_rules, tag_editor};
+
 #[derive(Clone, Debug, Serialize)]
 pub struct MusicError {
     pub id: Option<String>,
@@ -32,6 +35,12 @@ pub struct PlayState {
     pub path: Option<String>,
     pub progress: Option<String>,
     pub left_duration: Option<String>,
+    /// Seconds into the track; `progress` holds the same for display.
+    #[serde(default)]
+    pub position: Option<f64>,
+    /// Index of the chapter being played, for tracks with chapters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chapter: Option<usize>,
 }
 
 impl PlayState {
@@ -41,6 +50,7 @@ impl PlayState {
         path: String,
         progress: String,
         left_duration: String,
+        position: f64,
     ) -> Self {
         Self {
             id: Some(id),
@@ -48,10 +58,47 @@ impl PlayState {
             path: Some(path),
             progress: Some(progress),
             left_duration: Some(left_duration),
+            position: Some(position),
+            chapter: None,
+        }
+    }
+
+    pub fn with_chapter(mut self, chapter: Option<usize>) -> Self {
+        self.chapter = chapter;
+        self
+    }
+}
+
+/// Where a long track was left off.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResumePoint {
+    /// Seconds into the track.
+    pub position: f64,
+    /// Set once the track played to the end or was marked as finished.
+    pub finished: bool,
+    pub updated_at: i64,
+}
+
+impl ResumePoint {
+    pub fn new(position: f64, finished: bool) -> Self {
+        Self {
+            position,
+            finished,
+            updated_at: Utc::now().timestamp(),
         }
     }
 }
 
+/// The resume point of a playlist entry.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackResume {
+    pub id: String,
+    pub position: f64,
+    pub finished: bool,
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct MusicMeta {
     pub title: String,
@@ -69,17 +116,6 @@ impl MusicMeta {
     }
 }
 
-#[derive(Clone, Debug, Serialize)]
-pub struct MusicImage {
-    pub image: String,
-}
-
-impl MusicImage {
-    pub fn new(image: String) -> Self {
-        Self { image }
-    }
-}
-
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MusicFile {
@@ -87,8 +123,24 @@ pub struct MusicFile {
     pub name: String,
     pub path: String,
     pub image_path: Option<String>,
+    /// Small version of the artwork for list views.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail_path: Option<String>,
     pub artist: Option<String>,
     pub album: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unsupported: Option<String>,
+    /// Set for tracks of a cue sheet, which play part of `path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segment: Option<TrackSegment>,
+    /// Stars from 0 to 5 in half steps; mirrors the stored ratings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<f32>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub favourite: bool,
+    /// Unix time the track joined the library.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added_at: Option<i64>,
 }
 
 impl MusicFile {
@@ -105,8 +157,271 @@ impl MusicFile {
             name,
             path,
             image_path,
+            thumbnail_path: None,
             artist,
             album,
+            unsupported: None,
+            segment: None,
+            rating: None,
+            favourite: false,
+            added_at: None,
+        }
+    }
+
+    pub fn with_unsupported(mut self, reason: Option<String>) -> Self {
+        self.unsupported = reason;
+        self
+    }
+
+    pub fn with_added_at(mut self, added_at: Option<i64>) -> Self {
+        self.added_at = added_at;
+        self
+    }
+
+    pub fn with_segment(mut self, segment: Option<TrackSegment>) -> Self {
+        self.segment = segment;
+        self
+    }
+
+    pub fn track_rating(&self) -> TrackRating {
+        TrackRating {
+            rating: self.rating,
+            favourite: self.favourite,
+        }
+    }
+
+    pub fn set_track_rating(&mut self, rating: TrackRating) {
+        self.rating = rating.rating;
+        self.favourite = rating.favourite;
+    }
+
+    /// Names the track in the resume points; cue sheet tracks share their file.
+    pub fn resume_key(&self) -> String {
+        match self.segment {
+            Some(segment) => format!("{}#{}", self.path, segment.start),
+            None => self.path.clone(),
+        }
+    }
+}
+
+/// Star rating and favourite flag of a track, stored by the identity of its file.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackRating {
+    pub rating: Option<f32>,
+    pub favourite: bool,
+}
+
+impl TrackRating {
+    pub fn is_unset(&self) -> bool {
+        self.rating.is_none() && !self.favourite
+    }
+}
+
+/// What a playlist can be sorted by.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SortField {
+    Title,
+    Artist,
+    Album,
+    Path,
+    Rating,
+    Favourite,
+}
+
+/// The part of a file a virtual track plays, in seconds. Without an end it runs to
+/// the end of the file.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TrackSegment {
+    pub start: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<f64>,
+}
+
+impl TrackSegment {
+    pub fn new(start: f64, end: Option<f64>) -> Self {
+        Self { start, end }
+    }
+}
+
+/// A named list of tracks. The current playlist is the "now playing" list that
+/// playback walks through.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Playlist {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub tracks: Vec<MusicFile>,
+    /// Track played last while this was the current playlist.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_track_id: Option<String>,
+    /// Where that track stopped, in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_position: Option<f64>,
+    /// Set for smart playlists, whose tracks are the library tracks matching these
+    /// rules and are refreshed as the library changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smart: Option<SmartRules>,
+}
+
+impl Playlist {
+    pub fn new(id: String, name: String, tracks: Vec<MusicFile>) -> Self {
+        Self {
+            id,
+            name,
+            tracks,
+            last_track_id: None,
+            last_position: None,
+            smart: None,
+        }
+    }
+
+    pub fn with_smart(mut self, smart: Option<SmartRules>) -> Self {
+        self.smart = smart;
+        self
+    }
+}
+
+/// What a smart playlist holds, in which order and how much of it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartRules {
+    pub group: RuleGroup,
+    #[serde(default)]
+    pub sort: Option<SmartSort>,
+    #[serde(default)]
+    pub limit: Option<SmartLimit>,
+}
+
+/// Rules joined with AND, or with OR when `any` is set. An empty group matches
+/// every track.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleGroup {
+    #[serde(default)]
+    pub any: bool,
+    pub rules: Vec<RuleNode>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RuleNode {
+    Rule(Rule),
+    Group(RuleGroup),
+}
+
+/// Compares one field of a track with `value`. Numbers are written as text too:
+/// durations in seconds, ratings in stars, dates as `YYYY-MM-DD` or Unix time, and
+/// a number of days for `IN_LAST_DAYS`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rule {
+    pub field: RuleField,
+    pub operator: RuleOperator,
+    #[serde(default)]
+    pub value: String,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RuleField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Year,
+    Comment,
+    PlayCount,
+    SkipCount,
+    Rating,
+    Favourite,
+    LastPlayed,
+    DateAdded,
+    Duration,
+    Codec,
+    Path,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RuleOperator {
+    Is,
+    IsNot,
+    Contains,
+    NotContains,
+    StartsWith,
+    EndsWith,
+    GreaterThan,
+    LessThan,
+    InLastDays,
+    NotInLastDays,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartSort {
+    pub field: RuleField,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+/// Caps a smart playlist at a number of tracks, minutes or megabytes, taking
+/// tracks in sort order.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartLimit {
+    pub kind: LimitKind,
+    pub value: f64,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum LimitKind {
+    Count,
+    Minutes,
+    Megabytes,
+}
+
+/// What smart playlist rules know about a file beyond its library entry, kept
+/// until the file changes.
+#[derive(Clone, Debug, Default)]
+pub struct TrackFacts {
+    /// Unix time the file was last modified.
+    pub modified: i64,
+    pub size: u64,
+    /// Set once `duration` and `codec` were read.
+    pub probed: bool,
+    pub duration: Option<f64>,
+    pub codec: Option<String>,
+    pub tags: Option<TrackTags>,
+}
+
+/// A playlist without its tracks, for listing.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistSummary {
+    pub id: String,
+    pub name: String,
+    pub track_count: usize,
+    pub last_track_id: Option<String>,
+    pub last_position: Option<f64>,
+    pub current: bool,
+    pub smart: bool,
+}
+
+impl PlaylistSummary {
+    pub fn new(playlist: &Playlist, current: bool) -> Self {
+        Self {
+            id: playlist.id.clone(),
+            name: playlist.name.clone(),
+            track_count: playlist.tracks.len(),
+            last_track_id: playlist.last_track_id.clone(),
+            last_position: playlist.last_position,
+            current,
+            smart: playlist.smart.is_some(),
         }
     }
 }
@@ -115,6 +430,91 @@ impl MusicFile {
 pub struct MusicSetting {
     pub volume: f32,
     pub sequence_type: u32,
+    #[serde(default = "default_acoustid_url")]
+    pub acoustid_url: String,
+    #[serde(default)]
+    pub acoustid_key: Option<String>,
+    #[serde(default = "default_strip_patterns")]
+    pub strip_patterns: Vec<String>,
+    #[serde(default = "default_path_patterns")]
+    pub path_patterns: Vec<String>,
+    #[serde(default = "default_providers")]
+    pub providers: Vec<ProviderSetting>,
+    /// Largest size the cache may grow to before the least recently used entries are
+    /// evicted; 0 disables the limit.
+    #[serde(default = "default_cache_limit_mb")]
+    pub cache_limit_mb: u64,
+    #[serde(default)]
+    pub network_policy: NetworkPolicy,
+    /// Tracks at least this long remember where they were left off; 0 turns resume
+    /// points off.
+    #[serde(default = "default_resume_threshold_mins")]
+    pub resume_threshold_mins: u32,
+    /// Also store ratings in the files, as ID3 POPM frames or Vorbis RATING comments.
+    #[serde(default)]
+    pub write_rating_tags: bool,
+    /// Random playback favours highly rated and favourite tracks.
+    #[serde(default)]
+    pub weighted_shuffle: bool,
+}
+
+/// When metadata providers may be contacted.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum NetworkPolicy {
+    /// Look tracks up as soon as they are added or played.
+    #[default]
+    Always,
+    /// Only use local tags and artwork until the user runs the queued lookups.
+    OnRequest,
+    /// Never contact providers.
+    Never,
+}
+
+impl NetworkPolicy {
+    /// Whether a lookup may go online; `requested` marks lookups the user started.
+    pub fn allows(self, requested: bool) -> bool {
+        match self {
+            Self::Always => true,
+            Self::OnRequest => requested,
+            Self::Never => false,
+        }
+    }
+}
+
+fn default_resume_threshold_mins() -> u32 {
+    20
+}
+
+fn default_cache_limit_mb() -> u64 {
+    512
+}
+
+fn default_acoustid_url() -> String {
+    "https://api.acoustid.org/v2/lookup".to_string()
+}
+
+fn default_strip_patterns() -> Vec<String> {
+    tag_editor::DEFAULT_STRIP_PATTERNS
+        .iter()
+        .map(|pattern| pattern.to_string())
+        .collect()
+}
+
+fn default_providers() -> Vec<ProviderSetting> {
+    vec![
+        ProviderSetting::new(ProviderKind::Itunes, true),
+        ProviderSetting::new(ProviderKind::MusicBrainz, false),
+        ProviderSetting::new(ProviderKind::CoverArtArchive, true),
+        ProviderSetting::new(ProviderKind::Lrclib, true),
+    ]
+}
+
+fn default_path_patterns() -> Vec<String> {
+    path_tags::DEFAULT_PATH_PATTERNS
+        .iter()
+        .map(|pattern| pattern.to_string())
+        .collect()
 }
 
 impl Default for MusicSetting {
@@ -122,6 +522,16 @@ impl Default for MusicSetting {
         Self {
             volume: 1.0,
             sequence_type: 1,
+            acoustid_url: default_acoustid_url(),
+            acoustid_key: None,
+            strip_patterns: default_strip_patterns(),
+            path_patterns: default_path_patterns(),
+            providers: default_providers(),
+            cache_limit_mb: default_cache_limit_mb(),
+            network_policy: NetworkPolicy::default(),
+            resume_threshold_mins: default_resume_threshold_mins(),
+            write_rating_tags: false,
+            weighted_shuffle: false,
         }
     }
 }
@@ -130,40 +540,596 @@ impl MusicSetting {
     pub fn with_volume(&self, volume: f32) -> Self {
         Self {
             volume,
-            sequence_type: self.sequence_type,
+            ..self.clone()
         }
     }
     pub fn with_sequence_type(&self, sequence_type: u32) -> Self {
         Self {
-            volume: self.volume,
             sequence_type,
+            ..self.clone()
+        }
+    }
+    pub fn with_acoustid(
+        &self,
+        acoustid_url: Option<String>,
+        acoustid_key: Option<String>,
+    ) -> Self {
+        Self {
+            acoustid_url: acoustid_url.unwrap_or_else(default_acoustid_url),
+            acoustid_key: acoustid_key.filter(|key| !key.trim().is_empty()),
+            ..self.clone()
+        }
+    }
+    pub fn with_strip_patterns(&self, strip_patterns: Vec<String>) -> Self {
+        Self {
+            strip_patterns: strip_patterns
+                .into_iter()
+                .filter(|pattern| !pattern.is_empty())
+                .collect(),
+            ..self.clone()
+        }
+    }
+    pub fn with_path_patterns(&self, path_patterns: Vec<String>) -> Self {
+        Self {
+            path_patterns,
+            ..self.clone()
+        }
+    }
+    pub fn with_providers(&self, providers: Vec<ProviderSetting>) -> Self {
+        Self {
+            providers,
+            ..self.clone()
+        }
+    }
+    pub fn with_cache_limit_mb(&self, cache_limit_mb: u64) -> Self {
+        Self {
+            cache_limit_mb,
+            ..self.clone()
+        }
+    }
+    pub fn with_network_policy(&self, network_policy: NetworkPolicy) -> Self {
+        Self {
+            network_policy,
+            ..self.clone()
+        }
+    }
+    pub fn with_resume_threshold_mins(&self, resume_threshold_mins: u32) -> Self {
+        Self {
+            resume_threshold_mins,
+            ..self.clone()
+        }
+    }
+    pub fn with_write_rating_tags(&self, write_rating_tags: bool) -> Self {
+        Self {
+            write_rating_tags,
+            ..self.clone()
+        }
+    }
+    pub fn with_weighted_shuffle(&self, weighted_shuffle: bool) -> Self {
+        Self {
+            weighted_shuffle,
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ProviderKind {
+    Itunes,
+    MusicBrainz,
+    CoverArtArchive,
+    Lrclib,
+}
+
+/// One online metadata provider. Providers are queried in the order they are listed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderSetting {
+    pub kind: ProviderKind,
+    pub enabled: bool,
+    pub base_url: String,
+}
+
+impl ProviderSetting {
+    pub fn new(kind: ProviderKind, enabled: bool) -> Self {
+        Self {
+            kind,
+            enabled,
+            base_url: provider::default_base_url(kind).to_string(),
         }
     }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MusicMap {
+    /// The `MusicFile::resume_key` of the tracks the metadata belongs to.
+    #[serde(default)]
+    pub key: String,
     pub name: String,
     pub title: String,
     pub artist: String,
     pub album: String,
     pub image_path: String,
+    #[serde(default)]
+    pub thumbnail_path: Option<String>,
 }
 
 impl MusicMap {
     pub fn new(
+        key: String,
         name: String,
         title: String,
         artist: String,
         album: String,
         image_path: String,
+        thumbnail_path: Option<String>,
     ) -> Self {
         Self {
+            key,
             name,
             title,
             artist,
             album,
             image_path,
+            thumbnail_path,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryFolder {
+    pub path: String,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default = "default_exclude")]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub min_size: u64,
+    #[serde(default)]
+    pub min_duration: f64,
+}
+
+fn default_exclude() -> Vec<String> {
+    scan_rules::DEFAULT_EXCLUDE
+        .iter()
+        .map(|pattern| pattern.to_string())
+        .collect()
+}
+
+impl LibraryFolder {
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            include: Vec::new(),
+            exclude: default_exclude(),
+            min_size: 0,
+            min_duration: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Library {
+    pub folders: Vec<LibraryFolder>,
+    pub tracks: Vec<MusicFile>,
+}
+
+impl Library {
+    pub fn new(folders: Vec<LibraryFolder>, tracks: Vec<MusicFile>) -> Self {
+        Self { folders, tracks }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryChange {
+    pub added: Vec<MusicFile>,
+    pub updated: Vec<MusicFile>,
+    pub removed: Vec<String>,
+}
+
+impl LibraryChange {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedEntry {
+    pub path: String,
+    pub reason: String,
+}
+
+impl SkippedEntry {
+    pub fn new(path: String, reason: String) -> Self {
+        Self { path, reason }
+    }
+}
+
+/// Result of importing a playlist file: the now playing list with the imported
+/// tracks appended, and the entries that did not resolve to a file.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistImport {
+    pub playlist: Vec<MusicFile>,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+impl PlaylistImport {
+    pub fn new(playlist: Vec<MusicFile>, skipped: Vec<SkippedEntry>) -> Self {
+        Self { playlist, skipped }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanReport {
+    pub files: Vec<String>,
+    pub skipped: Vec<SkippedEntry>,
+    pub unsupported: Vec<SkippedEntry>,
+    pub cancelled: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanProgress {
+    pub scanned_dirs: usize,
+    pub found_files: usize,
+    pub skipped: usize,
+    pub current: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DuplicateKind {
+    File,
+    Audio,
+    Acoustic,
+    Metadata,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateTrack {
+    pub path: String,
+    pub name: String,
+    pub codec: String,
+    pub lossless: bool,
+    pub bitrate: Option<u32>,
+    pub sample_rate: u32,
+    pub bits_per_sample: u32,
+    pub duration: f64,
+    pub size: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    pub keeper: String,
+    pub tracks: Vec<DuplicateTrack>,
+}
+
+#[derive(Clone, Debug, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateReport {
+    pub groups: Vec<DuplicateGroup>,
+}
+
+/// What looking a file up needs from its content, with the file state it was read
+/// from, so unchanged files are not probed and hashed again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredFileInfo {
+    pub identity: String,
+    /// The cached copy of the file's own artwork, embedded or sidecar.
+    pub artwork: Option<String>,
+    pub size: u64,
+    pub modified: u64,
+}
+
+impl StoredFileInfo {
+    pub fn new(identity: String, artwork: Option<String>, size: u64, modified: u64) -> Self {
+        Self {
+            identity,
+            artwork,
+            size,
+            modified,
         }
     }
 }
+
+/// A compressed fingerprint together with the file state it was computed from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredFingerprint {
+    pub fingerprint: String,
+    pub duration: f64,
+    pub size: u64,
+    pub modified: u64,
+}
+
+impl StoredFingerprint {
+    pub fn new(fingerprint: String, duration: f64, size: u64, modified: u64) -> Self {
+        Self {
+            fingerprint,
+            duration,
+            size,
+            modified,
+        }
+    }
+}
+
+/// The editable tags of one file as shown in the tag editor.
+#[derive(Clone, Debug, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackTags {
+    pub path: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<String>,
+    pub track: Option<u32>,
+    pub disc: Option<u32>,
+    pub comment: Option<String>,
+    pub has_cover: bool,
+    pub writable: bool,
+}
+
+/// Changes applied to every selected file. Fields left out are not touched and
+/// empty strings remove the tag; `cover` is the path of an image to embed as the
+/// front cover.
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TagEdit {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<String>,
+    pub track: Option<String>,
+    pub disc: Option<String>,
+    pub comment: Option<String>,
+    pub cover: Option<String>,
+    pub remove_cover: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TagEditReport {
+    pub updated: Vec<String>,
+    pub failed: Vec<SkippedEntry>,
+}
+
+/// Tags parsed from a file's path, or `None` if no pattern fit it.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathTags {
+    pub path: String,
+    pub tags: Option<TagEdit>,
+}
+
+impl PathTags {
+    pub fn new(path: String, tags: Option<TagEdit>) -> Self {
+        Self { path, tags }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileMove {
+    pub from: String,
+    pub to: String,
+}
+
+impl FileMove {
+    pub fn new(from: String, to: String) -> Self {
+        Self { from, to }
+    }
+}
+
+/// Planned or performed file moves, along with the files that were left alone.
+#[derive(Clone, Debug, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameReport {
+    pub moves: Vec<FileMove>,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+/// A recording found by a provider.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackMatch {
+    pub provider: Option<ProviderKind>,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    /// Length in seconds, when the provider knows it.
+    pub duration: Option<f64>,
+    pub artwork_url: Option<String>,
+    /// MusicBrainz release id, used to find artwork on the Cover Art Archive.
+    pub release_id: Option<String>,
+    /// How well the recording fits the file, from 0 to 1.
+    #[serde(default)]
+    pub score: f64,
+}
+
+/// A file whose lookup returned several equally plausible recordings, left for the
+/// user to pick from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchChoice {
+    pub name: String,
+    pub path: String,
+    pub candidates: Vec<TrackMatch>,
+}
+
+impl MatchChoice {
+    pub fn new(name: String, path: String, candidates: Vec<TrackMatch>) -> Self {
+        Self {
+            name,
+            path,
+            candidates,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum LyricsSource {
+    /// An `.lrc` file next to the track.
+    Sidecar,
+    /// USLT/SYLT frames, or a LYRICS comment.
+    Embedded,
+    Online,
+}
+
+/// Lyrics of one track. Synchronized lyrics have a time on every line, plain ones
+/// on none.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lyrics {
+    pub path: String,
+    pub source: LyricsSource,
+    pub synced: bool,
+    pub lines: Vec<LyricLine>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricLine {
+    /// Start of the line in seconds.
+    pub time: Option<f64>,
+    pub text: String,
+    /// Word timings from enhanced LRC, when present.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<LyricWord>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricWord {
+    pub time: f64,
+    pub text: String,
+}
+
+/// The line, and word if timed, being sung at the current playback position.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricsPosition {
+    pub path: String,
+    pub line: usize,
+    pub word: Option<usize>,
+}
+
+/// A chapter of an audiobook, podcast or video soundtrack, in seconds from the
+/// start of the file.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Chapter {
+    pub title: String,
+    pub start: f64,
+    pub end: Option<f64>,
+}
+
+impl Chapter {
+    pub fn new(title: String, start: f64, end: Option<f64>) -> Self {
+        Self { title, start, end }
+    }
+}
+
+/// One audio track of a file with several, such as a commentary or dubbed track.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioStream {
+    /// Position among all tracks of the file, the number `start_play` takes.
+    pub index: usize,
+    pub codec: String,
+    pub language: Option<String>,
+    pub channels: Option<usize>,
+    pub sample_rate: Option<u32>,
+    pub selected: bool,
+}
+
+/// One play of a track, from when it started until it finished or was skipped.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayEvent {
+    /// `MusicFile::resume_key` of the track.
+    pub track: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Unix time in seconds.
+    pub started_at: i64,
+    /// Seconds actually heard, not counting seeks.
+    pub listened: f64,
+    pub completed: bool,
+}
+
+impl PlayEvent {
+    pub fn start(music_file: &MusicFile) -> Self {
+        Self {
+            track: music_file.resume_key(),
+            title: music_file.name.clone(),
+            artist: music_file.artist.clone(),
+            album: music_file.album.clone(),
+            started_at: Utc::now().timestamp(),
+            listened: 0.0,
+            completed: false,
+        }
+    }
+}
+
+/// Play history of one track.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackStats {
+    pub track: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub play_count: u32,
+    pub skip_count: u32,
+    /// Unix time of the last play that was listened to at all, finished or not.
+    pub last_played: Option<i64>,
+    pub listened: f64,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum StatsPeriod {
+    Week,
+    Month,
+    Year,
+}
+
+/// A track, artist or album ranked by how often it was played.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsEntry {
+    pub name: String,
+    /// The artist of a track or album; unset for artists.
+    pub artist: Option<String>,
+    pub plays: u32,
+    pub listened: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListeningStats {
+    pub period: StatsPeriod,
+    /// Unix time the period starts at.
+    pub since: i64,
+    pub total_listened: f64,
+    pub top_tracks: Vec<StatsEntry>,
+    pub top_artists: Vec<StatsEntry>,
+    pub top_albums: Vec<StatsEntry>,
+}
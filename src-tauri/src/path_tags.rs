@@ -0,0 +1,477 @@
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use log::{debug, warn};
+use rayon::prelude::*;
+
+use crate::{
+    file_reader,
+    music::{FileMove, RenameReport, SkippedEntry, TagEdit, TrackTags},
+    tag_editor,
+};
+
+/// Patterns tried in order when a file has no usable tags.
+pub const DEFAULT_PATH_PATTERNS: [&str; 1] = ["%artist% - %title%"];
+
+/// Characters that are not allowed in file names on at least one supported platform.
+const RESERVED_CHARS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Artist,
+    AlbumArtist,
+    Album,
+    Title,
+    Track,
+    Disc,
+    Year,
+    Genre,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "artist" => Some(Self::Artist),
+            "albumartist" => Some(Self::AlbumArtist),
+            "album" => Some(Self::Album),
+            "title" => Some(Self::Title),
+            "track" => Some(Self::Track),
+            "disc" => Some(Self::Disc),
+            "year" => Some(Self::Year),
+            "genre" => Some(Self::Genre),
+            _ => None,
+        }
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Self::Track | Self::Disc | Self::Year)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Token {
+    Literal(String),
+    Field(Field),
+}
+
+/// A path pattern such as `%artist%/%album%/%track% - %title%`.
+///
+/// Each `/`-separated part describes one path component, matched against the end of
+/// a path with the file extension removed. Fields are written as `%name%`; `%track%`,
+/// `%disc%` and `%year%` only match digits.
+#[derive(Clone, Debug)]
+pub struct PathPattern {
+    components: Vec<Vec<Token>>,
+}
+
+impl PathPattern {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let components = pattern
+            .trim()
+            .trim_matches('/')
+            .split('/')
+            .map(parse_component)
+            .collect::<Result<Vec<_>, _>>()?;
+        if components.iter().all(|tokens| tokens.is_empty()) {
+            return Err("path pattern is empty".to_string());
+        }
+        Ok(Self { components })
+    }
+
+    /// Parses the tags encoded in `path`, or `None` if the path does not fit.
+    pub fn parse_path(&self, path: &Path) -> Option<TagEdit> {
+        let mut names: Vec<String> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => name.to_str().map(str::to_string),
+                _ => None,
+            })
+            .collect();
+        let file_name = names.pop()?;
+        let stem = Path::new(&file_name)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(&file_name)
+            .to_string();
+        names.push(stem);
+        if names.len() < self.components.len() {
+            return None;
+        }
+
+        let mut values = Vec::new();
+        let names = &names[names.len() - self.components.len()..];
+        for (tokens, name) in self.components.iter().zip(names) {
+            if !match_tokens(tokens, name, &mut values) {
+                return None;
+            }
+        }
+
+        let mut edit = TagEdit::default();
+        for (field, value) in values {
+            let value = Some(value.trim().to_string()).filter(|value| !value.is_empty());
+            let slot = match field {
+                Field::Artist => &mut edit.artist,
+                Field::AlbumArtist => &mut edit.album_artist,
+                Field::Album => &mut edit.album,
+                Field::Title => &mut edit.title,
+                Field::Track => &mut edit.track,
+                Field::Disc => &mut edit.disc,
+                Field::Year => &mut edit.year,
+                Field::Genre => &mut edit.genre,
+            };
+            if slot.is_none() {
+                *slot = value;
+            }
+        }
+        Some(edit)
+    }
+
+    /// Builds the relative path, without extension, that `tags` describe. Missing
+    /// fields fall back to placeholders so every file still gets a path.
+    pub fn format(&self, tags: &TrackTags, fallback_title: &str) -> PathBuf {
+        self.components
+            .iter()
+            .map(|tokens| format_component(tokens, tags, fallback_title))
+            .collect()
+    }
+}
+
+/// Compiles the configured patterns, skipping the ones that do not parse.
+pub fn parse_patterns(patterns: &[String]) -> Vec<PathPattern> {
+    patterns
+        .iter()
+        .filter_map(|pattern| {
+            PathPattern::parse(pattern)
+                .map_err(|err| warn!("ignoring path pattern {}: {}", pattern, err))
+                .ok()
+        })
+        .collect()
+}
+
+fn parse_component(component: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = component;
+    while let Some(start) = rest.find('%') {
+        if start > 0 {
+            tokens.push(Token::Literal(rest[..start].to_string()));
+        }
+        let after = &rest[start + 1..];
+        let end = after
+            .find('%')
+            .ok_or_else(|| format!("unterminated field in pattern: {}", component))?;
+        let name = &after[..end];
+        let field =
+            Field::from_name(name).ok_or_else(|| format!("unknown pattern field: %{}%", name))?;
+        tokens.push(Token::Field(field));
+        rest = &after[end + 1..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Literal(rest.to_string()));
+    }
+    Ok(tokens)
+}
+
+/// Matches `text` against `tokens`, giving each field the shortest value that lets the
+/// rest of the pattern match.
+fn match_tokens(tokens: &[Token], text: &str, values: &mut Vec<(Field, String)>) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return text.is_empty();
+    };
+    match token {
+        Token::Literal(literal) => text
+            .strip_prefix(literal.as_str())
+            .is_some_and(|text| match_tokens(rest, text, values)),
+        Token::Field(field) => {
+            for (end, ch) in text.char_indices() {
+                if field.is_numeric() && !ch.is_ascii_digit() {
+                    break;
+                }
+                let end = end + ch.len_utf8();
+                if match_tokens(rest, &text[end..], values) {
+                    values.push((*field, text[..end].to_string()));
+                    return true;
+                }
+            }
+            false
+        }
+    }
+}
+
+fn format_component(tokens: &[Token], tags: &TrackTags, fallback_title: &str) -> String {
+    let name: String = tokens
+        .iter()
+        .map(|token| match token {
+            Token::Literal(literal) => literal.clone(),
+            Token::Field(field) => sanitize(&field_value(*field, tags, fallback_title)),
+        })
+        .collect();
+    let name = tidy(&name);
+    if name.is_empty() {
+        "Unknown".to_string()
+    } else {
+        name
+    }
+}
+
+fn field_value(field: Field, tags: &TrackTags, fallback_title: &str) -> String {
+    let text = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    match field {
+        Field::Artist => text(&tags.artist).unwrap_or_else(|| "Unknown Artist".to_string()),
+        Field::AlbumArtist => text(&tags.album_artist)
+            .or_else(|| text(&tags.artist))
+            .unwrap_or_else(|| "Unknown Artist".to_string()),
+        Field::Album => text(&tags.album).unwrap_or_else(|| "Unknown Album".to_string()),
+        Field::Title => text(&tags.title).unwrap_or_else(|| fallback_title.to_string()),
+        Field::Track => tags
+            .track
+            .map(|track| format!("{:02}", track))
+            .unwrap_or_default(),
+        Field::Disc => tags.disc.map(|disc| disc.to_string()).unwrap_or_default(),
+        Field::Year => text(&tags.year)
+            .map(|year| year.chars().take(4).collect())
+            .unwrap_or_default(),
+        Field::Genre => text(&tags.genre).unwrap_or_default(),
+    }
+}
+
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|ch| {
+            if RESERVED_CHARS.contains(&ch) || ch.is_control() {
+                '_'
+            } else {
+                ch
+            }
+        })
+        .collect()
+}
+
+/// Drops separators left dangling by empty fields, as well as leading dots that
+/// would hide the file and trailing dots that Windows refuses.
+fn tidy(name: &str) -> String {
+    name.trim_start_matches([' ', '-', '_', '.'])
+        .trim_end_matches([' ', '-', '_', '.'])
+        .to_string()
+}
+
+/// Parses the tags of each path with the first pattern that fits.
+pub fn parse_paths(paths: &[String], patterns: &[PathPattern]) -> Vec<(String, Option<TagEdit>)> {
+    paths
+        .iter()
+        .map(|path| {
+            let tags = patterns
+                .iter()
+                .find_map(|pattern| pattern.parse_path(Path::new(path)));
+            (path.clone(), tags)
+        })
+        .collect()
+}
+
+/// Works out where each file would be moved to under `root`, without touching the
+/// filesystem. Files already in place are left out; files whose target exists or is
+/// claimed by another file are skipped.
+pub fn plan_renames(paths: Vec<String>, root: &Path, pattern: &PathPattern) -> RenameReport {
+    let targets: Vec<(String, Result<PathBuf, String>)> = paths
+        .into_par_iter()
+        .map(|path| {
+            let target = tag_editor::read_tags(&path).map(|tags| {
+                let source = &file_reader::local_path(&path);
+                let stem = source
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .unwrap_or("Unknown");
+                let mut target = root.join(pattern.format(&tags, stem));
+                if let Some(extension) = source.extension() {
+                    let mut file_name = target.file_name().unwrap_or_default().to_os_string();
+                    file_name.push(".");
+                    file_name.push(extension);
+                    target.set_file_name(file_name);
+                }
+                target
+            });
+            (path, target)
+        })
+        .collect();
+
+    let mut report = RenameReport::default();
+    let mut claimed = HashSet::new();
+    for (path, target) in targets {
+        let target = match target {
+            Ok(target) => target,
+            Err(reason) => {
+                report.skipped.push(SkippedEntry::new(path, reason));
+                continue;
+            }
+        };
+        if file_reader::local_path(&path) == target {
+            continue;
+        }
+        let to = file_reader::path_string(&target);
+        if target.exists() {
+            report
+                .skipped
+                .push(SkippedEntry::new(path, format!("{} already exists", to)));
+        } else if !claimed.insert(target) {
+            report.skipped.push(SkippedEntry::new(
+                path,
+                format!("{} is the target of another file", to),
+            ));
+        } else {
+            report.moves.push(FileMove::new(path, to));
+        }
+    }
+    report
+}
+
+/// Moves files as planned, creating target folders and removing source folders that
+/// end up empty, up to but not including the library folder in `roots` holding them.
+/// Moves whose target appeared since planning are skipped.
+pub fn apply_renames(moves: Vec<FileMove>, roots: &[PathBuf]) -> RenameReport {
+    let mut report = RenameReport::default();
+    for file_move in moves {
+        let from = &file_reader::local_path(&file_move.from);
+        let to = &file_reader::local_path(&file_move.to);
+        if to.exists() {
+            let reason = format!("{} already exists", file_move.to);
+            report
+                .skipped
+                .push(SkippedEntry::new(file_move.from, reason));
+            continue;
+        }
+        match move_file(from, to) {
+            Ok(()) => {
+                if let Some(root) = roots
+                    .iter()
+                    .filter(|root| from.starts_with(root))
+                    .max_by_key(|root| root.components().count())
+                {
+                    remove_empty_parents(from, root);
+                }
+                report.moves.push(file_move);
+            }
+            Err(err) => {
+                let reason = format!("failed to move to {}: {}", file_move.to, err);
+                report
+                    .skipped
+                    .push(SkippedEntry::new(file_move.from, reason));
+            }
+        }
+    }
+    report
+}
+
+/// Moves files back to where `moves` took them from, newest first.
+pub fn undo_renames(moves: Vec<FileMove>, roots: &[PathBuf]) -> RenameReport {
+    let reversed = moves
+        .into_iter()
+        .rev()
+        .map(|file_move| FileMove::new(file_move.to, file_move.from))
+        .collect();
+    apply_renames(reversed, roots)
+}
+
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    // Renaming fails across filesystems; fall back to copying.
+    fs::copy(from, to)?;
+    if let Err(err) = fs::remove_file(from) {
+        let _ = fs::remove_file(to);
+        return Err(err);
+    }
+    Ok(())
+}
+
+fn remove_empty_parents(path: &Path, root: &Path) {
+    for dir in path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| *dir != root && dir.starts_with(root))
+    {
+        let is_empty = fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_none());
+        if !is_empty || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        debug!("removed empty folder {}", dir.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_malformed_patterns() {
+        assert!(PathPattern::parse("%artist% - %title%").is_ok());
+        assert!(PathPattern::parse("%artist - %title%").is_err());
+        assert!(PathPattern::parse("%composer% - %title%").is_err());
+        assert!(PathPattern::parse(" / ").is_err());
+    }
+
+    #[test]
+    fn parses_fields_from_the_end_of_the_path() {
+        let pattern = PathPattern::parse("%artist%/%album%/%track% - %title%").unwrap();
+        let edit = pattern
+            .parse_path(Path::new("/music/Band/Record/03 - Song Name.flac"))
+            .unwrap();
+        assert_eq!(edit.artist.as_deref(), Some("Band"));
+        assert_eq!(edit.album.as_deref(), Some("Record"));
+        assert_eq!(edit.track.as_deref(), Some("03"));
+        assert_eq!(edit.title.as_deref(), Some("Song Name"));
+        assert!(edit.year.is_none());
+    }
+
+    #[test]
+    fn fields_take_the_shortest_match() {
+        let pattern = PathPattern::parse("%artist% - %title%").unwrap();
+        let edit = pattern.parse_path(Path::new("A - B - C.mp3")).unwrap();
+        assert_eq!(edit.artist.as_deref(), Some("A"));
+        assert_eq!(edit.title.as_deref(), Some("B - C"));
+    }
+
+    #[test]
+    fn rejects_paths_that_do_not_fit() {
+        let pattern = PathPattern::parse("%track% - %title%").unwrap();
+        assert!(pattern.parse_path(Path::new("Intro - Song.mp3")).is_none());
+
+        let pattern = PathPattern::parse("%artist%/%album%/%title%").unwrap();
+        assert!(pattern.parse_path(Path::new("Album/Song.mp3")).is_none());
+    }
+
+    #[test]
+    fn formats_paths_with_placeholders() {
+        let pattern = PathPattern::parse("%artist%/%album%/%track% - %title%").unwrap();
+        let tags = TrackTags {
+            artist: Some("AC/DC".to_string()),
+            track: Some(3),
+            ..TrackTags::default()
+        };
+        assert_eq!(
+            pattern.format(&tags, "file"),
+            ["AC_DC", "Unknown Album", "03 - file"]
+                .iter()
+                .collect::<PathBuf>()
+        );
+
+        let tags = TrackTags {
+            title: Some(".hidden".to_string()),
+            ..TrackTags::default()
+        };
+        let pattern = PathPattern::parse("%track% - %title%").unwrap();
+        assert_eq!(pattern.format(&tags, "file"), PathBuf::from("hidden"));
+    }
+}
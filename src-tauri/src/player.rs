@@ -3,30 +3,34 @@
 #![allow(clippy::needless_update)]
 
 use std::fs::File;
-use std::path::Path;
 use std::sync::Mutex;
 use std::sync::mpsc::Sender;
 
-use base64::Engine;
-use base64::engine::general_purpose;
 use log::{debug, info, warn};
 use symphonia::core::codecs::{CODEC_TYPE_NULL, DecoderOptions, FinalizeResult};
 use symphonia::core::errors::{Error, Result};
 use symphonia::core::formats::{FormatReader, SeekMode, SeekTo, Track};
 use symphonia::core::io::{MediaSource, MediaSourceStream, ReadOnlySource};
-use symphonia::core::meta::{MetadataOptions, StandardTagKey, Visual};
-use symphonia::core::probe::{Hint, ProbeResult};
+use symphonia::core::meta::{MetadataOptions, StandardTagKey};
+use symphonia::core::probe::Hint;
 use symphonia::core::units::{Time, TimeBase};
-use tauri::{AppHandle, Manager};
+use tauri::{AppHandle, Emitter, Manager};
 
-use crate::music::{MusicImage, MusicInfo, MusicMeta, PlayState};
+use crate::chapters;
+use crate::file_reader;
+use crate::lyrics;
+use crate::music::{AudioStream, MusicInfo, MusicMeta, PlayState, TrackSegment};
 use crate::output;
 use crate::state::{IdState, MusicFilesState, PauseState, TimePositionState};
+use crate::stats;
+use crate::tag_editor;
 
-const DIRTY_DATA: &str = "【熊猫无损音乐www.xmwav.com】更多打包资源下载";
+/// Cue sheet tracks closer than this count as continuous, in seconds.
+const GAPLESS_TOLERANCE: f64 = 0.05;
 
-pub fn load_metadata(music_path: &str) -> Option<MusicMeta> {
-    let path = Path::new(music_path);
+/// Reads title, artist and album, with `strip_patterns` removed from each value.
+pub fn load_metadata(music_path: &str, strip_patterns: &[String]) -> Option<MusicMeta> {
+    let path = &file_reader::local_path(music_path);
     let hint = Hint::new();
     let source = File::open(path).ok()?;
     let mss = MediaSourceStream::new(Box::new(source), Default::default());
@@ -54,15 +58,16 @@ pub fn load_metadata(music_path: &str) -> Option<MusicMeta> {
     let mut music_meta = MusicMeta::new(String::new());
     for tag in tags.iter().filter(|tag| tag.is_known()) {
         if let Some(std_key) = tag.std_key {
+            let value = || tag_editor::strip_patterns(&tag.value.to_string(), strip_patterns);
             match std_key {
                 StandardTagKey::Album => {
-                    music_meta.album = tag.value.to_string().replace(DIRTY_DATA, "");
+                    music_meta.album = value();
                 }
                 StandardTagKey::Artist => {
-                    music_meta.artist = tag.value.to_string().replace(DIRTY_DATA, "");
+                    music_meta.artist = value();
                 }
                 StandardTagKey::TrackTitle => {
-                    music_meta.title = tag.value.to_string().replace(DIRTY_DATA, "");
+                    music_meta.title = value();
                 }
                 _ => {}
             }
@@ -71,16 +76,61 @@ pub fn load_metadata(music_path: &str) -> Option<MusicMeta> {
     Some(music_meta)
 }
 
+/// Audio streams of `music_path`, marking `selected`, or the stream played by
+/// default when no valid one is selected.
+pub fn audio_streams(music_path: &str, selected: Option<usize>) -> Option<Vec<AudioStream>> {
+    let path = &file_reader::local_path(music_path);
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+    let source = File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(source), Default::default());
+    let probed = symphonia::default::get_probe()
+        .format(&hint, mss, &Default::default(), &Default::default())
+        .ok()?;
+
+    let tracks = probed.format.tracks();
+    let playing = selected_track(tracks, selected)
+        .and_then(|track| tracks.iter().position(|t| t.id == track.id));
+    Some(
+        tracks
+            .iter()
+            .enumerate()
+            .filter(|(_, track)| track.codec_params.codec != CODEC_TYPE_NULL)
+            .map(|(index, track)| {
+                let params = &track.codec_params;
+                let codec = symphonia::default::get_codecs()
+                    .get_codec(params.codec)
+                    .map(|codec| codec.long_name.to_string())
+                    .unwrap_or_default();
+                AudioStream {
+                    index,
+                    codec,
+                    language: track.language.clone(),
+                    channels: params.channels.map(|channels| channels.count()),
+                    sample_rate: params.sample_rate,
+                    selected: playing == Some(index),
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Plays `music_path` from `time_position`. For a cue sheet track, `segment` is the
+/// part of the file it covers and `time_position` is relative to its start.
+/// `track_num` picks one of several audio streams; the first is played otherwise.
 pub fn start_play(
     app: &AppHandle,
     time_position: Option<Time>,
+    segment: Option<TrackSegment>,
+    track_num: Option<usize>,
     music_path: &str,
     play_state: &Sender<PlayState>,
     store_state: &Sender<PlayState>,
     music_info_tx: &Sender<MusicInfo>,
-    music_image_tx: &Sender<MusicImage>,
 ) -> Result<i32> {
-    let path = Path::new(music_path);
+    let path = &file_reader::local_path(music_path);
     let mut hint = Hint::new();
 
     let source: Box<dyn MediaSource> = if path.as_os_str() == "-" {
@@ -99,9 +149,7 @@ pub fn start_play(
     let metadata_opts: MetadataOptions = Default::default();
 
     match symphonia::default::get_probe().format(&hint, mss, &format_opts, &metadata_opts) {
-        Ok(mut probed) => {
-            dump_visuals(&mut probed, music_image_tx);
-
+        Ok(probed) => {
             let tracks = probed.format.tracks();
             if !tracks.is_empty() {
                 for track in tracks.iter() {
@@ -142,10 +190,19 @@ pub fn start_play(
             }
 
             let decode_opts = Default::default();
+            let seek = match segment {
+                Some(segment) => {
+                    let offset = time_position.map_or(0.0, |t| t.seconds as f64 + t.frac);
+                    Some(segment.start + offset)
+                        .filter(|seconds| *seconds > 0.0)
+                        .map(crate::convert_to_time)
+                }
+                None => time_position,
+            };
             play(
                 probed.format,
-                None,
-                time_position,
+                track_num,
+                seek,
                 &decode_opts,
                 play_state,
                 store_state,
@@ -174,9 +231,7 @@ fn play(
     store_state_tx: &Sender<PlayState>,
     app: &AppHandle,
 ) -> Result<i32> {
-    let track = track_num
-        .and_then(|t| reader.tracks().get(t))
-        .or_else(|| first_supported_track(reader.tracks()));
+    let track = selected_track(reader.tracks(), track_num);
 
     let mut track_id = match track {
         Some(track) => track.id,
@@ -247,6 +302,7 @@ struct TrackContext {
     id: Option<String>,
     name: String,
     path: String,
+    segment: Option<TrackSegment>,
 }
 
 fn get_track_context(app: &AppHandle) -> Option<TrackContext> {
@@ -261,6 +317,40 @@ fn get_track_context(app: &AppHandle) -> Option<TrackContext> {
         id: Some(f.id),
         name: f.name,
         path: f.path,
+        segment: f.segment,
+    })
+}
+
+/// At the end of a cue sheet track, moves playback on to the next track when it
+/// continues the same file where this one ends, so the file keeps playing without a
+/// gap.
+fn continue_gapless(app: &AppHandle, ctx: &TrackContext) -> Option<TrackContext> {
+    let id = ctx.id.clone()?;
+    let end = ctx.segment?.end?;
+    let next_id = crate::next_track_id(app, &id)?;
+    let next = {
+        let music_files_state = app.state::<Mutex<MusicFilesState>>();
+        let state = music_files_state.lock().ok()?;
+        state.get().iter().find(|f| f.id == next_id).cloned()?
+    };
+    let continues = next.path == ctx.path
+        && next
+            .segment
+            .is_some_and(|segment| (segment.start - end).abs() < GAPLESS_TOLERANCE);
+    if !continues {
+        return None;
+    }
+
+    stats::finish(app, true);
+    if let Ok(mut id_state) = app.state::<Mutex<IdState>>().lock() {
+        id_state.set(Some(next_id));
+    }
+    let _ = app.emit("finished", id);
+    Some(TrackContext {
+        id: Some(next.id),
+        name: next.name,
+        path: next.path,
+        segment: next.segment,
     })
 }
 
@@ -322,37 +412,55 @@ fn play_track(
                 if packet.ts() >= play_opts.seek_ts {
                     if let Some(tb) = tb {
                         let ctx = get_track_context(app);
-                        if let Some(ctx) = ctx {
+                        if let Some(mut ctx) = ctx {
                             let ts = packet.ts();
-                            let t = tb.calc_time(ts);
-
-                            let hours = t.seconds / (60 * 60);
-                            let mins = (t.seconds % (60 * 60)) / 60;
-                            let secs = f64::from((t.seconds % 60) as u32) + t.frac;
-                            let progress = format!("{:}:{:0>2}:{:0>4.1}", hours, mins, secs);
-
-                            let left_duration = dur
-                                .map(|dur| {
-                                    let t = tb.calc_time(dur.saturating_sub(ts));
-                                    let hours = t.seconds / (60 * 60);
-                                    let mins = (t.seconds % (60 * 60)) / 60;
-                                    let secs = f64::from((t.seconds % 60) as u32) + t.frac;
-                                    format!("{:}:{:0>2}:{:0>4.1}", hours, mins, secs)
-                                })
+                            let segment_ts =
+                                |seconds: f64| tb.calc_timestamp(crate::convert_to_time(seconds));
+                            if let Some(end) = ctx.segment.and_then(|segment| segment.end)
+                                && ts >= segment_ts(end)
+                            {
+                                match continue_gapless(app, &ctx) {
+                                    Some(next) => ctx = next,
+                                    None => break Ok(()),
+                                }
+                            }
+                            // Cue sheet tracks report progress from their own start.
+                            let start_ts =
+                                ctx.segment.map_or(0, |segment| segment_ts(segment.start));
+                            let end_ts = ctx
+                                .segment
+                                .and_then(|segment| segment.end)
+                                .map(segment_ts)
+                                .or(dur);
+                            let t = tb.calc_time(ts.saturating_sub(start_ts));
+                            let progress = fmt_progress(t);
+                            let left_duration = end_ts
+                                .map(|dur| fmt_progress(tb.calc_time(dur.saturating_sub(ts))))
                                 .unwrap_or_default();
 
                             if let Ok(mut time_pos) = app.state::<Mutex<TimePositionState>>().lock()
                             {
                                 time_pos.set(Some(t));
                             }
-
+                            // Lyrics are loaded for the whole file, so they follow its
+                            // clock; chapters are only read for files without a cue sheet.
+                            let file_seconds = ctx.segment.map_or(0.0, |segment| segment.start)
+                                + t.seconds as f64
+                                + t.frac;
+                            lyrics::update_position(app, &ctx.path, file_seconds);
+
+                            let id = ctx.id.clone().unwrap_or_default();
+                            let chapter = chapters::current(app, &id, t.seconds as f64 + t.frac);
+                            stats::track_progress(app, &id, t.seconds as f64 + t.frac);
                             let state = PlayState::new(
-                                ctx.id.clone().unwrap_or_default(),
+                                id,
                                 ctx.name.clone(),
                                 ctx.path.clone(),
                                 progress.clone(),
                                 left_duration.clone(),
-                            );
+                                t.seconds as f64 + t.frac,
+                            )
+                            .with_chapter(chapter);
                             let _ = play_state_tx.send(state.clone());
                             let _ = store_state_tx.send(state);
                         } else {
@@ -382,6 +490,14 @@ fn first_supported_track(tracks: &[Track]) -> Option<&Track> {
         .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
 }
 
+/// Track `track_num` when it is a playable one, else the first that is.
+fn selected_track(tracks: &[Track], track_num: Option<usize>) -> Option<&Track> {
+    track_num
+        .and_then(|t| tracks.get(t))
+        .filter(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .or_else(|| first_supported_track(tracks))
+}
+
 fn ignore_end_of_stream_error(result: Result<()>) -> Result<()> {
     match result {
         Err(Error::IoError(err))
@@ -404,34 +520,6 @@ fn do_verification(finalization: FinalizeResult) -> Result<i32> {
     }
 }
 
-fn dump_visual(visual: &Visual, music_image_tx: &Sender<MusicImage>) {
-    let content_type = visual.media_type.to_lowercase();
-    let image = format!(
-        "data:{};base64, {}",
-        content_type,
-        general_purpose::STANDARD.encode(&visual.data)
-    );
-    let _ = music_image_tx.send(MusicImage::new(image));
-}
-
-fn dump_visuals(probed: &mut ProbeResult, music_image_tx: &Sender<MusicImage>) {
-    if let Some(metadata) = probed.format.metadata().current() {
-        for visual in metadata.visuals().iter() {
-            dump_visual(visual, music_image_tx);
-        }
-
-        if probed.metadata.get().as_ref().is_some() {
-            info!(
-                "visuals from container format are preferred; skipping additional probed visuals"
-            );
-        }
-    } else if let Some(metadata) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
-        for visual in metadata.visuals().iter() {
-            dump_visual(visual, music_image_tx);
-        }
-    }
-}
-
 fn fmt_time(ts: u64, tb: TimeBase) -> String {
     let time = tb.calc_time(ts);
     let hours = time.seconds / (60 * 60);
@@ -439,3 +527,11 @@ fn fmt_time(ts: u64, tb: TimeBase) -> String {
     let secs = f64::from((time.seconds % 60) as u32) + time.frac;
     format!("{}:{:0>2}:{:0>6.3}", hours, mins, secs)
 }
+
+/// Formats a playback position as shown in the progress, e.g. `0:03:07.5`.
+pub fn fmt_progress(time: Time) -> String {
+    let hours = time.seconds / (60 * 60);
+    let mins = (time.seconds % (60 * 60)) / 60;
+    let secs = f64::from((time.seconds % 60) as u32) + time.frac;
+    format!("{}:{:0>2}:{:0>4.1}", hours, mins, secs)
+}
@@ -0,0 +1,570 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use quick_xml::{Reader, escape::escape, events::Event};
+use rayon::prelude::*;
+
+use crate::{
+    file_reader::{self, AudioSniff},
+    music::{MusicFile, SkippedEntry, TrackSegment},
+};
+
+const XSPF_NAMESPACE: &str = "http://xspf.org/ns/0/";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PlaylistFormat {
+    /// M3U and M3U8, with `#EXTINF` lines.
+    M3u,
+    Pls,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "m3u" | "m3u8" => Some(Self::M3u),
+            "pls" => Some(Self::Pls),
+            "xspf" => Some(Self::Xspf),
+            _ => None,
+        }
+    }
+}
+
+/// An entry as written in the playlist file.
+#[derive(Default)]
+struct Entry {
+    location: String,
+    title: Option<String>,
+    duration: Option<f64>,
+}
+
+/// A playlist entry pointing at a file that exists.
+pub struct ResolvedEntry {
+    pub path: String,
+    pub title: Option<String>,
+    /// Length in seconds as the playlist states it, which may not match the file.
+    pub duration: Option<f64>,
+}
+
+/// Reads a playlist file. Entries that are not local files, or whose files are
+/// missing, are returned as skipped instead of resolved.
+pub fn read(path: &Path) -> Result<(Vec<ResolvedEntry>, Vec<SkippedEntry>), String> {
+    let format = PlaylistFormat::from_path(path)
+        .ok_or_else(|| "not an M3U, PLS or XSPF playlist".to_string())?;
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let text = decode(&bytes);
+    let entries = match format {
+        PlaylistFormat::M3u => parse_m3u(&text),
+        PlaylistFormat::Pls => parse_pls(&text),
+        PlaylistFormat::Xspf => parse_xspf(&text)?,
+    };
+
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let mut resolved = Vec::new();
+    let mut skipped = Vec::new();
+    for entry in entries {
+        match resolve(&entry.location, base_dir) {
+            Ok(path) => resolved.push(ResolvedEntry {
+                path,
+                title: entry.title,
+                duration: entry.duration,
+            }),
+            Err(reason) => skipped.push(SkippedEntry::new(entry.location, reason)),
+        }
+    }
+    Ok((resolved, skipped))
+}
+
+/// Writes `tracks` in the format named by the extension of `path`. Relative
+/// locations are used where `relative` is set and the track shares a root with the
+/// playlist; everything else is written absolute.
+pub fn write(path: &Path, tracks: &[MusicFile], relative: bool) -> Result<(), String> {
+    let format = PlaylistFormat::from_path(path)
+        .ok_or_else(|| "not an M3U, PLS or XSPF playlist".to_string())?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let durations: Vec<Option<f64>> = tracks.par_iter().map(track_duration).collect();
+    let entries: Vec<(&MusicFile, Option<f64>, PathBuf)> = tracks
+        .iter()
+        .zip(durations)
+        .map(|(track, duration)| {
+            let path = Path::new(&track.path);
+            let location = relative
+                .then(|| relative_path(path, base_dir))
+                .flatten()
+                .unwrap_or_else(|| path.to_path_buf());
+            (track, duration, location)
+        })
+        .collect();
+
+    let content = match format {
+        PlaylistFormat::M3u => write_m3u(&entries),
+        PlaylistFormat::Pls => write_pls(&entries),
+        PlaylistFormat::Xspf => write_xspf(&entries),
+    };
+    fs::write(path, content).map_err(|e| e.to_string())
+}
+
+/// How long `track` plays: a cue track's own span, or the rest of its file from
+/// its start.
+fn track_duration(track: &MusicFile) -> Option<f64> {
+    if let Some(TrackSegment {
+        start,
+        end: Some(end),
+    }) = track.segment
+    {
+        return Some(end - start);
+    }
+    let duration = match file_reader::sniff_audio_file(&file_reader::local_path(&track.path)) {
+        AudioSniff::Playable(duration) => duration?,
+        _ => return None,
+    };
+    let start = track.segment.as_ref().map_or(0.0, |segment| segment.start);
+    Some((duration - start).max(0.0))
+}
+
+/// A stated length in seconds; unknown lengths are written as -1 or left out.
+fn parse_duration(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| *seconds >= 0.0)
+}
+
+/// Playlists are UTF-8 by convention, but old M3U files are often Latin-1.
+pub fn decode(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+/// Plain and extended M3U. The `#EXTINF` line gives the title and duration of the
+/// entry that follows it.
+fn parse_m3u(text: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut title = None;
+    let mut duration = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        // #EXTINF:<seconds>[ attributes],<title>
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let (length, name) = info.split_once(',').unwrap_or((info, ""));
+            duration = length.split_whitespace().next().and_then(parse_duration);
+            title = Some(name.trim().to_string()).filter(|name| !name.is_empty());
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        entries.push(Entry {
+            location: line.to_string(),
+            title: title.take(),
+            duration: duration.take(),
+        });
+    }
+    entries
+}
+
+/// `FileN`, `TitleN` and `LengthN` keys of a `[playlist]` section, in the order of N.
+fn parse_pls(text: &str) -> Vec<Entry> {
+    let mut entries: BTreeMap<u32, Entry> = BTreeMap::new();
+    for line in text.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let split = key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len());
+        let (name, number) = key.split_at(split);
+        let Ok(number) = number.parse::<u32>() else {
+            continue;
+        };
+        let value = value.trim();
+        match name {
+            "file" => entries.entry(number).or_default().location = value.to_string(),
+            "title" if !value.is_empty() => {
+                entries.entry(number).or_default().title = Some(value.to_string());
+            }
+            "length" => entries.entry(number).or_default().duration = parse_duration(value),
+            _ => {}
+        }
+    }
+    entries
+        .into_values()
+        .filter(|entry| !entry.location.is_empty())
+        .collect()
+}
+
+fn parse_xspf(text: &str) -> Result<Vec<Entry>, String> {
+    let mut reader = Reader::from_str(text);
+    let mut entries = Vec::new();
+    let mut track: Option<Entry> = None;
+    let mut field: Option<Vec<u8>> = None;
+    loop {
+        match reader
+            .read_event()
+            .map_err(|e| format!("invalid XSPF: {}", e))?
+        {
+            Event::Start(element) => {
+                let name = element.local_name().as_ref().to_vec();
+                if name == b"track" {
+                    track = Some(Entry::default());
+                } else if track.is_some() {
+                    field = Some(name);
+                }
+            }
+            Event::Text(content) => {
+                let (Some(track), Some(field)) = (track.as_mut(), field.as_deref()) else {
+                    continue;
+                };
+                let value = content
+                    .unescape()
+                    .map_err(|e| format!("invalid XSPF: {}", e))?
+                    .trim()
+                    .to_string();
+                match field {
+                    b"location" if track.location.is_empty() => {
+                        track.location = location_from_uri(&value);
+                    }
+                    b"title" if !value.is_empty() => track.title = Some(value),
+                    // Milliseconds in XSPF.
+                    b"duration" => {
+                        track.duration = parse_duration(&value).map(|millis| millis / 1000.0);
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(element) => {
+                if element.local_name().as_ref() == b"track"
+                    && let Some(track) = track.take()
+                    && !track.location.is_empty()
+                {
+                    entries.push(track);
+                }
+                field = None;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+/// XSPF locations are URIs; relative ones are percent-decoded into paths here,
+/// absolute ones are left for `resolve`.
+fn location_from_uri(uri: &str) -> String {
+    if uri.contains("://") {
+        return uri.to_string();
+    }
+    urlencoding::decode(uri)
+        .map(|path| path.into_owned())
+        .unwrap_or_else(|_| uri.to_string())
+}
+
+/// Turns a playlist location into the path of an existing local file.
+fn resolve(location: &str, base_dir: &Path) -> Result<String, String> {
+    let location = location.trim();
+    let path = if let Some(uri_path) = location.strip_prefix("file://") {
+        let uri_path = uri_path.strip_prefix("localhost").unwrap_or(uri_path);
+        let decoded = urlencoding::decode(uri_path)
+            .map_err(|_| "location is not valid UTF-8".to_string())?
+            .into_owned();
+        // file:///C:/Music/... on Windows
+        if cfg!(windows) && decoded.get(2..3) == Some(":") {
+            PathBuf::from(&decoded[1..])
+        } else {
+            PathBuf::from(decoded)
+        }
+    } else if location.contains("://") {
+        return Err("remote streams are not supported".to_string());
+    } else if cfg!(windows) {
+        PathBuf::from(location)
+    } else {
+        // Playlists written on Windows separate folders with backslashes.
+        PathBuf::from(location.replace('\\', "/"))
+    };
+
+    let path = if path.is_absolute() {
+        path
+    } else {
+        base_dir.join(path)
+    };
+    if !path.is_file() {
+        return Err("file not found".to_string());
+    }
+    path.to_str()
+        .map(str::to_string)
+        .ok_or_else(|| "path is not valid UTF-8".to_string())
+}
+
+/// `target` relative to `base_dir`, or `None` when they have no root in common.
+fn relative_path(target: &Path, base_dir: &Path) -> Option<PathBuf> {
+    let target: Vec<Component> = target.components().collect();
+    let base: Vec<Component> = base_dir.components().collect();
+    if target.first() != base.first() {
+        return None;
+    }
+    let common = target.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    for component in &target[common..] {
+        relative.push(component);
+    }
+    Some(relative)
+}
+
+fn display_title(track: &MusicFile) -> String {
+    match &track.artist {
+        Some(artist) if !artist.is_empty() => format!("{} - {}", artist, track.name),
+        _ => track.name.clone(),
+    }
+}
+
+fn write_m3u(entries: &[(&MusicFile, Option<f64>, PathBuf)]) -> String {
+    let mut content = String::from("#EXTM3U\n");
+    for (track, duration, location) in entries {
+        content.push_str(&format!(
+            "#EXTINF:{},{}\n{}\n",
+            duration.map_or(-1, |duration| duration.round() as i64),
+            display_title(track),
+            location.display()
+        ));
+    }
+    content
+}
+
+fn write_pls(entries: &[(&MusicFile, Option<f64>, PathBuf)]) -> String {
+    let mut content = String::from("[playlist]\n");
+    for (index, (track, duration, location)) in entries.iter().enumerate() {
+        let number = index + 1;
+        content.push_str(&format!(
+            "File{number}={}\nTitle{number}={}\nLength{number}={}\n",
+            location.display(),
+            display_title(track),
+            duration.map_or(-1, |duration| duration.round() as i64),
+        ));
+    }
+    content.push_str(&format!("NumberOfEntries={}\nVersion=2\n", entries.len()));
+    content
+}
+
+fn write_xspf(entries: &[(&MusicFile, Option<f64>, PathBuf)]) -> String {
+    let mut content = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"{}\">\n  <trackList>\n",
+        XSPF_NAMESPACE
+    );
+    for (track, duration, location) in entries {
+        content.push_str("    <track>\n");
+        content.push_str(&format!(
+            "      <location>{}</location>\n",
+            escape(&location_uri(location))
+        ));
+        content.push_str(&format!("      <title>{}</title>\n", escape(&track.name)));
+        if let Some(artist) = track.artist.as_deref().filter(|artist| !artist.is_empty()) {
+            content.push_str(&format!("      <creator>{}</creator>\n", escape(artist)));
+        }
+        if let Some(album) = track.album.as_deref().filter(|album| !album.is_empty()) {
+            content.push_str(&format!("      <album>{}</album>\n", escape(album)));
+        }
+        if let Some(duration) = duration {
+            content.push_str(&format!(
+                "      <duration>{}</duration>\n",
+                (duration * 1000.0).round() as u64
+            ));
+        }
+        content.push_str("    </track>\n");
+    }
+    content.push_str("  </trackList>\n</playlist>\n");
+    content
+}
+
+/// A percent-encoded URI reference: `file://` for absolute paths, relative
+/// otherwise.
+fn location_uri(location: &Path) -> String {
+    let path = location.to_string_lossy().replace('\\', "/");
+    let encoded = path
+        .split('/')
+        .map(|segment| {
+            // Keep Windows drive letters readable.
+            if segment.len() == 2 && segment.ends_with(':') {
+                segment.to_string()
+            } else {
+                urlencoding::encode(segment).into_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    if !location.is_absolute() {
+        encoded
+    } else if encoded.starts_with('/') {
+        format!("file://{}", encoded)
+    } else {
+        format!("file:///{}", encoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TempDir, write_melody};
+
+    fn summary(entries: &[Entry]) -> Vec<(&str, Option<&str>, Option<f64>)> {
+        entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.location.as_str(),
+                    entry.title.as_deref(),
+                    entry.duration,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_extended_m3u() {
+        let text = "#EXTM3U\n#EXTINF:123 tvg-id=\"x\",Artist - Title\nsong.mp3\n\n#EXTINF:-1,\nother.mp3\n# comment\nplain.mp3\n";
+        assert_eq!(
+            summary(&parse_m3u(text)),
+            [
+                ("song.mp3", Some("Artist - Title"), Some(123.0)),
+                ("other.mp3", None, None),
+                ("plain.mp3", None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_pls_in_entry_order() {
+        let text = "[playlist]\nFile2=b.mp3\nTitle2=B\nfile1 = a.mp3\nLength1=61\nLength2=-1\nTitle3=No file\nNumberOfEntries=2\n";
+        assert_eq!(
+            summary(&parse_pls(text)),
+            [("a.mp3", None, Some(61.0)), ("b.mp3", Some("B"), None)]
+        );
+    }
+
+    #[test]
+    fn parses_xspf() {
+        let text = r#"<?xml version="1.0"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <title>Not a track</title>
+  <trackList>
+    <track>
+      <location>Music/A%20Song.flac</location>
+      <title>A &amp; B</title>
+      <duration>61500</duration>
+    </track>
+    <track><title>No location</title></track>
+    <track><location>file:///music/b.mp3</location></track>
+  </trackList>
+</playlist>"#;
+        assert_eq!(
+            summary(&parse_xspf(text).unwrap()),
+            [
+                ("Music/A Song.flac", Some("A & B"), Some(61.5)),
+                ("file:///music/b.mp3", None, None),
+            ]
+        );
+        assert!(parse_xspf("<playlist><track></playlist>").is_err());
+    }
+
+    #[test]
+    fn decodes_latin1_and_strips_the_bom() {
+        assert_eq!(decode(b"\xEF\xBB\xBFcaf\xC3\xA9.mp3"), "café.mp3");
+        assert_eq!(decode(b"caf\xE9.mp3"), "café.mp3");
+    }
+
+    #[test]
+    fn relative_paths_climb_to_the_common_root() {
+        assert_eq!(
+            relative_path(Path::new("/music/a/b.mp3"), Path::new("/music/lists")),
+            Some(PathBuf::from("../a/b.mp3"))
+        );
+        assert_eq!(
+            relative_path(Path::new("/music/b.mp3"), Path::new("/music")),
+            Some(PathBuf::from("b.mp3"))
+        );
+        assert_eq!(
+            relative_path(Path::new("/music/b.mp3"), Path::new("music")),
+            None
+        );
+    }
+
+    #[test]
+    fn resolves_local_files_only() {
+        let dir = TempDir::new();
+        let song = dir.path().join("a song.wav");
+        fs::write(&song, b"").unwrap();
+        let expected = song.to_str().unwrap().to_string();
+
+        assert_eq!(resolve("a song.wav", dir.path()), Ok(expected.clone()));
+        let uri = location_uri(&song);
+        assert_eq!(resolve(&uri, Path::new("/elsewhere")), Ok(expected));
+        assert_eq!(
+            resolve("missing.wav", dir.path()),
+            Err("file not found".to_string())
+        );
+        assert_eq!(
+            resolve("http://example.com/stream", dir.path()),
+            Err("remote streams are not supported".to_string())
+        );
+    }
+
+    #[test]
+    fn written_playlists_read_back() {
+        let dir = TempDir::new();
+        let music_dir = dir.path().join("Music");
+        let list_dir = dir.path().join("Lists");
+        fs::create_dir_all(&music_dir).unwrap();
+        fs::create_dir_all(&list_dir).unwrap();
+        let song = music_dir.join("a song & more.wav");
+        write_melody(&song, &[440.0], 2.0, 8000);
+        let tracks = [
+            MusicFile::new(
+                "1".to_string(),
+                "A Song".to_string(),
+                song.to_str().unwrap().to_string(),
+                None,
+                Some("Artist".to_string()),
+                None,
+            ),
+            MusicFile::new(
+                "2".to_string(),
+                "Gone".to_string(),
+                music_dir.join("gone.wav").to_str().unwrap().to_string(),
+                None,
+                None,
+                None,
+            ),
+        ];
+
+        for name in ["list.m3u8", "list.pls", "list.xspf"] {
+            for relative in [true, false] {
+                let path = list_dir.join(name);
+                write(&path, &tracks, relative).unwrap();
+                let (resolved, skipped) = read(&path).unwrap();
+                assert_eq!(resolved.len(), 1, "{}", name);
+                assert_eq!(
+                    Path::new(&resolved[0].path).canonicalize().unwrap(),
+                    song.canonicalize().unwrap(),
+                    "{}",
+                    name
+                );
+                assert_eq!(resolved[0].duration, Some(2.0), "{}", name);
+                assert!(resolved[0].title.as_deref().unwrap().contains("A Song"));
+                assert_eq!(skipped.len(), 1, "{}", name);
+                assert_eq!(skipped[0].reason, "file not found");
+            }
+        }
+    }
+}
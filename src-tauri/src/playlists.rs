@@ -0,0 +1,301 @@
+use std::{cmp::Ordering, sync::Mutex};
+
+use log::warn;
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use crate::{
+    music::{MusicFile, PlayState, Playlist, PlaylistSummary, SmartRules, SortField},
+    player, smart,
+    state::{IdState, MusicFilesState, PauseState, SmartPlaylistState, TimePositionState},
+    store,
+};
+
+pub fn summaries(app: &AppHandle) -> Vec<PlaylistSummary> {
+    let playlists = store::load_playlists(app);
+    let current = store::load_current_playlist_id(app);
+    playlists
+        .iter()
+        .map(|playlist| PlaylistSummary::new(playlist, Some(&playlist.id) == current.as_ref()))
+        .collect()
+}
+
+/// Adds an empty playlist at the end of the list.
+pub fn create(app: &AppHandle, name: &str) -> Result<PlaylistSummary, String> {
+    let name = valid_name(name)?;
+    let playlist = Playlist::new(Uuid::new_v4().to_string(), name, Vec::new());
+    let summary = PlaylistSummary::new(&playlist, false);
+    store::update_playlists(app, |playlists| playlists.push(playlist));
+    Ok(summary)
+}
+
+/// Adds a smart playlist at the end of the list, filled with the library tracks
+/// matching `rules`.
+pub fn create_smart(
+    app: &AppHandle,
+    name: &str,
+    rules: SmartRules,
+) -> Result<PlaylistSummary, String> {
+    let name = valid_name(name)?;
+    let tracks = smart::preview(app, &rules);
+    let smart_state = app.state::<Mutex<SmartPlaylistState>>();
+    let _refreshing = smart_state.lock().map_err(|e| e.to_string())?;
+    let playlist = Playlist::new(Uuid::new_v4().to_string(), name, tracks).with_smart(Some(rules));
+    let summary = PlaylistSummary::new(&playlist, false);
+    store::update_playlists(app, |playlists| playlists.push(playlist));
+    Ok(summary)
+}
+
+/// Replaces the rules of smart playlist `id` and refills it.
+pub fn update_rules(app: &AppHandle, id: &str, rules: SmartRules) -> Result<Playlist, String> {
+    let tracks = smart::preview(app, &rules);
+    // Held while storing so that a refresh running meanwhile cannot overwrite it.
+    let smart_state = app.state::<Mutex<SmartPlaylistState>>();
+    let _refreshing = smart_state.lock().map_err(|e| e.to_string())?;
+    let updated = store::update_playlists(app, |playlists| {
+        let playlist = find(playlists, id)?;
+        if playlist.smart.is_none() {
+            return Err(format!("{} is not a smart playlist", playlist.name));
+        }
+        playlist.smart = Some(rules);
+        playlist.tracks = tracks;
+        Ok(playlist.clone())
+    })?;
+
+    if is_current(app, id)
+        && let Ok(mut music_files_state) = app.state::<Mutex<MusicFilesState>>().lock()
+    {
+        music_files_state.set(updated.tracks.clone());
+    }
+    Ok(updated)
+}
+
+/// Errors when the now playing list is a smart playlist, whose tracks only change
+/// with its rules.
+pub fn ensure_editable(app: &AppHandle) -> Result<(), String> {
+    let Some(id) = store::load_current_playlist_id(app) else {
+        return Ok(());
+    };
+    match store::load_playlists(app)
+        .into_iter()
+        .find(|playlist| playlist.id == id)
+    {
+        Some(playlist) if playlist.smart.is_some() => Err(format!(
+            "{} is a smart playlist; edit its rules instead",
+            playlist.name
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub fn rename(app: &AppHandle, id: &str, name: &str) -> Result<PlaylistSummary, String> {
+    let name = valid_name(name)?;
+    let current = is_current(app, id);
+    store::update_playlists(app, |playlists| {
+        let playlist = find(playlists, id)?;
+        playlist.name = name;
+        Ok(PlaylistSummary::new(playlist, current))
+    })
+}
+
+/// Copies a playlist next to the original. The copy's tracks get new ids, so they
+/// stay distinct when both lists are played.
+pub fn duplicate(app: &AppHandle, id: &str, name: Option<&str>) -> Result<PlaylistSummary, String> {
+    store::update_playlists(app, |playlists| {
+        let index = position(playlists, id)?;
+        let original = &playlists[index];
+        let name = match name {
+            Some(name) => valid_name(name)?,
+            None => format!("{} copy", original.name),
+        };
+
+        let mut copy = Playlist::new(Uuid::new_v4().to_string(), name, original.tracks.clone())
+            .with_smart(original.smart.clone());
+        for track in copy.tracks.iter_mut() {
+            let new_id = Uuid::new_v4().to_string();
+            if original.last_track_id.as_ref() == Some(&track.id) {
+                copy.last_track_id = Some(new_id.clone());
+                copy.last_position = original.last_position;
+            }
+            track.id = new_id;
+        }
+        let summary = PlaylistSummary::new(&copy, false);
+        playlists.insert(index + 1, copy);
+        Ok(summary)
+    })
+}
+
+/// Deletes a playlist other than the one playing.
+pub fn delete(app: &AppHandle, id: &str) -> Result<(), String> {
+    if is_current(app, id) {
+        return Err("the now playing playlist cannot be deleted".to_string());
+    }
+    store::update_playlists(app, |playlists| {
+        let index = position(playlists, id)?;
+        playlists.remove(index);
+        Ok(())
+    })
+}
+
+/// Puts the playlists in the order of `ids`, which must list each of them once.
+pub fn reorder(app: &AppHandle, ids: &[String]) -> Result<Vec<PlaylistSummary>, String> {
+    store::update_playlists(app, |playlists| {
+        if ids.len() != playlists.len() {
+            return Err("every playlist must be listed exactly once".to_string());
+        }
+        let mut remaining = playlists.clone();
+        let mut ordered = Vec::with_capacity(remaining.len());
+        for id in ids {
+            let index = position(&remaining, id)?;
+            ordered.push(remaining.swap_remove(index));
+        }
+        *playlists = ordered;
+        Ok(())
+    })?;
+    Ok(summaries(app))
+}
+
+/// Sorts the now playing list by `field`. Tracks without a value for the field go
+/// last either way.
+pub fn sort(app: &AppHandle, field: SortField, descending: bool) -> Vec<MusicFile> {
+    let music_files_state = app.state::<Mutex<MusicFilesState>>();
+    let Ok(mut state) = music_files_state.lock() else {
+        return Vec::new();
+    };
+    let mut tracks = state.get_cloned();
+    if let Err(err) = ensure_editable(app) {
+        warn!("not sorting: {}", err);
+        return tracks;
+    }
+    sort_tracks(&mut tracks, field, descending);
+    state.set(tracks.clone());
+    store::store_playlist(app, &tracks);
+    tracks
+}
+
+pub fn sort_tracks(tracks: &mut [MusicFile], field: SortField, descending: bool) {
+    tracks.sort_by(|a, b| {
+        let (a_key, b_key) = (sort_key(a, field), sort_key(b, field));
+        match (a_key, b_key) {
+            (Some(a_key), Some(b_key)) if descending => b_key.cmp(&a_key),
+            (Some(a_key), Some(b_key)) => a_key.cmp(&b_key),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    });
+}
+
+/// Values compared when sorting by `field`; text compares case-insensitively.
+fn sort_key(music: &MusicFile, field: SortField) -> Option<SortKey> {
+    let text = |value: &str| SortKey::Text(value.to_lowercase());
+    match field {
+        SortField::Title => Some(text(&music.name)),
+        SortField::Artist => music.artist.as_deref().map(text),
+        SortField::Album => music.album.as_deref().map(text),
+        SortField::Path => Some(text(&music.path)),
+        SortField::Rating => music
+            .rating
+            .map(|stars| SortKey::Number((stars * 2.0) as i64)),
+        SortField::Favourite => Some(SortKey::Number(music.favourite as i64)),
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SortKey {
+    Number(i64),
+    Text(String),
+}
+
+/// Makes `id` the now playing playlist. Playback stops, the playlist being left
+/// remembers its track and position, and the selected one is restored to where it
+/// was left.
+pub fn select(app: &AppHandle, id: &str) -> Result<Playlist, String> {
+    let mut playlists = store::load_playlists(app);
+    let target = position(&playlists, id)?;
+    let current = store::load_current_playlist_id(app);
+    if current.as_deref() == Some(id) {
+        return Ok(playlists.swap_remove(target));
+    }
+
+    if let Ok(mut pause_state) = app.state::<Mutex<PauseState>>().lock() {
+        pause_state.set(true, None, None);
+    }
+    let track_id = app
+        .state::<Mutex<IdState>>()
+        .lock()
+        .ok()
+        .and_then(|s| s.get());
+    let stopped_at = app
+        .state::<Mutex<TimePositionState>>()
+        .lock()
+        .ok()
+        .and_then(|s| s.get())
+        .map(|time| time.seconds as f64 + time.frac);
+    let selected = store::update_playlists(app, |playlists| {
+        let target = position(playlists, id)?;
+        if let Some(left) = playlists
+            .iter_mut()
+            .find(|playlist| Some(&playlist.id) == current.as_ref())
+        {
+            left.last_track_id = track_id;
+            left.last_position = stopped_at;
+        }
+        store::store_current_playlist_id(app, id);
+        Ok::<_, String>(playlists[target].clone())
+    })?;
+
+    if let Ok(mut id_state) = app.state::<Mutex<IdState>>().lock() {
+        id_state.set(selected.last_track_id.clone());
+    }
+    if let Ok(mut time_pos) = app.state::<Mutex<TimePositionState>>().lock() {
+        time_pos.set(selected.last_position.map(crate::convert_to_time));
+    }
+    if let Ok(mut music_files_state) = app.state::<Mutex<MusicFilesState>>().lock() {
+        music_files_state.set(selected.tracks.clone());
+    }
+    store::store_play_state(app, resume_state(&selected));
+    Ok(selected)
+}
+
+/// The play state `load_play_state` restores for a playlist's last track.
+fn resume_state(playlist: &Playlist) -> Option<PlayState> {
+    let track_id = playlist.last_track_id.as_ref()?;
+    let track = playlist.tracks.iter().find(|track| &track.id == track_id)?;
+    let progress = playlist
+        .last_position
+        .map(|position| player::fmt_progress(crate::convert_to_time(position)));
+    Some(PlayState {
+        id: Some(track.id.clone()),
+        name: Some(track.name.clone()),
+        path: Some(track.path.clone()),
+        progress,
+        left_duration: None,
+        position: playlist.last_position,
+        chapter: None,
+    })
+}
+
+fn valid_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("playlist name cannot be empty".to_string());
+    }
+    Ok(name.to_string())
+}
+
+fn is_current(app: &AppHandle, id: &str) -> bool {
+    store::load_current_playlist_id(app).as_deref() == Some(id)
+}
+
+fn position(playlists: &[Playlist], id: &str) -> Result<usize, String> {
+    playlists
+        .iter()
+        .position(|playlist| playlist.id == id)
+        .ok_or_else(|| format!("playlist {} not found", id))
+}
+
+fn find<'a>(playlists: &'a mut [Playlist], id: &str) -> Result<&'a mut Playlist, String> {
+    let index = position(playlists, id)?;
+    Ok(&mut playlists[index])
+}
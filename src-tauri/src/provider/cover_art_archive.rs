@@ -0,0 +1,159 @@
+use std::{collections::HashMap, io::Error};
+
+use futures::future::BoxFuture;
+use serde::Deserialize;
+
+use super::{MetadataProvider, TrackMatch, TrackQuery};
+use crate::{http_client::HttpClient, music::ProviderKind};
+
+/// Largest thumbnail requested; the originals can be tens of megabytes.
+const THUMBNAIL_SIZE: &str = "1200";
+
+/// The Cover Art Archive. It only serves artwork, for releases found on MusicBrainz.
+pub struct CoverArtArchive {
+    base_url: String,
+}
+
+impl CoverArtArchive {
+    pub fn new(base_url: String) -> Self {
+        Self { base_url }
+    }
+
+    async fn front_cover(
+        &self,
+        client: &HttpClient,
+        release_id: &str,
+    ) -> Result<Option<String>, Error> {
+        let url = format!("{}/release/{}", self.base_url, release_id);
+        let Some(text) = client.get_text(&url).await? else {
+            return Ok(None);
+        };
+        let response: ReleaseImagesRes = serde_json::from_str(&text)
+            .map_err(|e| Error::other(format!("failed to parse response: {}", e)))?;
+        Ok(response
+            .images
+            .into_iter()
+            .find(|image| image.front)
+            .map(|mut image| {
+                image
+                    .thumbnails
+                    .remove(THUMBNAIL_SIZE)
+                    .or_else(|| image.thumbnails.remove("large"))
+                    .unwrap_or(image.image)
+            }))
+    }
+}
+
+impl MetadataProvider for CoverArtArchive {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::CoverArtArchive
+    }
+
+    fn can_search(&self) -> bool {
+        false
+    }
+
+    fn search<'a>(
+        &'a self,
+        _client: &'a HttpClient,
+        _query: &'a TrackQuery,
+    ) -> BoxFuture<'a, Result<Vec<TrackMatch>, Error>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn artwork<'a>(
+        &'a self,
+        client: &'a HttpClient,
+        found: &'a TrackMatch,
+    ) -> BoxFuture<'a, Result<Option<String>, Error>> {
+        Box::pin(async move {
+            match &found.release_id {
+                Some(release_id) => self.front_cover(client, release_id).await,
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseImagesRes {
+    #[serde(default)]
+    images: Vec<ReleaseImage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseImage {
+    #[serde(default)]
+    front: bool,
+    image: String,
+    #[serde(default)]
+    thumbnails: HashMap<String, String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestServer;
+
+    fn found(release_id: Option<&str>) -> TrackMatch {
+        TrackMatch {
+            provider: Some(ProviderKind::MusicBrainz),
+            title: "Title".to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            duration: None,
+            artwork_url: None,
+            release_id: release_id.map(str::to_string),
+            score: 0.0,
+        }
+    }
+
+    #[test]
+    fn picks_the_front_cover_thumbnail() {
+        let server = TestServer::start(|path| {
+            match path {
+            "/release/with-thumbnail" => (
+                200,
+                r#"{"images":[
+                    {"front":false,"image":"https://example.com/back.jpg","thumbnails":{"1200":"https://example.com/back-1200.jpg"}},
+                    {"front":true,"image":"https://example.com/front.jpg","thumbnails":{"large":"https://example.com/front-500.jpg","1200":"https://example.com/front-1200.jpg"}}
+                ]}"#
+                .to_string(),
+            ),
+            "/release/large-only" => (
+                200,
+                r#"{"images":[{"front":true,"image":"https://example.com/front.jpg","thumbnails":{"large":"https://example.com/front-500.jpg"}}]}"#
+                    .to_string(),
+            ),
+            "/release/original-only" => (
+                200,
+                r#"{"images":[{"front":true,"image":"https://example.com/front.jpg"}]}"#.to_string(),
+            ),
+            _ => (404, String::new()),
+        }
+        });
+        let client = HttpClient::new().unwrap();
+        let archive = CoverArtArchive::new(server.url.clone());
+        let artwork = |release_id| {
+            tauri::async_runtime::block_on(archive.artwork(&client, &found(release_id))).unwrap()
+        };
+
+        assert_eq!(
+            artwork(Some("with-thumbnail")).as_deref(),
+            Some("https://example.com/front-1200.jpg")
+        );
+        assert_eq!(
+            artwork(Some("large-only")).as_deref(),
+            Some("https://example.com/front-500.jpg")
+        );
+        assert_eq!(
+            artwork(Some("original-only")).as_deref(),
+            Some("https://example.com/front.jpg")
+        );
+        assert_eq!(artwork(Some("missing")), None);
+        assert_eq!(server.requests().len(), 4);
+
+        assert_eq!(artwork(None), None);
+        assert_eq!(server.requests().len(), 4);
+    }
+}
@@ -0,0 +1,188 @@
+use std::io::Error;
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use super::{MetadataProvider, TrackMatch, TrackQuery};
+use crate::{http_client::HttpClient, music::ProviderKind};
+
+/// The iTunes Search API. Its results carry artwork, so it needs no separate
+/// artwork lookup.
+pub struct Itunes {
+    base_url: String,
+}
+
+impl Itunes {
+    pub fn new(base_url: String) -> Self {
+        Self { base_url }
+    }
+
+    async fn search_songs(
+        &self,
+        client: &HttpClient,
+        query: &TrackQuery,
+    ) -> Result<Vec<TrackMatch>, Error> {
+        let url = format!(
+            "{}/search?term={}",
+            self.base_url,
+            urlencoding::encode(&query.keyword)
+        );
+        let Some(text) = client.get_text(&url).await? else {
+            return Ok(Vec::new());
+        };
+        let response: MusicDataRes = serde_json::from_str(&text)
+            .map_err(|e| Error::other(format!("failed to parse response: {}", e)))?;
+
+        let mut songs: Vec<Body> = response
+            .results
+            .into_iter()
+            .filter(|music_data| {
+                music_data.kind.as_deref() == Some("song")
+                    && music_data.wrapper_type.as_deref() == Some("track")
+            })
+            .collect();
+        songs.sort_by_key(|music_data| {
+            music_data
+                .release_date
+                .as_deref()
+                .and_then(|s| s.parse::<DateTime<Utc>>().ok())
+        });
+
+        Ok(songs
+            .into_iter()
+            .map(|music_data| TrackMatch {
+                provider: Some(ProviderKind::Itunes),
+                title: music_data.track_name.unwrap_or_default(),
+                artist: music_data.artist_name.unwrap_or_default(),
+                album: music_data.collection_name.unwrap_or_default(),
+                duration: music_data
+                    .track_time_millis
+                    .map(|millis| millis as f64 / 1000.0),
+                artwork_url: music_data
+                    .artwork_url100
+                    .map(|url| url.replace("100x100", "1000x1000")),
+                release_id: None,
+                score: 0.0,
+            })
+            .collect())
+    }
+}
+
+impl MetadataProvider for Itunes {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Itunes
+    }
+
+    fn search<'a>(
+        &'a self,
+        client: &'a HttpClient,
+        query: &'a TrackQuery,
+    ) -> BoxFuture<'a, Result<Vec<TrackMatch>, Error>> {
+        Box::pin(self.search_songs(client, query))
+    }
+
+    fn artwork<'a>(
+        &'a self,
+        _client: &'a HttpClient,
+        _found: &'a TrackMatch,
+    ) -> BoxFuture<'a, Result<Option<String>, Error>> {
+        Box::pin(async { Ok(None) })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MusicDataRes {
+    pub result_count: u32,
+    pub results: Vec<Body>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Body {
+    pub wrapper_type: Option<String>,
+    pub kind: Option<String>,
+    pub artist_id: Option<i64>,
+    pub collection_id: Option<i64>,
+    pub track_id: Option<i64>,
+    pub artist_name: Option<String>,
+    pub collection_name: Option<String>,
+    pub track_name: Option<String>,
+    pub collection_censored_name: Option<String>,
+    pub track_censored_name: Option<String>,
+    pub artist_view_url: Option<String>,
+    pub collection_view_url: Option<String>,
+    pub track_view_url: Option<String>,
+    pub preview_url: Option<String>,
+    pub artwork_url30: Option<String>,
+    pub artwork_url60: Option<String>,
+    pub artwork_url100: Option<String>,
+    pub release_date: Option<String>,
+    pub collection_explicitness: Option<String>,
+    pub track_explicitness: Option<String>,
+    pub disc_count: Option<i32>,
+    pub disc_number: Option<i32>,
+    pub track_count: Option<i32>,
+    pub track_number: Option<i32>,
+    pub track_time_millis: Option<i64>,
+    pub country: Option<String>,
+    pub currency: Option<String>,
+    pub primary_genre_name: Option<String>,
+    pub is_streamable: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestServer;
+
+    #[test]
+    fn maps_songs_to_matches_oldest_first() {
+        let server = TestServer::start(|_| {
+            (
+                200,
+                r#"{"resultCount":3,"results":[
+                    {"wrapperType":"track","kind":"song","trackName":"Remaster","artistName":"Artist",
+                     "collectionName":"Best Of","trackTimeMillis":200500,"releaseDate":"2015-05-01T07:00:00Z",
+                     "artworkUrl100":"https://example.com/a/100x100bb.jpg"},
+                    {"wrapperType":"track","kind":"music-video","trackName":"Video"},
+                    {"wrapperType":"track","kind":"song","trackName":"Title","artistName":"Artist",
+                     "collectionName":"Album","trackTimeMillis":199000,"releaseDate":"1999-01-01T08:00:00Z",
+                     "artworkUrl100":"https://example.com/b/100x100bb.jpg"}
+                ]}"#
+                .to_string(),
+            )
+        });
+        let client = HttpClient::new().unwrap();
+        let itunes = Itunes::new(server.url.clone());
+        let query = TrackQuery::new(Some("Title".to_string()), Some("Artist".to_string()), None);
+
+        let found = tauri::async_runtime::block_on(itunes.search(&client, &query)).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].provider, Some(ProviderKind::Itunes));
+        assert_eq!(found[0].title, "Title");
+        assert_eq!(found[0].artist, "Artist");
+        assert_eq!(found[0].album, "Album");
+        assert_eq!(found[0].duration, Some(199.0));
+        assert_eq!(
+            found[0].artwork_url.as_deref(),
+            Some("https://example.com/b/1000x1000bb.jpg")
+        );
+        assert_eq!(found[1].title, "Remaster");
+        assert_eq!(server.requests(), ["/search?term=Artist%20%2B%20Title"]);
+
+        let artwork = tauri::async_runtime::block_on(itunes.artwork(&client, &found[0]));
+        assert_eq!(artwork.unwrap(), None);
+    }
+
+    #[test]
+    fn not_found_is_no_match() {
+        let server = TestServer::start(|_| (404, String::new()));
+        let client = HttpClient::new().unwrap();
+        let itunes = Itunes::new(server.url.clone());
+        let query = TrackQuery::from_keyword("nothing".to_string());
+        let found = tauri::async_runtime::block_on(itunes.search(&client, &query)).unwrap();
+        assert!(found.is_empty());
+    }
+}
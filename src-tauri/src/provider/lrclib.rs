@@ -0,0 +1,182 @@
+use std::io::Error;
+
+use futures::future::BoxFuture;
+use serde::Deserialize;
+
+use super::{MetadataProvider, TrackMatch, TrackQuery};
+use crate::{http_client::HttpClient, music::ProviderKind};
+
+/// LRCLIB, an open database of synchronized lyrics. It only serves lyrics.
+pub struct Lrclib {
+    base_url: String,
+}
+
+impl Lrclib {
+    pub fn new(base_url: String) -> Self {
+        Self { base_url }
+    }
+
+    async fn find_lyrics(
+        &self,
+        client: &HttpClient,
+        query: &TrackQuery,
+    ) -> Result<Option<String>, Error> {
+        let found = match (&query.title, &query.artist) {
+            (Some(title), Some(artist)) => self.get(client, title, artist, query).await?,
+            _ => self.search(client, &query.keyword).await?,
+        };
+        Ok(found.and_then(|lyrics| {
+            lyrics
+                .synced_lyrics
+                .or(lyrics.plain_lyrics)
+                .filter(|text| !text.trim().is_empty())
+        }))
+    }
+
+    /// Exact lookup by title and artist, narrowed by album and duration when known.
+    async fn get(
+        &self,
+        client: &HttpClient,
+        title: &str,
+        artist: &str,
+        query: &TrackQuery,
+    ) -> Result<Option<LyricsRes>, Error> {
+        let mut url = format!(
+            "{}/get?track_name={}&artist_name={}",
+            self.base_url,
+            urlencoding::encode(title),
+            urlencoding::encode(artist)
+        );
+        if let Some(album) = &query.album {
+            url.push_str(&format!("&album_name={}", urlencoding::encode(album)));
+        }
+        if let Some(duration) = query.duration {
+            url.push_str(&format!("&duration={}", duration.round() as u64));
+        }
+        let Some(text) = client.get_text(&url).await? else {
+            return Ok(None);
+        };
+        serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|e| Error::other(format!("failed to parse response: {}", e)))
+    }
+
+    async fn search(&self, client: &HttpClient, keyword: &str) -> Result<Option<LyricsRes>, Error> {
+        if keyword.trim().is_empty() {
+            return Ok(None);
+        }
+        let url = format!(
+            "{}/search?q={}",
+            self.base_url,
+            urlencoding::encode(keyword)
+        );
+        let Some(text) = client.get_text(&url).await? else {
+            return Ok(None);
+        };
+        let results: Vec<LyricsRes> = serde_json::from_str(&text)
+            .map_err(|e| Error::other(format!("failed to parse response: {}", e)))?;
+        Ok(results.into_iter().next())
+    }
+}
+
+impl MetadataProvider for Lrclib {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Lrclib
+    }
+
+    fn can_search(&self) -> bool {
+        false
+    }
+
+    fn search<'a>(
+        &'a self,
+        _client: &'a HttpClient,
+        _query: &'a TrackQuery,
+    ) -> BoxFuture<'a, Result<Vec<TrackMatch>, Error>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn artwork<'a>(
+        &'a self,
+        _client: &'a HttpClient,
+        _found: &'a TrackMatch,
+    ) -> BoxFuture<'a, Result<Option<String>, Error>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    fn lyrics<'a>(
+        &'a self,
+        client: &'a HttpClient,
+        query: &'a TrackQuery,
+    ) -> BoxFuture<'a, Result<Option<String>, Error>> {
+        Box::pin(self.find_lyrics(client, query))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LyricsRes {
+    #[serde(default)]
+    plain_lyrics: Option<String>,
+    #[serde(default)]
+    synced_lyrics: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestServer;
+
+    #[test]
+    fn gets_synchronized_lyrics_by_title_and_artist() {
+        let server = TestServer::start(|_| {
+            (
+                200,
+                r#"{"plainLyrics":"Hello","syncedLyrics":"[00:01.00]Hello"}"#.to_string(),
+            )
+        });
+        let client = HttpClient::new().unwrap();
+        let lrclib = Lrclib::new(server.url.clone());
+        let query = TrackQuery::new(
+            Some("Title".to_string()),
+            Some("Artist".to_string()),
+            Some("Album".to_string()),
+        )
+        .with_duration(Some(185.6));
+
+        let lyrics = tauri::async_runtime::block_on(lrclib.lyrics(&client, &query)).unwrap();
+        assert_eq!(lyrics.as_deref(), Some("[00:01.00]Hello"));
+        assert_eq!(
+            server.requests(),
+            ["/get?track_name=Title&artist_name=Artist&album_name=Album&duration=186"]
+        );
+    }
+
+    #[test]
+    fn searches_by_keyword_without_title_and_artist() {
+        let server = TestServer::start(|_| {
+            (
+                200,
+                r#"[{"plainLyrics":"First","syncedLyrics":null},{"plainLyrics":"Second"}]"#
+                    .to_string(),
+            )
+        });
+        let client = HttpClient::new().unwrap();
+        let lrclib = Lrclib::new(server.url.clone());
+        let query = TrackQuery::from_keyword("some song".to_string());
+
+        let lyrics = tauri::async_runtime::block_on(lrclib.lyrics(&client, &query)).unwrap();
+        assert_eq!(lyrics.as_deref(), Some("First"));
+        assert_eq!(server.requests(), ["/search?q=some%20song"]);
+    }
+
+    #[test]
+    fn blank_lyrics_are_none() {
+        let server = TestServer::start(|_| (200, r#"{"plainLyrics":"  "}"#.to_string()));
+        let client = HttpClient::new().unwrap();
+        let lrclib = Lrclib::new(server.url.clone());
+        let query = TrackQuery::new(Some("Title".to_string()), Some("Artist".to_string()), None);
+        let lyrics = tauri::async_runtime::block_on(lrclib.lyrics(&client, &query)).unwrap();
+        assert_eq!(lyrics, None);
+    }
+}
@@ -0,0 +1,122 @@
+use std::io::Error;
+
+use futures::future::BoxFuture;
+
+use crate::{
+    http_client::HttpClient,
+    music::{ProviderKind, ProviderSetting, TrackMatch},
+};
+
+mod cover_art_archive;
+mod itunes;
+mod lrclib;
+mod musicbrainz;
+
+pub use cover_art_archive::CoverArtArchive;
+pub use itunes::Itunes;
+pub use lrclib::Lrclib;
+pub use musicbrainz::MusicBrainz;
+
+/// What is known about a track before looking it up.
+#[derive(Clone, Debug, Default)]
+pub struct TrackQuery {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Free-text search used by providers without structured queries.
+    pub keyword: String,
+    /// Decoded length of the file in seconds, used to rank the results.
+    pub duration: Option<f64>,
+}
+
+impl TrackQuery {
+    pub fn new(title: Option<String>, artist: Option<String>, album: Option<String>) -> Self {
+        let keyword = match (&artist, &title) {
+            (Some(artist), Some(title)) => format!("{} + {}", artist, title),
+            (None, Some(title)) => title.clone(),
+            (Some(artist), None) => artist.clone(),
+            (None, None) => String::new(),
+        };
+        Self {
+            title,
+            artist,
+            album,
+            keyword,
+            duration: None,
+        }
+    }
+
+    pub fn with_duration(self, duration: Option<f64>) -> Self {
+        Self { duration, ..self }
+    }
+
+    pub fn from_keyword(keyword: String) -> Self {
+        Self {
+            keyword,
+            ..Default::default()
+        }
+    }
+}
+
+/// An online source of track metadata and artwork.
+pub trait MetadataProvider: Send + Sync {
+    fn kind(&self) -> ProviderKind;
+
+    /// Whether the provider searches for recordings at all; artwork and lyrics
+    /// sources do not.
+    fn can_search(&self) -> bool {
+        true
+    }
+
+    /// Searches for recordings matching `query`, best match first.
+    fn search<'a>(
+        &'a self,
+        client: &'a HttpClient,
+        query: &'a TrackQuery,
+    ) -> BoxFuture<'a, Result<Vec<TrackMatch>, Error>>;
+
+    /// Finds a cover for a recording any provider returned. Providers that cannot
+    /// resolve artwork for it return `None`.
+    fn artwork<'a>(
+        &'a self,
+        client: &'a HttpClient,
+        found: &'a TrackMatch,
+    ) -> BoxFuture<'a, Result<Option<String>, Error>>;
+
+    /// Finds lyrics for a track, LRC formatted when they are synchronized. Providers
+    /// without lyrics keep this default.
+    fn lyrics<'a>(
+        &'a self,
+        _client: &'a HttpClient,
+        _query: &'a TrackQuery,
+    ) -> BoxFuture<'a, Result<Option<String>, Error>> {
+        Box::pin(async { Ok(None) })
+    }
+}
+
+/// Builds the enabled providers in their configured order.
+pub fn from_settings(settings: &[ProviderSetting]) -> Vec<Box<dyn MetadataProvider>> {
+    settings
+        .iter()
+        .filter(|setting| setting.enabled)
+        .map(|setting| {
+            let base_url = setting.base_url.trim_end_matches('/').to_string();
+            let provider: Box<dyn MetadataProvider> = match setting.kind {
+                ProviderKind::Itunes => Box::new(Itunes::new(base_url)),
+                ProviderKind::MusicBrainz => Box::new(MusicBrainz::new(base_url)),
+                ProviderKind::CoverArtArchive => Box::new(CoverArtArchive::new(base_url)),
+                ProviderKind::Lrclib => Box::new(Lrclib::new(base_url)),
+            };
+            provider
+        })
+        .collect()
+}
+
+pub fn default_base_url(kind: ProviderKind) -> &'static str {
+    match kind {
+        ProviderKind::Itunes => "https://itunes.apple.com/cn",
+        ProviderKind::MusicBrainz => "https://musicbrainz.org/ws/2",
+        ProviderKind::CoverArtArchive => "https://coverartarchive.org",
+        ProviderKind::Lrclib => "https://lrclib.net/api",
+    }
+}
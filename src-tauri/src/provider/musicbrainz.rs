@@ -0,0 +1,196 @@
+use std::io::Error;
+
+use futures::future::BoxFuture;
+use serde::Deserialize;
+
+use super::{MetadataProvider, TrackMatch, TrackQuery};
+use crate::{http_client::HttpClient, music::ProviderKind};
+
+const SEARCH_LIMIT: u32 = 10;
+
+/// The MusicBrainz recording search. It has no artwork of its own but returns the
+/// release ids the Cover Art Archive is keyed by.
+pub struct MusicBrainz {
+    base_url: String,
+}
+
+impl MusicBrainz {
+    pub fn new(base_url: String) -> Self {
+        Self { base_url }
+    }
+
+    async fn search_recordings(
+        &self,
+        client: &HttpClient,
+        query: &TrackQuery,
+    ) -> Result<Vec<TrackMatch>, Error> {
+        let url = format!(
+            "{}/recording?fmt=json&limit={}&query={}",
+            self.base_url,
+            SEARCH_LIMIT,
+            urlencoding::encode(&lucene_query(query))
+        );
+        let Some(text) = client.get_text(&url).await? else {
+            return Ok(Vec::new());
+        };
+        let response: RecordingSearchRes = serde_json::from_str(&text)
+            .map_err(|e| Error::other(format!("failed to parse response: {}", e)))?;
+
+        Ok(response
+            .recordings
+            .into_iter()
+            .map(|recording| {
+                let artist = recording
+                    .artist_credit
+                    .iter()
+                    .map(|credit| format!("{}{}", credit.name, credit.joinphrase))
+                    .collect::<String>();
+                let release = recording.releases.into_iter().next();
+                TrackMatch {
+                    provider: Some(ProviderKind::MusicBrainz),
+                    title: recording.title,
+                    artist: artist.trim().to_string(),
+                    album: release
+                        .as_ref()
+                        .map(|release| release.title.clone())
+                        .unwrap_or_default(),
+                    duration: recording.length.map(|millis| millis as f64 / 1000.0),
+                    artwork_url: None,
+                    release_id: release.map(|release| release.id),
+                    score: 0.0,
+                }
+            })
+            .collect())
+    }
+}
+
+impl MetadataProvider for MusicBrainz {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::MusicBrainz
+    }
+
+    fn search<'a>(
+        &'a self,
+        client: &'a HttpClient,
+        query: &'a TrackQuery,
+    ) -> BoxFuture<'a, Result<Vec<TrackMatch>, Error>> {
+        Box::pin(self.search_recordings(client, query))
+    }
+
+    fn artwork<'a>(
+        &'a self,
+        _client: &'a HttpClient,
+        _found: &'a TrackMatch,
+    ) -> BoxFuture<'a, Result<Option<String>, Error>> {
+        Box::pin(async { Ok(None) })
+    }
+}
+
+/// Searches by field when title or artist are known and by free text otherwise.
+fn lucene_query(query: &TrackQuery) -> String {
+    let mut terms = Vec::new();
+    if let Some(title) = &query.title {
+        terms.push(format!("recording:\"{}\"", escape(title)));
+    }
+    if let Some(artist) = &query.artist {
+        terms.push(format!("artist:\"{}\"", escape(artist)));
+    }
+    if let Some(album) = &query.album {
+        terms.push(format!("release:\"{}\"", escape(album)));
+    }
+    if terms.is_empty() {
+        escape(&query.keyword)
+    } else {
+        terms.join(" AND ")
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordingSearchRes {
+    #[serde(default)]
+    recordings: Vec<Recording>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Recording {
+    title: String,
+    length: Option<u64>,
+    #[serde(default, rename = "artist-credit")]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(default)]
+    releases: Vec<Release>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtistCredit {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    id: String,
+    title: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestServer;
+
+    #[test]
+    fn maps_recordings_to_matches() {
+        let server = TestServer::start(|_| {
+            (
+                200,
+                r#"{"recordings":[
+                    {"id":"r1","title":"Title","length":241000,
+                     "artist-credit":[{"name":"One","joinphrase":" feat. "},{"name":"Two"}],
+                     "releases":[{"id":"release-1","title":"Album"},{"id":"release-2","title":"Single"}]},
+                    {"id":"r2","title":"Live"}
+                ]}"#
+                .to_string(),
+            )
+        });
+        let client = HttpClient::new().unwrap();
+        let musicbrainz = MusicBrainz::new(server.url.clone());
+        let query = TrackQuery::new(
+            Some("Title".to_string()),
+            Some("One \"1\"".to_string()),
+            None,
+        );
+
+        let found = tauri::async_runtime::block_on(musicbrainz.search(&client, &query)).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].provider, Some(ProviderKind::MusicBrainz));
+        assert_eq!(found[0].title, "Title");
+        assert_eq!(found[0].artist, "One feat. Two");
+        assert_eq!(found[0].album, "Album");
+        assert_eq!(found[0].duration, Some(241.0));
+        assert_eq!(found[0].release_id.as_deref(), Some("release-1"));
+        assert_eq!(found[0].artwork_url, None);
+        assert_eq!(found[1].artist, "");
+        assert_eq!(found[1].album, "");
+        assert_eq!(found[1].release_id, None);
+
+        let expected_query = urlencoding::encode(r#"recording:"Title" AND artist:"One \"1\"""#);
+        assert_eq!(
+            server.requests(),
+            [format!(
+                "/recording?fmt=json&limit=10&query={}",
+                expected_query
+            )]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_keyword() {
+        let query = TrackQuery::from_keyword("some file name".to_string());
+        assert_eq!(lucene_query(&query), "some file name");
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    thread,
+};
+
+use log::warn;
+use rayon::prelude::*;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{
+    cache,
+    music::{MusicFile, TrackRating},
+    smart,
+    state::{FileInfoState, LibraryState, MusicFilesState},
+    store, tag_editor,
+};
+
+/// Highest rating, in stars.
+const MAX_STARS: f32 = 5.0;
+
+/// Shuffle weight of an unrated track, as if it had this many stars.
+const UNRATED_STARS: f32 = 2.5;
+
+/// Extra shuffle weight of a favourite, in stars.
+const FAVOURITE_BONUS_STARS: f32 = 3.0;
+
+/// Checks that `rating` is a whole or half number of stars from 0 to 5.
+pub fn validate(rating: Option<f32>) -> Result<Option<f32>, String> {
+    match rating {
+        Some(stars) if !(0.0..=MAX_STARS).contains(&stars) || (stars * 2.0).fract() != 0.0 => Err(
+            format!("{} is not a rating from 0 to 5 in half stars", stars),
+        ),
+        rating => Ok(rating),
+    }
+}
+
+/// Gives tracks the ratings stored for them, as when they are added to a playlist.
+pub fn apply_stored(app: &AppHandle, tracks: &mut [MusicFile]) {
+    apply(app, tracks, false);
+}
+
+/// Like `apply_stored`, for files new to the library: files without a stored
+/// rating take the one in their tags, which is then stored.
+pub fn apply_scanned(app: &AppHandle, tracks: &mut [MusicFile]) {
+    apply(app, tracks, true);
+}
+
+fn apply(app: &AppHandle, tracks: &mut [MusicFile], from_tags: bool) {
+    let mut ratings = store::load_ratings(app);
+    if tracks.is_empty() || (ratings.is_empty() && !from_tags) {
+        return;
+    }
+    let identities = identities(app, tracks);
+    let mut changed = false;
+    for music in tracks.iter_mut() {
+        let Some(identity) = identities.get(&music.path) else {
+            continue;
+        };
+        let key = rating_key(identity, music);
+        // Earlier versions stored ratings by path.
+        if !ratings.contains_key(&key)
+            && let Some(rating) = ratings.remove(&music.resume_key())
+        {
+            ratings.insert(key.clone(), rating);
+            changed = true;
+        }
+        let rating = match ratings.get(&key) {
+            Some(rating) => *rating,
+            // Cue sheet tracks share their file, so the file's rating is not theirs.
+            None if from_tags && music.segment.is_none() => {
+                let Some(stars) = tag_editor::read_rating(&music.path) else {
+                    continue;
+                };
+                let rating = TrackRating {
+                    rating: Some(stars),
+                    favourite: false,
+                };
+                ratings.insert(key, rating);
+                changed = true;
+                rating
+            }
+            None => continue,
+        };
+        music.set_track_rating(rating);
+    }
+    if changed {
+        store::store_ratings(app, &ratings);
+    }
+}
+
+/// Names the stored rating of a track by the identity of its file, so it follows
+/// the file through renames and moves. Cue sheet tracks add their start.
+fn rating_key(identity: &str, music: &MusicFile) -> String {
+    match music.segment {
+        Some(segment) => format!("{}#{}", identity, segment.start),
+        None => identity.to_string(),
+    }
+}
+
+/// The identity of each file `tracks` play, by path.
+fn identities(app: &AppHandle, tracks: &[MusicFile]) -> HashMap<String, String> {
+    let file_infos = app
+        .state::<Mutex<FileInfoState>>()
+        .lock()
+        .map(|state| state.get_cloned())
+        .unwrap_or_default();
+    let paths: HashSet<&str> = tracks.iter().map(|music| music.path.as_str()).collect();
+    paths
+        .into_par_iter()
+        .map(|path| {
+            let identity = cache::current_identity(path, file_infos.get(path));
+            (path.to_string(), identity)
+        })
+        .collect()
+}
+
+/// Lets `update` change the rating of track `id` of the now playing list or the
+/// library, then stores it and mirrors it into every list holding the track. Emits
+/// `rating-changed` with the updated track.
+pub fn rate<F>(app: &AppHandle, id: &str, update: F) -> Result<MusicFile, String>
+where
+    F: FnOnce(&mut TrackRating),
+{
+    let mut music = find_track(app, id).ok_or_else(|| format!("track {} not found", id))?;
+    let key = music.resume_key();
+    let mut rating = music.track_rating();
+    let previous = rating;
+    update(&mut rating);
+    music.set_track_rating(rating);
+
+    let identities = identities(app, std::slice::from_ref(&music));
+    let stored_key = identities
+        .get(&music.path)
+        .map(|identity| rating_key(identity, &music))
+        .unwrap_or_else(|| key.clone());
+    let mut ratings = store::load_ratings(app);
+    // Drops the path-keyed rating of earlier versions.
+    ratings.remove(&key);
+    if rating.is_unset() {
+        ratings.remove(&stored_key);
+    } else {
+        ratings.insert(stored_key, rating);
+    }
+    store::store_ratings(app, &ratings);
+    mirror(app, &key, rating);
+
+    // Cue sheet tracks share their file, so only whole files carry the rating.
+    if rating.rating != previous.rating
+        && music.segment.is_none()
+        && store::load_settings(app).write_rating_tags
+    {
+        let path = music.path.clone();
+        thread::spawn(move || {
+            if let Err(err) = tag_editor::write_rating(&path, rating.rating) {
+                warn!("failed to write rating of {}: {}", path, err);
+            }
+        });
+    }
+
+    let _ = app.emit("rating-changed", music.clone());
+    smart::spawn_refresh(app, smart::RefreshScope::Listening);
+    Ok(music)
+}
+
+/// How likely weighted shuffle is to pick `music`, relative to other tracks.
+pub fn shuffle_weight(music: &MusicFile) -> f32 {
+    let stars = music.rating.unwrap_or(UNRATED_STARS);
+    let bonus = if music.favourite {
+        FAVOURITE_BONUS_STARS
+    } else {
+        0.0
+    };
+    1.0 + stars + bonus
+}
+
+fn find_track(app: &AppHandle, id: &str) -> Option<MusicFile> {
+    let in_playlist = app
+        .state::<Mutex<MusicFilesState>>()
+        .lock()
+        .ok()
+        .and_then(|state| state.get().iter().find(|music| music.id == id).cloned());
+    in_playlist.or_else(|| {
+        app.state::<Mutex<LibraryState>>()
+            .lock()
+            .ok()
+            .and_then(|library| {
+                library
+                    .tracks()
+                    .iter()
+                    .find(|music| music.id == id)
+                    .cloned()
+            })
+    })
+}
+
+/// Updates the library and every playlist entry of the track named by `key`.
+fn mirror(app: &AppHandle, key: &str, rating: TrackRating) {
+    let update = |tracks: &mut [MusicFile]| {
+        let mut changed = false;
+        for music in tracks.iter_mut() {
+            if music.resume_key() == key && music.track_rating() != rating {
+                music.set_track_rating(rating);
+                changed = true;
+            }
+        }
+        changed
+    };
+
+    if let Ok(mut library) = app.state::<Mutex<LibraryState>>().lock() {
+        let mut tracks = library.tracks_cloned();
+        if update(&mut tracks) {
+            library.set_tracks(tracks.clone());
+            store::store_library_tracks(app, &tracks);
+        }
+    }
+
+    if let Ok(mut state) = app.state::<Mutex<MusicFilesState>>().lock() {
+        let mut playlist = state.get_cloned();
+        if update(&mut playlist) {
+            state.set(playlist.clone());
+            store::store_playlist(app, &playlist);
+        }
+    }
+
+    let current = store::load_current_playlist_id(app);
+    store::update_playlists(app, |playlists| {
+        for stored in playlists
+            .iter_mut()
+            .filter(|stored| Some(&stored.id) != current.as_ref())
+        {
+            update(&mut stored.tracks);
+        }
+    });
+}
@@ -0,0 +1,215 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use log::warn;
+
+use crate::music::LibraryFolder;
+
+/// Per-directory ignore file, one glob per line, honoured for that directory and below.
+pub const IGNORE_FILENAME: &str = ".anchorignore";
+
+/// Hidden files and directories are excluded unless a folder overrides its exclude list.
+pub const DEFAULT_EXCLUDE: [&str; 1] = [".*"];
+
+/// A compiled list of gitignore-style globs.
+///
+/// Patterns without a slash match a name at any depth, patterns containing a slash are
+/// anchored to the directory the list belongs to, and a trailing slash restricts a
+/// pattern to directories. Empty lines and lines starting with `#` are ignored.
+#[derive(Default)]
+pub struct PatternSet {
+    any: GlobSet,
+    dirs: GlobSet,
+}
+
+impl PatternSet {
+    pub fn parse<S: AsRef<str>>(patterns: &[S]) -> Result<Self, globset::Error> {
+        let mut any = GlobSetBuilder::new();
+        let mut dirs = GlobSetBuilder::new();
+        for pattern in patterns {
+            let pattern = pattern.as_ref().trim();
+            if pattern.is_empty() || pattern.starts_with('#') {
+                continue;
+            }
+            let (pattern, dir_only) = match pattern.strip_suffix('/') {
+                Some(pattern) => (pattern, true),
+                None => (pattern, false),
+            };
+            let glob = if pattern.contains('/') {
+                pattern.trim_start_matches('/').to_string()
+            } else {
+                format!("**/{}", pattern)
+            };
+            let glob = GlobBuilder::new(&glob).literal_separator(true).build()?;
+            if dir_only {
+                dirs.add(glob);
+            } else {
+                any.add(glob);
+            }
+        }
+        Ok(Self {
+            any: any.build()?,
+            dirs: dirs.build()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.any.is_empty() && self.dirs.is_empty()
+    }
+
+    pub fn is_match(&self, relative: &Path, is_dir: bool) -> bool {
+        self.any.is_match(relative) || (is_dir && self.dirs.is_match(relative))
+    }
+}
+
+/// Filters applied while scanning a library folder.
+pub struct ScanRules {
+    include: PatternSet,
+    exclude: PatternSet,
+    pub min_size: u64,
+    pub min_duration: f64,
+}
+
+impl Default for ScanRules {
+    fn default() -> Self {
+        Self {
+            include: PatternSet::default(),
+            exclude: PatternSet::parse(&DEFAULT_EXCLUDE).unwrap_or_default(),
+            min_size: 0,
+            min_duration: 0.0,
+        }
+    }
+}
+
+impl ScanRules {
+    pub fn from_folder(folder: &LibraryFolder) -> Result<Self, String> {
+        let include = PatternSet::parse(&folder.include)
+            .map_err(|e| format!("invalid include pattern: {}", e))?;
+        let exclude = PatternSet::parse(&folder.exclude)
+            .map_err(|e| format!("invalid exclude pattern: {}", e))?;
+        Ok(Self {
+            include,
+            exclude,
+            min_size: folder.min_size,
+            min_duration: folder.min_duration,
+        })
+    }
+
+    /// Whether a path relative to the library folder is filtered out. Include patterns
+    /// only narrow down files; directories are always descended into unless excluded.
+    pub fn excludes(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.exclude.is_match(relative, is_dir) {
+            return true;
+        }
+        !is_dir && !self.include.is_empty() && !self.include.is_match(relative, false)
+    }
+}
+
+/// The parsed contents of one `.anchorignore` file.
+pub struct IgnoreFile {
+    base: PathBuf,
+    patterns: PatternSet,
+}
+
+impl IgnoreFile {
+    pub fn load(dir: &Path) -> Option<Self> {
+        let path = dir.join(IGNORE_FILENAME);
+        let content = fs::read_to_string(&path).ok()?;
+        let lines: Vec<&str> = content.lines().collect();
+        match PatternSet::parse(&lines) {
+            Ok(patterns) => Some(Self {
+                base: dir.to_path_buf(),
+                patterns,
+            }),
+            Err(err) => {
+                warn!("ignoring invalid {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    pub fn ignores(&self, path: &Path, is_dir: bool) -> bool {
+        path.strip_prefix(&self.base)
+            .is_ok_and(|relative| self.patterns.is_match(relative, is_dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn rules(include: &[&str], exclude: &[&str]) -> ScanRules {
+        ScanRules {
+            include: PatternSet::parse(include).unwrap(),
+            exclude: PatternSet::parse(exclude).unwrap(),
+            min_size: 0,
+            min_duration: 0.0,
+        }
+    }
+
+    #[test]
+    fn names_match_at_any_depth_and_slashes_anchor() {
+        let patterns = PatternSet::parse(&["*.log", "/Live/*.flac"]).unwrap();
+        assert!(patterns.is_match(Path::new("rip.log"), false));
+        assert!(patterns.is_match(Path::new("Album/CD1/rip.log"), false));
+        assert!(patterns.is_match(Path::new("Live/01.flac"), false));
+        assert!(!patterns.is_match(Path::new("Album/Live/01.flac"), false));
+        assert!(!patterns.is_match(Path::new("Live/Disc 1/01.flac"), false));
+    }
+
+    #[test]
+    fn trailing_slashes_only_match_directories() {
+        let patterns = PatternSet::parse(&["Scans/"]).unwrap();
+        assert!(patterns.is_match(Path::new("Album/Scans"), true));
+        assert!(!patterns.is_match(Path::new("Album/Scans"), false));
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let patterns = PatternSet::parse(&["", "  ", "# *.mp3"]).unwrap();
+        assert!(patterns.is_empty());
+    }
+
+    #[test]
+    fn hidden_entries_are_excluded_by_default() {
+        let rules = ScanRules::default();
+        assert!(rules.excludes(Path::new(".git"), true));
+        assert!(rules.excludes(Path::new("Album/.cover.jpg"), false));
+        assert!(!rules.excludes(Path::new("Album/01.mp3"), false));
+    }
+
+    #[test]
+    fn includes_narrow_files_but_not_directories() {
+        let rules = rules(&["*.flac"], &["Bootlegs/"]);
+        assert!(!rules.excludes(Path::new("Album/01.flac"), false));
+        assert!(rules.excludes(Path::new("Album/01.mp3"), false));
+        assert!(!rules.excludes(Path::new("Album"), true));
+        assert!(rules.excludes(Path::new("Bootlegs"), true));
+    }
+
+    #[test]
+    fn ignore_files_apply_below_their_directory() {
+        let dir = TempDir::new();
+        fs::write(
+            dir.path().join(IGNORE_FILENAME),
+            "# demos\n*.demo.mp3\nOuttakes/\n",
+        )
+        .unwrap();
+        let ignore = IgnoreFile::load(dir.path()).unwrap();
+        assert!(ignore.ignores(&dir.path().join("Album/01.demo.mp3"), false));
+        assert!(ignore.ignores(&dir.path().join("Album/Outtakes"), true));
+        assert!(!ignore.ignores(&dir.path().join("Album/01.mp3"), false));
+        assert!(!ignore.ignores(Path::new("/elsewhere/01.demo.mp3"), false));
+    }
+
+    #[test]
+    fn invalid_ignore_files_are_skipped() {
+        let dir = TempDir::new();
+        fs::write(dir.path().join(IGNORE_FILENAME), "[unclosed\n").unwrap();
+        assert!(IgnoreFile::load(dir.path()).is_none());
+    }
+}
@@ -0,0 +1,720 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs,
+    sync::Mutex,
+    thread,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use chrono::{NaiveDate, Utc};
+use rayon::prelude::*;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{
+    file_reader::{self, AudioSniff},
+    music::{
+        LimitKind, MusicFile, Rule, RuleField, RuleGroup, RuleNode, RuleOperator, SmartRules,
+        TrackFacts, TrackStats,
+    },
+    state::{LibraryState, MusicFilesState, SmartPlaylistState},
+    stats, store, tag_editor,
+};
+
+const SECONDS_PER_DAY: f64 = 24.0 * 60.0 * 60.0;
+const BYTES_PER_MEGABYTE: f64 = 1024.0 * 1024.0;
+
+/// How long a refresh waits for further changes before it runs.
+const REFRESH_DELAY: Duration = Duration::from_secs(2);
+
+/// The scope of the refresh waiting out `REFRESH_DELAY`, if one is.
+static PENDING_REFRESH: Mutex<Option<RefreshScope>> = Mutex::new(None);
+
+/// What changed since the smart playlists were last evaluated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefreshScope {
+    /// Tracks were added, removed or retagged; every playlist may change.
+    Library,
+    /// A play was logged or a rating changed; only playlists whose rules read
+    /// plays or ratings may change.
+    Listening,
+}
+
+/// Refreshes the smart playlists in the background `REFRESH_DELAY` after the
+/// first change, folding in those that come in meanwhile, so a burst of plays or
+/// edits costs a single refresh.
+pub fn spawn_refresh(app: &AppHandle, scope: RefreshScope) {
+    let Ok(mut pending) = PENDING_REFRESH.lock() else {
+        return;
+    };
+    if let Some(pending) = pending.as_mut() {
+        if scope == RefreshScope::Library {
+            *pending = scope;
+        }
+        return;
+    }
+    *pending = Some(scope);
+
+    let app = app.clone();
+    thread::spawn(move || {
+        thread::sleep(REFRESH_DELAY);
+        let scope = PENDING_REFRESH
+            .lock()
+            .ok()
+            .and_then(|mut pending| pending.take());
+        if let Some(scope) = scope {
+            refresh(&app, scope);
+        }
+    });
+}
+
+/// Re-evaluates the smart playlists `scope` may affect against the library,
+/// storing those whose tracks changed and emitting them as `playlist-changed`.
+/// The now playing list is updated in place when it is one of them.
+pub fn refresh(app: &AppHandle, scope: RefreshScope) {
+    let smart: Vec<(String, SmartRules)> = store::load_playlists(app)
+        .into_iter()
+        .filter_map(|playlist| Some((playlist.id, playlist.smart?)))
+        .filter(|(_, rules)| scope == RefreshScope::Library || reads_listening(rules))
+        .collect();
+    if smart.is_empty() {
+        return;
+    }
+
+    let smart_state = app.state::<Mutex<SmartPlaylistState>>();
+    let Ok(mut state) = smart_state.lock() else {
+        return;
+    };
+    let mut results: HashMap<String, Vec<MusicFile>> = smart
+        .iter()
+        .map(|(id, rules)| (id.clone(), evaluate(app, rules, state.facts_mut())))
+        .collect();
+
+    let changed = store::update_playlists(app, |playlists| {
+        let mut changed = Vec::new();
+        for playlist in playlists.iter_mut() {
+            let Some(tracks) = results.remove(&playlist.id) else {
+                continue;
+            };
+            if serde_json::to_value(&tracks).ok() != serde_json::to_value(&playlist.tracks).ok() {
+                playlist.tracks = tracks;
+                changed.push(playlist.clone());
+            }
+        }
+        changed
+    });
+    if changed.is_empty() {
+        return;
+    }
+
+    let current = store::load_current_playlist_id(app);
+    for playlist in changed {
+        if Some(&playlist.id) == current.as_ref()
+            && let Ok(mut music_files_state) = app.state::<Mutex<MusicFilesState>>().lock()
+        {
+            music_files_state.set(playlist.tracks.clone());
+        }
+        let _ = app.emit("playlist-changed", playlist);
+    }
+}
+
+/// The library tracks matching `rules`, sorted and limited.
+pub fn preview(app: &AppHandle, rules: &SmartRules) -> Vec<MusicFile> {
+    let smart_state = app.state::<Mutex<SmartPlaylistState>>();
+    match smart_state.lock() {
+        Ok(mut state) => evaluate(app, rules, state.facts_mut()),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn evaluate(
+    app: &AppHandle,
+    rules: &SmartRules,
+    cache: &mut HashMap<String, TrackFacts>,
+) -> Vec<MusicFile> {
+    let tracks = app
+        .state::<Mutex<LibraryState>>()
+        .lock()
+        .map(|library| library.tracks_cloned())
+        .unwrap_or_default();
+
+    let fields = rule_fields(rules);
+    let needs_stats = fields.iter().any(|field| {
+        matches!(
+            field,
+            RuleField::PlayCount | RuleField::SkipCount | RuleField::LastPlayed
+        )
+    });
+    let context = Context {
+        stats: if needs_stats {
+            stats::track_stats(&store::load_play_log(app))
+        } else {
+            HashMap::new()
+        },
+        facts: read_facts(&tracks, &fields, cache),
+        now: Utc::now().timestamp(),
+    };
+    context.select(tracks, rules)
+}
+
+/// Every field `rules` filter, sort or limit by.
+fn rule_fields(rules: &SmartRules) -> Vec<RuleField> {
+    let mut fields = Vec::new();
+    collect_fields(&rules.group, &mut fields);
+    fields.extend(rules.sort.map(|sort| sort.field));
+    if rules
+        .limit
+        .is_some_and(|limit| limit.kind == LimitKind::Minutes)
+    {
+        fields.push(RuleField::Duration);
+    }
+    fields
+}
+
+/// Whether `rules` read the play log or ratings, so plays and rating changes
+/// can change their tracks.
+fn reads_listening(rules: &SmartRules) -> bool {
+    rule_fields(rules).iter().any(|field| {
+        matches!(
+            field,
+            RuleField::PlayCount | RuleField::SkipCount | RuleField::LastPlayed | RuleField::Rating
+        )
+    })
+}
+
+fn collect_fields(group: &RuleGroup, fields: &mut Vec<RuleField>) {
+    for node in &group.rules {
+        match node {
+            RuleNode::Rule(rule) => fields.push(rule.field),
+            RuleNode::Group(group) => collect_fields(group, fields),
+        }
+    }
+}
+
+/// Facts of every track, reusing those cached for files that did not change and
+/// only probing or reading tags when one of `fields` needs it.
+fn read_facts(
+    tracks: &[MusicFile],
+    fields: &[RuleField],
+    cache: &mut HashMap<String, TrackFacts>,
+) -> HashMap<String, TrackFacts> {
+    let needs_probe = fields
+        .iter()
+        .any(|field| matches!(field, RuleField::Duration | RuleField::Codec));
+    let needs_tags = fields.iter().any(|field| {
+        matches!(
+            field,
+            RuleField::AlbumArtist | RuleField::Genre | RuleField::Year | RuleField::Comment
+        )
+    });
+
+    let facts: Vec<(String, TrackFacts)> = tracks
+        .par_iter()
+        .filter_map(|music| {
+            let metadata = fs::metadata(file_reader::local_path(&music.path)).ok()?;
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_secs() as i64);
+            let mut facts = cache
+                .get(&music.path)
+                .filter(|cached| cached.modified == modified && cached.size == metadata.len())
+                .cloned()
+                .unwrap_or_else(|| TrackFacts {
+                    modified,
+                    size: metadata.len(),
+                    ..Default::default()
+                });
+            if needs_probe && !facts.probed {
+                let (sniff, codec) =
+                    file_reader::sniff_audio_codec(&file_reader::local_path(&music.path));
+                facts.duration = match sniff {
+                    AudioSniff::Playable(duration) => duration,
+                    _ => None,
+                };
+                facts.codec = codec;
+                facts.probed = true;
+            }
+            if needs_tags && facts.tags.is_none() {
+                facts.tags = Some(tag_editor::read_tags(&music.path).unwrap_or_default());
+            }
+            Some((music.path.clone(), facts))
+        })
+        .collect();
+
+    let facts: HashMap<String, TrackFacts> = facts.into_iter().collect();
+    cache.extend(
+        facts
+            .iter()
+            .map(|(path, facts)| (path.clone(), facts.clone())),
+    );
+    facts
+}
+
+struct Context {
+    stats: HashMap<String, TrackStats>,
+    facts: HashMap<String, TrackFacts>,
+    now: i64,
+}
+
+impl Context {
+    /// The `tracks` matching `rules`, sorted and limited.
+    fn select(&self, tracks: Vec<MusicFile>, rules: &SmartRules) -> Vec<MusicFile> {
+        let mut matched: Vec<MusicFile> = tracks
+            .into_iter()
+            .filter(|music| music.unsupported.is_none())
+            .filter(|music| self.matches_group(music, &rules.group))
+            .collect();
+        if let Some(sort) = rules.sort {
+            matched.sort_by(|a, b| {
+                let (a_value, b_value) = (self.value(a, sort.field), self.value(b, sort.field));
+                match (a_value, b_value) {
+                    (Some(a_value), Some(b_value)) if sort.descending => b_value.compare(&a_value),
+                    (Some(a_value), Some(b_value)) => a_value.compare(&b_value),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                }
+            });
+        }
+
+        let Some(limit) = rules.limit else {
+            return matched;
+        };
+        let mut total = 0.0;
+        matched
+            .into_iter()
+            .take_while(|music| {
+                total += match limit.kind {
+                    LimitKind::Count => 1.0,
+                    LimitKind::Minutes => self.duration(music).unwrap_or_default() / 60.0,
+                    LimitKind::Megabytes => {
+                        self.facts.get(&music.path).map_or(0, |facts| facts.size) as f64
+                            / BYTES_PER_MEGABYTE
+                    }
+                };
+                total <= limit.value
+            })
+            .collect()
+    }
+
+    fn matches_group(&self, music: &MusicFile, group: &RuleGroup) -> bool {
+        let matches = |node: &RuleNode| match node {
+            RuleNode::Rule(rule) => self.matches_rule(music, rule),
+            RuleNode::Group(group) => self.matches_group(music, group),
+        };
+        if group.rules.is_empty() {
+            true
+        } else if group.any {
+            group.rules.iter().any(matches)
+        } else {
+            group.rules.iter().all(matches)
+        }
+    }
+
+    /// Negated operators hold for tracks without a value for the field; the others
+    /// never do.
+    fn matches_rule(&self, music: &MusicFile, rule: &Rule) -> bool {
+        let value = self.value(music, rule.field);
+        let holds = |operator| {
+            value
+                .as_ref()
+                .is_some_and(|value| self.holds(value, operator, rule))
+        };
+        match rule.operator {
+            RuleOperator::IsNot => !holds(RuleOperator::Is),
+            RuleOperator::NotContains => !holds(RuleOperator::Contains),
+            RuleOperator::NotInLastDays => !holds(RuleOperator::InLastDays),
+            operator => holds(operator),
+        }
+    }
+
+    fn holds(&self, value: &Value, operator: RuleOperator, rule: &Rule) -> bool {
+        let wanted = rule.value.trim();
+        let wanted_number = || parse_number(rule.field, wanted);
+        match (operator, value) {
+            (RuleOperator::Is, Value::Number(number)) => {
+                wanted_number().is_some_and(|wanted| (number - wanted).abs() < f64::EPSILON)
+            }
+            (RuleOperator::Is, value) => value.text() == wanted.to_lowercase(),
+            (RuleOperator::Contains, value) => value.text().contains(&wanted.to_lowercase()),
+            (RuleOperator::StartsWith, value) => value.text().starts_with(&wanted.to_lowercase()),
+            (RuleOperator::EndsWith, value) => value.text().ends_with(&wanted.to_lowercase()),
+            (RuleOperator::GreaterThan, Value::Number(number)) => {
+                wanted_number().is_some_and(|wanted| *number > wanted)
+            }
+            (RuleOperator::LessThan, Value::Number(number)) => {
+                wanted_number().is_some_and(|wanted| *number < wanted)
+            }
+            (RuleOperator::InLastDays, Value::Number(time)) => wanted
+                .parse::<f64>()
+                .is_ok_and(|days| *time >= self.now as f64 - days * SECONDS_PER_DAY),
+            _ => false,
+        }
+    }
+
+    fn value(&self, music: &MusicFile, field: RuleField) -> Option<Value> {
+        let text = |value: Option<&str>| value.map(|value| Value::Text(value.to_lowercase()));
+        let tags = self
+            .facts
+            .get(&music.path)
+            .and_then(|facts| facts.tags.as_ref());
+        let stats = self.stats.get(&music.resume_key());
+        match field {
+            RuleField::Title => text(Some(music.name.as_str())),
+            RuleField::Artist => text(music.artist.as_deref()),
+            RuleField::Album => text(music.album.as_deref()),
+            RuleField::AlbumArtist => text(tags.and_then(|tags| tags.album_artist.as_deref())),
+            RuleField::Genre => text(tags.and_then(|tags| tags.genre.as_deref())),
+            RuleField::Comment => text(tags.and_then(|tags| tags.comment.as_deref())),
+            RuleField::Year => tags
+                .and_then(|tags| tags.year.as_deref())
+                .and_then(|year| year.get(..4)?.parse().ok())
+                .map(Value::Number),
+            RuleField::PlayCount => Some(Value::Number(
+                stats.map_or(0, |stats| stats.play_count) as f64
+            )),
+            RuleField::SkipCount => Some(Value::Number(
+                stats.map_or(0, |stats| stats.skip_count) as f64
+            )),
+            RuleField::LastPlayed => stats
+                .and_then(|stats| stats.last_played)
+                .map(|time| Value::Number(time as f64)),
+            RuleField::Rating => music.rating.map(|stars| Value::Number(stars as f64)),
+            RuleField::Favourite => Some(Value::Number(music.favourite as u8 as f64)),
+            RuleField::DateAdded => music
+                .added_at
+                .or_else(|| self.facts.get(&music.path).map(|facts| facts.modified))
+                .map(|time| Value::Number(time as f64)),
+            RuleField::Duration => self.duration(music).map(Value::Number),
+            RuleField::Codec => text(
+                self.facts
+                    .get(&music.path)
+                    .and_then(|facts| facts.codec.as_deref()),
+            ),
+            RuleField::Path => text(Some(music.path.as_str())),
+        }
+    }
+
+    /// Cue sheet tracks last as long as their segment.
+    fn duration(&self, music: &MusicFile) -> Option<f64> {
+        let file_duration = self.facts.get(&music.path).and_then(|facts| facts.duration);
+        match music.segment {
+            Some(segment) => Some(segment.end.or(file_duration)? - segment.start),
+            None => file_duration,
+        }
+    }
+}
+
+enum Value {
+    Text(String),
+    Number(f64),
+}
+
+impl Value {
+    fn text(&self) -> String {
+        match self {
+            Value::Text(text) => text.clone(),
+            Value::Number(number) => number.to_string(),
+        }
+    }
+
+    fn compare(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a.total_cmp(b),
+            (a, b) => a.text().cmp(&b.text()),
+        }
+    }
+}
+
+/// Reads a rule value as a number of the field's unit. Dates may also be written
+/// as `YYYY-MM-DD`, and favourites as `true` or `false`.
+fn parse_number(field: RuleField, value: &str) -> Option<f64> {
+    if let Ok(number) = value.parse::<f64>() {
+        return Some(number);
+    }
+    match field {
+        RuleField::LastPlayed | RuleField::DateAdded => {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+                .map(|time| time.and_utc().timestamp() as f64)
+        }
+        RuleField::Favourite => value
+            .parse::<bool>()
+            .ok()
+            .map(|favourite| favourite as u8 as f64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{SmartLimit, SmartSort};
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn track(name: &str, artist: Option<&str>) -> MusicFile {
+        MusicFile::new(
+            name.to_string(),
+            name.to_string(),
+            format!("/music/{}.flac", name),
+            None,
+            artist.map(str::to_string),
+            None,
+        )
+    }
+
+    fn rule(field: RuleField, operator: RuleOperator, value: &str) -> RuleNode {
+        RuleNode::Rule(Rule {
+            field,
+            operator,
+            value: value.to_string(),
+        })
+    }
+
+    fn all(rules: Vec<RuleNode>) -> RuleGroup {
+        RuleGroup { any: false, rules }
+    }
+
+    fn rules(group: RuleGroup) -> SmartRules {
+        SmartRules {
+            group,
+            sort: None,
+            limit: None,
+        }
+    }
+
+    fn played(music: &MusicFile, play_count: u32, days_ago: Option<i64>) -> (String, TrackStats) {
+        let stats = TrackStats {
+            track: music.resume_key(),
+            title: music.name.clone(),
+            artist: music.artist.clone(),
+            album: None,
+            play_count,
+            skip_count: 0,
+            last_played: days_ago.map(|days| NOW - days * SECONDS_PER_DAY as i64),
+            listened: 0.0,
+        };
+        (music.resume_key(), stats)
+    }
+
+    fn context(stats: Vec<(String, TrackStats)>) -> Context {
+        Context {
+            stats: stats.into_iter().collect(),
+            facts: HashMap::new(),
+            now: NOW,
+        }
+    }
+
+    fn names(tracks: &[MusicFile]) -> Vec<&str> {
+        tracks.iter().map(|music| music.name.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_numbers_dates_and_favourites() {
+        assert_eq!(parse_number(RuleField::PlayCount, "3"), Some(3.0));
+        assert_eq!(parse_number(RuleField::Rating, "2.5"), Some(2.5));
+        assert_eq!(
+            parse_number(RuleField::LastPlayed, "2023-11-14"),
+            Some(1_699_920_000.0)
+        );
+        assert_eq!(
+            parse_number(RuleField::DateAdded, "1700000000"),
+            Some(1_700_000_000.0)
+        );
+        assert_eq!(parse_number(RuleField::Favourite, "true"), Some(1.0));
+        assert_eq!(parse_number(RuleField::Favourite, "false"), Some(0.0));
+        // Dates and booleans only count for the fields that take them.
+        assert_eq!(parse_number(RuleField::PlayCount, "2023-11-14"), None);
+        assert_eq!(parse_number(RuleField::Duration, "true"), None);
+        assert_eq!(parse_number(RuleField::LastPlayed, "14/11/2023"), None);
+    }
+
+    #[test]
+    fn compares_numbers_numerically_and_text_alphabetically() {
+        assert_eq!(
+            Value::Number(9.0).compare(&Value::Number(10.0)),
+            Ordering::Less
+        );
+        assert_eq!(
+            Value::Text("b".to_string()).compare(&Value::Text("a".to_string())),
+            Ordering::Greater
+        );
+        assert_eq!(
+            Value::Number(2.0).compare(&Value::Text("2".to_string())),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn text_operators_ignore_case() {
+        let tracks = vec![
+            track("Blue Monday", Some("New Order")),
+            track("Ceremony", Some("Joy Division")),
+        ];
+        let context = context(Vec::new());
+        let select = |operator, value| {
+            let rules = rules(all(vec![rule(RuleField::Artist, operator, value)]));
+            names(&context.select(tracks.clone(), &rules))
+                .into_iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(select(RuleOperator::Is, "new order"), ["Blue Monday"]);
+        assert_eq!(select(RuleOperator::Contains, "DIVISION"), ["Ceremony"]);
+        assert_eq!(select(RuleOperator::StartsWith, "joy"), ["Ceremony"]);
+        assert_eq!(select(RuleOperator::EndsWith, "order"), ["Blue Monday"]);
+        assert_eq!(select(RuleOperator::NotContains, "order"), ["Ceremony"]);
+    }
+
+    #[test]
+    fn negated_operators_hold_without_a_value() {
+        let tracks = vec![track("tagged", Some("Artist")), track("untagged", None)];
+        let context = context(Vec::new());
+        let is_not = rules(all(vec![rule(
+            RuleField::Artist,
+            RuleOperator::IsNot,
+            "artist",
+        )]));
+        assert_eq!(
+            names(&context.select(tracks.clone(), &is_not)),
+            ["untagged"]
+        );
+        let contains = rules(all(vec![rule(
+            RuleField::Artist,
+            RuleOperator::Contains,
+            "",
+        )]));
+        assert_eq!(names(&context.select(tracks, &contains)), ["tagged"]);
+    }
+
+    #[test]
+    fn compares_play_counts_and_dates() {
+        let (a, b, c) = (track("a", None), track("b", None), track("c", None));
+        let context = context(vec![
+            played(&a, 5, Some(2)),
+            played(&b, 1, Some(40)),
+            played(&c, 0, None),
+        ]);
+        let tracks = vec![a, b, c];
+        let select =
+            |node| names(&context.select(tracks.clone(), &rules(all(vec![node])))).join(",");
+
+        assert_eq!(
+            select(rule(RuleField::PlayCount, RuleOperator::GreaterThan, "1")),
+            "a"
+        );
+        assert_eq!(
+            select(rule(RuleField::PlayCount, RuleOperator::LessThan, "2")),
+            "b,c"
+        );
+        assert_eq!(
+            select(rule(RuleField::PlayCount, RuleOperator::Is, "0")),
+            "c"
+        );
+        assert_eq!(
+            select(rule(
+                RuleField::PlayCount,
+                RuleOperator::GreaterThan,
+                "many"
+            )),
+            ""
+        );
+        assert_eq!(
+            select(rule(RuleField::LastPlayed, RuleOperator::InLastDays, "30")),
+            "a"
+        );
+        // Tracks never played count as not played lately.
+        assert_eq!(
+            select(rule(
+                RuleField::LastPlayed,
+                RuleOperator::NotInLastDays,
+                "30"
+            )),
+            "b,c"
+        );
+        assert_eq!(
+            select(rule(
+                RuleField::LastPlayed,
+                RuleOperator::LessThan,
+                "2023-11-01"
+            )),
+            "b"
+        );
+    }
+
+    #[test]
+    fn nested_groups_combine_with_and_or() {
+        let mut favourite = track("favourite", Some("Someone"));
+        favourite.favourite = true;
+        let mut rated = track("rated", Some("Someone"));
+        rated.rating = Some(4.5);
+        let tracks = vec![favourite, rated, track("other", Some("Someone"))];
+        let context = context(Vec::new());
+
+        let group = all(vec![
+            rule(RuleField::Artist, RuleOperator::Is, "someone"),
+            RuleNode::Group(RuleGroup {
+                any: true,
+                rules: vec![
+                    rule(RuleField::Favourite, RuleOperator::Is, "true"),
+                    rule(RuleField::Rating, RuleOperator::GreaterThan, "4"),
+                ],
+            }),
+        ]);
+        assert_eq!(
+            names(&context.select(tracks.clone(), &rules(group))),
+            ["favourite", "rated"]
+        );
+        assert_eq!(
+            names(&context.select(tracks, &rules(all(Vec::new())))).len(),
+            3
+        );
+    }
+
+    #[test]
+    fn sorts_missing_values_last_and_limits() {
+        let mut tracks = vec![track("a", None), track("b", None), track("c", None)];
+        tracks[0].rating = Some(2.0);
+        tracks[2].rating = Some(4.0);
+        let context = context(Vec::new());
+        let mut sorted = rules(all(Vec::new()));
+        sorted.sort = Some(SmartSort {
+            field: RuleField::Rating,
+            descending: true,
+        });
+        assert_eq!(
+            names(&context.select(tracks.clone(), &sorted)),
+            ["c", "a", "b"]
+        );
+
+        sorted.limit = Some(SmartLimit {
+            kind: LimitKind::Count,
+            value: 2.0,
+        });
+        assert_eq!(names(&context.select(tracks, &sorted)), ["c", "a"]);
+    }
+
+    #[test]
+    fn only_play_and_rating_rules_follow_listening() {
+        let by_artist = rules(all(vec![rule(RuleField::Artist, RuleOperator::Is, "x")]));
+        assert!(!reads_listening(&by_artist));
+
+        let mut most_played = by_artist.clone();
+        most_played.sort = Some(SmartSort {
+            field: RuleField::PlayCount,
+            descending: true,
+        });
+        assert!(reads_listening(&most_played));
+
+        let rated = rules(all(vec![RuleNode::Group(all(vec![rule(
+            RuleField::Rating,
+            RuleOperator::GreaterThan,
+            "3",
+        )]))]));
+        assert!(reads_listening(&rated));
+    }
+}
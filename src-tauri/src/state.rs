@@ -1,7 +1,16 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use serde::{Deserialize, Serialize};
 use symphonia::core::units::Time;
 
-use crate::music::MusicFile;
+use crate::music::{
+    Chapter, LibraryFolder, Lyrics, LyricsPosition, MatchChoice, MusicFile, PlayEvent,
+    StoredFileInfo, StoredFingerprint, TrackFacts,
+};
 
 #[derive(Debug, Clone, Default)]
 pub struct IdState(Option<String>);
@@ -141,3 +150,262 @@ impl TimePositionState {
         self.0
     }
 }
+
+#[derive(Debug, Clone, Default)]
+pub struct LibraryState {
+    folders: Vec<LibraryFolder>,
+    tracks: Vec<MusicFile>,
+}
+
+impl LibraryState {
+    pub fn set_folders(&mut self, folders: Vec<LibraryFolder>) {
+        self.folders = folders;
+    }
+    pub fn folders(&self) -> &[LibraryFolder] {
+        &self.folders
+    }
+    pub fn set_tracks(&mut self, tracks: Vec<MusicFile>) {
+        self.tracks = tracks;
+    }
+    pub fn tracks(&self) -> &[MusicFile] {
+        &self.tracks
+    }
+    pub fn tracks_cloned(&self) -> Vec<MusicFile> {
+        self.tracks.clone()
+    }
+}
+
+#[derive(Default)]
+pub struct LibraryWatcherState(Option<RecommendedWatcher>);
+
+impl LibraryWatcherState {
+    pub fn set(&mut self, watcher: Option<RecommendedWatcher>) {
+        self.0 = watcher;
+    }
+    pub fn watch(&mut self, path: &Path) -> notify::Result<()> {
+        match self.0.as_mut() {
+            Some(watcher) => watcher.watch(path, RecursiveMode::Recursive),
+            None => Ok(()),
+        }
+    }
+    pub fn unwatch(&mut self, path: &Path) -> notify::Result<()> {
+        match self.0.as_mut() {
+            Some(watcher) => watcher.unwatch(path),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ScanState(Arc<AtomicBool>);
+
+impl ScanState {
+    /// Starts a new scan generation and returns its cancellation flag.
+    pub fn begin(&mut self) -> Arc<AtomicBool> {
+        self.0 = Arc::new(AtomicBool::new(false));
+        self.0.clone()
+    }
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FingerprintState(HashMap<String, StoredFingerprint>);
+
+impl FingerprintState {
+    pub fn set(&mut self, fingerprints: HashMap<String, StoredFingerprint>) {
+        self.0 = fingerprints;
+    }
+    pub fn get_cloned(&self) -> HashMap<String, StoredFingerprint> {
+        self.0.clone()
+    }
+}
+
+/// Durations of files played this session, keyed by path, so starting a track does
+/// not probe its file again to decide whether it resumes.
+#[derive(Debug, Clone, Default)]
+pub struct DurationState(HashMap<String, f64>);
+
+impl DurationState {
+    pub fn get(&self, path: &str) -> Option<f64> {
+        self.0.get(path).copied()
+    }
+    pub fn insert(&mut self, path: String, duration: f64) {
+        self.0.insert(path, duration);
+    }
+}
+
+/// Each looked-up file's identity and artwork, keyed by path.
+#[derive(Debug, Clone, Default)]
+pub struct FileInfoState(HashMap<String, StoredFileInfo>);
+
+impl FileInfoState {
+    pub fn set(&mut self, file_infos: HashMap<String, StoredFileInfo>) {
+        self.0 = file_infos;
+    }
+    pub fn extend(&mut self, file_infos: HashMap<String, StoredFileInfo>) {
+        self.0.extend(file_infos);
+    }
+    pub fn get_cloned(&self) -> HashMap<String, StoredFileInfo> {
+        self.0.clone()
+    }
+}
+
+/// Ambiguous lookups waiting for the user to pick a match, keyed by file path.
+#[derive(Debug, Clone, Default)]
+pub struct MatchChoiceState(Vec<MatchChoice>);
+
+impl MatchChoiceState {
+    /// Adds `choices`, replacing any pending choice for the same file.
+    pub fn extend(&mut self, choices: Vec<MatchChoice>) {
+        self.0
+            .retain(|pending| !choices.iter().any(|choice| choice.path == pending.path));
+        self.0.extend(choices);
+    }
+    pub fn get(&self, path: &str) -> Option<MatchChoice> {
+        self.0.iter().find(|choice| choice.path == path).cloned()
+    }
+    pub fn remove(&mut self, path: &str) -> Option<MatchChoice> {
+        let index = self.0.iter().position(|choice| choice.path == path)?;
+        Some(self.0.remove(index))
+    }
+    pub fn get_cloned(&self) -> Vec<MatchChoice> {
+        self.0.clone()
+    }
+}
+
+/// Files waiting for a metadata lookup. Kept in the store so lookups interrupted by
+/// a restart or a network failure are resumed.
+#[derive(Debug, Clone, Default)]
+pub struct LookupQueueState(Vec<MusicFile>);
+
+impl LookupQueueState {
+    pub fn set(&mut self, queue: Vec<MusicFile>) {
+        self.0 = queue;
+    }
+    pub fn push(&mut self, music_files: &[MusicFile]) {
+        for music_file in music_files {
+            if !self.0.iter().any(|queued| queued.path == music_file.path) {
+                self.0.push(music_file.clone());
+            }
+        }
+    }
+    pub fn retain<F: FnMut(&MusicFile) -> bool>(&mut self, keep: F) {
+        self.0.retain(keep);
+    }
+    pub fn get_cloned(&self) -> Vec<MusicFile> {
+        self.0.clone()
+    }
+}
+
+/// Lyrics of the track being played and the last position announced for them.
+#[derive(Debug, Clone, Default)]
+pub struct LyricsState {
+    path: Option<String>,
+    lyrics: Option<Lyrics>,
+    position: Option<LyricsPosition>,
+}
+
+impl LyricsState {
+    pub fn set(&mut self, path: String, lyrics: Option<Lyrics>) {
+        self.path = Some(path);
+        self.lyrics = lyrics;
+        self.position = None;
+    }
+    /// Whether lyrics for `path` were already looked for, found or not.
+    pub fn is_loaded(&self, path: &str) -> bool {
+        self.path.as_deref() == Some(path)
+    }
+    pub fn lyrics(&self) -> Option<&Lyrics> {
+        self.lyrics.as_ref()
+    }
+    pub fn reset_position(&mut self) {
+        self.position = None;
+    }
+    /// Records `position` and returns whether it differs from the last one.
+    pub fn update_position(&mut self, position: LyricsPosition) -> bool {
+        if self.position.as_ref() == Some(&position) {
+            return false;
+        }
+        self.position = Some(position);
+        true
+    }
+}
+
+/// Chapters of the track being played, keyed by track id since the tracks of a
+/// cue sheet share their file but not its chapters.
+#[derive(Debug, Clone, Default)]
+pub struct ChapterState {
+    id: Option<String>,
+    chapters: Vec<Chapter>,
+}
+
+impl ChapterState {
+    pub fn set(&mut self, id: String, chapters: Vec<Chapter>) {
+        self.id = Some(id);
+        self.chapters = chapters;
+    }
+    pub fn is_loaded(&self, id: &str) -> bool {
+        self.id.as_deref() == Some(id)
+    }
+    pub fn get_cloned(&self) -> Vec<Chapter> {
+        self.chapters.clone()
+    }
+    /// The chapter of track `id` playing at `seconds`.
+    pub fn index_at(&self, id: &str, seconds: f64) -> Option<usize> {
+        if !self.is_loaded(id) {
+            return None;
+        }
+        self.chapters
+            .iter()
+            .rposition(|chapter| chapter.start <= seconds)
+    }
+}
+
+/// The play of the current track that goes into the play log once it ends.
+#[derive(Debug, Clone, Default)]
+pub struct ListeningState {
+    track_id: Option<String>,
+    event: Option<PlayEvent>,
+    last_position: Option<f64>,
+}
+
+impl ListeningState {
+    pub fn is_tracking(&self, track_id: &str) -> bool {
+        self.track_id.as_deref() == Some(track_id)
+    }
+    /// Starts recording a play of `track_id`, returning the one it replaces.
+    pub fn start(&mut self, track_id: String, event: PlayEvent) -> Option<PlayEvent> {
+        self.track_id = Some(track_id);
+        self.last_position = None;
+        self.event.replace(event)
+    }
+    /// Counts the time from the last position to `position` as listened when it is
+    /// no more than `max_step`, so that seeks are left out.
+    pub fn advance(&mut self, position: f64, max_step: f64) {
+        if let (Some(event), Some(last)) = (self.event.as_mut(), self.last_position) {
+            let step = position - last;
+            if step > 0.0 && step <= max_step {
+                event.listened += step;
+            }
+        }
+        self.last_position = Some(position);
+    }
+    pub fn take(&mut self) -> Option<PlayEvent> {
+        self.track_id = None;
+        self.last_position = None;
+        self.event.take()
+    }
+}
+
+/// File facts read for smart playlists, by path. Holding the lock also keeps two
+/// refreshes from interleaving.
+#[derive(Debug, Clone, Default)]
+pub struct SmartPlaylistState(HashMap<String, TrackFacts>);
+
+impl SmartPlaylistState {
+    pub fn facts_mut(&mut self) -> &mut HashMap<String, TrackFacts> {
+        &mut self.0
+    }
+}
@@ -0,0 +1,258 @@
+use std::{collections::HashMap, hash::Hash, sync::Mutex};
+
+use chrono::{TimeDelta, Utc};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    music::{ListeningStats, PlayEvent, StatsEntry, StatsPeriod, TrackStats},
+    smart,
+    state::{ListeningState, MusicFilesState},
+    store,
+};
+
+/// How many tracks, artists and albums the listening stats rank by default.
+pub const DEFAULT_LIMIT: usize = 10;
+
+/// Positions further apart than this between two updates were seeked over.
+const MAX_PROGRESS_STEP_SECS: f64 = 1.0;
+
+/// Records that track `id` played up to `position`, starting a new play when it is
+/// not the one being recorded. A play left for another track without finishing,
+/// as when a track is picked from the list, goes into the log as skipped.
+pub fn track_progress(app: &AppHandle, id: &str, position: f64) {
+    let left = {
+        let listening_state = app.state::<Mutex<ListeningState>>();
+        let Ok(mut state) = listening_state.lock() else {
+            return;
+        };
+        let mut left = None;
+        if !state.is_tracking(id) {
+            let Some(music_file) = app
+                .state::<Mutex<MusicFilesState>>()
+                .lock()
+                .ok()
+                .and_then(|s| s.get().iter().find(|f| f.id == id).cloned())
+            else {
+                return;
+            };
+            left = state.start(id.to_string(), PlayEvent::start(&music_file));
+        }
+        state.advance(position, MAX_PROGRESS_STEP_SECS);
+        left
+    };
+    if let Some(left) = left {
+        store::append_play_event(app, left);
+        smart::spawn_refresh(app, smart::RefreshScope::Listening);
+    }
+}
+
+/// Ends the play being recorded, if any, and adds it to the play log.
+pub fn finish(app: &AppHandle, completed: bool) {
+    let event = app
+        .state::<Mutex<ListeningState>>()
+        .lock()
+        .ok()
+        .and_then(|mut s| s.take());
+    if let Some(mut event) = event {
+        event.completed = completed;
+        store::append_play_event(app, event);
+        smart::spawn_refresh(app, smart::RefreshScope::Listening);
+    }
+}
+
+/// Play and skip counts of every track in `log`, by `MusicFile::resume_key`.
+pub fn track_stats(log: &[PlayEvent]) -> HashMap<String, TrackStats> {
+    let mut stats: HashMap<String, TrackStats> = HashMap::new();
+    for event in log {
+        let track = stats
+            .entry(event.track.clone())
+            .or_insert_with(|| TrackStats {
+                track: event.track.clone(),
+                title: String::new(),
+                artist: None,
+                album: None,
+                play_count: 0,
+                skip_count: 0,
+                last_played: None,
+                listened: 0.0,
+            });
+        // Later plays carry the most recent tags.
+        track.title = event.title.clone();
+        track.artist = event.artist.clone();
+        track.album = event.album.clone();
+        track.listened += event.listened;
+        if event.listened > 0.0 {
+            track.last_played = track.last_played.max(Some(event.started_at));
+        }
+        if event.completed {
+            track.play_count += 1;
+        } else {
+            track.skip_count += 1;
+        }
+    }
+    stats
+}
+
+/// Listening time and the `limit` most played tracks, artists and albums over the
+/// last week, month or year.
+pub fn listening_stats(log: &[PlayEvent], period: StatsPeriod, limit: usize) -> ListeningStats {
+    let days = match period {
+        StatsPeriod::Week => 7,
+        StatsPeriod::Month => 30,
+        StatsPeriod::Year => 365,
+    };
+    let since = (Utc::now() - TimeDelta::days(days)).timestamp();
+    let events: Vec<&PlayEvent> = log
+        .iter()
+        .filter(|event| event.started_at >= since)
+        .collect();
+
+    let mut tracks = HashMap::new();
+    let mut artists = HashMap::new();
+    let mut albums = HashMap::new();
+    for event in &events {
+        count(&mut tracks, event.track.clone(), event, || {
+            (event.title.clone(), event.artist.clone())
+        });
+        if let Some(artist) = &event.artist {
+            count(&mut artists, artist.clone(), event, || {
+                (artist.clone(), None)
+            });
+        }
+        if let Some(album) = &event.album {
+            let key = (album.clone(), event.artist.clone());
+            count(&mut albums, key, event, || {
+                (album.clone(), event.artist.clone())
+            });
+        }
+    }
+
+    ListeningStats {
+        period,
+        since,
+        total_listened: events.iter().map(|event| event.listened).sum(),
+        top_tracks: top(tracks, limit),
+        top_artists: top(artists, limit),
+        top_albums: top(albums, limit),
+    }
+}
+
+fn count<K: Eq + Hash>(
+    entries: &mut HashMap<K, StatsEntry>,
+    key: K,
+    event: &PlayEvent,
+    describe: impl FnOnce() -> (String, Option<String>),
+) {
+    let entry = entries.entry(key).or_insert_with(|| {
+        let (name, artist) = describe();
+        StatsEntry {
+            name,
+            artist,
+            plays: 0,
+            listened: 0.0,
+        }
+    });
+    if event.completed {
+        entry.plays += 1;
+    }
+    entry.listened += event.listened;
+}
+
+/// The entries played most, ties going to the one listened to longest.
+fn top<K>(entries: HashMap<K, StatsEntry>, limit: usize) -> Vec<StatsEntry> {
+    let mut entries: Vec<StatsEntry> = entries.into_values().collect();
+    entries.sort_by(|a, b| {
+        b.plays
+            .cmp(&a.plays)
+            .then(b.listened.total_cmp(&a.listened))
+    });
+    entries.truncate(limit);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(track: &str, days_ago: i64, listened: f64, completed: bool) -> PlayEvent {
+        PlayEvent {
+            track: track.to_string(),
+            title: format!("{} title", track),
+            artist: Some(format!("{} artist", track)),
+            album: Some("Album".to_string()),
+            started_at: (Utc::now() - TimeDelta::days(days_ago)).timestamp(),
+            listened,
+            completed,
+        }
+    }
+
+    #[test]
+    fn counts_plays_and_skips_per_track() {
+        let log = [
+            event("a", 3, 200.0, true),
+            event("b", 2, 0.0, false),
+            event("a", 1, 40.0, false),
+            event("a", 0, 0.0, false),
+        ];
+        let stats = track_stats(&log);
+        assert_eq!(stats.len(), 2);
+
+        let a = &stats["a"];
+        assert_eq!(a.play_count, 1);
+        assert_eq!(a.skip_count, 2);
+        assert_eq!(a.listened, 240.0);
+        // The skipped play was still heard; the one skipped straight away was not.
+        assert_eq!(a.last_played, Some(log[2].started_at));
+
+        let b = &stats["b"];
+        assert_eq!((b.play_count, b.skip_count), (0, 1));
+        assert_eq!(b.last_played, None);
+    }
+
+    #[test]
+    fn later_plays_carry_the_tags() {
+        let mut renamed = event("a", 0, 10.0, true);
+        renamed.title = "New title".to_string();
+        let stats = track_stats(&[event("a", 1, 10.0, true), renamed]);
+        assert_eq!(stats["a"].title, "New title");
+    }
+
+    #[test]
+    fn ranks_within_the_period() {
+        let log = [
+            event("old", 40, 500.0, true),
+            event("old", 40, 500.0, true),
+            event("a", 10, 100.0, true),
+            event("b", 5, 100.0, true),
+            event("b", 4, 50.0, true),
+            event("a", 1, 30.0, false),
+        ];
+
+        let month = listening_stats(&log, StatsPeriod::Month, DEFAULT_LIMIT);
+        assert_eq!(month.total_listened, 280.0);
+        let tracks: Vec<(&str, u32)> = month
+            .top_tracks
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.plays))
+            .collect();
+        assert_eq!(tracks, [("b title", 2), ("a title", 1)]);
+        assert_eq!(month.top_albums.len(), 2);
+
+        let week = listening_stats(&log, StatsPeriod::Week, DEFAULT_LIMIT);
+        assert_eq!(week.total_listened, 180.0);
+        assert_eq!(week.top_tracks[0].name, "b title");
+        assert_eq!(week.top_artists.len(), 2);
+
+        let year = listening_stats(&log, StatsPeriod::Year, 1);
+        assert_eq!(year.top_tracks.len(), 1);
+        assert_eq!(year.top_tracks[0].name, "old title");
+    }
+
+    #[test]
+    fn ties_go_to_the_longest_listened() {
+        let log = [event("a", 0, 100.0, true), event("b", 0, 200.0, true)];
+        let stats = listening_stats(&log, StatsPeriod::Week, DEFAULT_LIMIT);
+        assert_eq!(stats.top_tracks[0].name, "b title");
+        assert_eq!(stats.top_tracks[1].name, "a title");
+    }
+}
@@ -1,34 +1,195 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+};
+
+use chrono::Utc;
 use log::warn;
 use serde_json::json;
-use tauri::AppHandle;
-use tauri_plugin_store::StoreExt;
+use tauri::{AppHandle, Wry};
+use tauri_plugin_store::{Store, StoreExt};
+use uuid::Uuid;
 
-use crate::music::{MusicFile, MusicSetting, PlayState};
+use crate::music::{
+    FileMove, LibraryFolder, MusicFile, MusicSetting, PlayEvent, PlayState, Playlist, ResumePoint,
+    StoredFileInfo, StoredFingerprint, TrackRating,
+};
 
 pub const PLAYLIST_STORE_FILENAME: &str = "playlist_store.json";
+/// The single playlist of older versions, moved into `PLAYLISTS_STORE_KEY` on first
+/// load.
 pub const PLAYLIST_STORE_KEY: &str = "playlist";
+pub const PLAYLISTS_STORE_KEY: &str = "playlists";
+pub const CURRENT_PLAYLIST_STORE_KEY: &str = "current_playlist";
+pub const LOOKUP_QUEUE_STORE_KEY: &str = "lookup_queue";
 
 pub const PLAY_STATE_STORE_FILENAME: &str = "play_state_store.json";
 pub const PLAY_STATE_STORE_KEY: &str = "play_state";
+pub const RESUME_POINTS_STORE_KEY: &str = "resume_points";
+pub const AUDIO_STREAMS_STORE_KEY: &str = "audio_streams";
 
 pub const SETTINGS_STORE_FILENAME: &str = "settings_store.json";
 pub const SETTINGS_STORE_KEY: &str = "settings";
 
+pub const LIBRARY_STORE_FILENAME: &str = "library_store.json";
+pub const LIBRARY_FOLDERS_STORE_KEY: &str = "folders";
+pub const LIBRARY_TRACKS_STORE_KEY: &str = "tracks";
+pub const LIBRARY_FINGERPRINTS_STORE_KEY: &str = "fingerprints";
+pub const LIBRARY_FILE_INFOS_STORE_KEY: &str = "file_infos";
+pub const LIBRARY_RENAME_HISTORY_STORE_KEY: &str = "rename_history";
+pub const LIBRARY_RATINGS_STORE_KEY: &str = "ratings";
+
+pub const STATS_STORE_FILENAME: &str = "stats_store.json";
+/// Older plays; the whole log in earlier versions.
+pub const PLAY_LOG_STORE_KEY: &str = "play_log";
+/// The latest plays, moved to `PLAY_LOG_STORE_KEY` once `PLAY_LOG_TAIL_LEN` of them
+/// have gathered, so recording a play does not rewrite the whole log.
+pub const PLAY_LOG_TAIL_STORE_KEY: &str = "play_log_tail";
+/// Prefix of the timestamped keys that plays which could not be read are kept under.
+pub const PLAY_LOG_BACKUP_STORE_KEY: &str = "play_log_backup";
+
+/// Plays kept in the log; the oldest are dropped beyond this.
+const MAX_PLAY_EVENTS: usize = 100_000;
+const PLAY_LOG_TAIL_LEN: usize = 500;
+
+pub const DEFAULT_PLAYLIST_NAME: &str = "Default";
+
+/// Held across every change to the stored playlists, so edits made at the same
+/// time, such as a smart playlist refresh and a rename, do not undo each other.
+static PLAYLISTS_LOCK: Mutex<()> = Mutex::new(());
+
+/// Saves the tracks of the current playlist.
 pub fn store_playlist(app: &AppHandle, playlist: &[MusicFile]) {
-    match app.store(PLAYLIST_STORE_FILENAME) {
-        Ok(store) => store.set(PLAYLIST_STORE_KEY, json!(playlist)),
-        Err(err) => warn!("failed to save playlist: {}", err),
+    update_playlists(app, |playlists| {
+        if let Some(index) = current_index(app, playlists) {
+            playlists[index].tracks = playlist.to_vec();
+        }
+    });
+}
+
+/// Loads the playlists, lets `update` change them and saves them if it did. Every
+/// write to the playlists goes through here; `update` must not call it or
+/// `load_playlists` again.
+pub fn update_playlists<T>(app: &AppHandle, update: impl FnOnce(&mut Vec<Playlist>) -> T) -> T {
+    let _guard = PLAYLISTS_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let mut playlists = read_playlists(app).unwrap_or_else(|| migrate_playlists(app));
+    let before = json!(playlists);
+    let result = update(&mut playlists);
+    if json!(playlists) != before {
+        store_playlists(app, &playlists);
     }
+    result
 }
 
+/// Tracks of the current playlist.
 pub fn load_playlist(app: &AppHandle) -> Vec<MusicFile> {
+    let mut playlists = load_playlists(app);
+    match current_index(app, &playlists) {
+        Some(index) => playlists.swap_remove(index).tracks,
+        None => Vec::new(),
+    }
+}
+
+fn store_playlists(app: &AppHandle, playlists: &[Playlist]) {
+    match app.store(PLAYLIST_STORE_FILENAME) {
+        Ok(store) => store.set(PLAYLISTS_STORE_KEY, json!(playlists)),
+        Err(err) => warn!("failed to save playlists: {}", err),
+    }
+}
+
+/// Every playlist in display order. The first load creates a default playlist,
+/// holding the tracks of the single playlist of older versions if there is one.
+/// Stored playlists that fail to parse are left untouched.
+pub fn load_playlists(app: &AppHandle) -> Vec<Playlist> {
+    read_playlists(app).unwrap_or_else(|| update_playlists(app, |playlists| playlists.clone()))
+}
+
+/// The stored playlists, or `None` while they still have to be migrated.
+fn read_playlists(app: &AppHandle) -> Option<Vec<Playlist>> {
+    let store = match app.store(PLAYLIST_STORE_FILENAME) {
+        Ok(store) => store,
+        Err(err) => {
+            warn!("failed to load playlists: {}", err);
+            return Some(Vec::new());
+        }
+    };
+    let data = store.get(PLAYLISTS_STORE_KEY)?;
+    Some(serde_json::from_value(data).unwrap_or_else(|err| {
+        warn!("failed to parse playlists: {}", err);
+        Vec::new()
+    }))
+}
+
+/// Creates the default playlist. Only called by `update_playlists`, so two first
+/// loads cannot both create one.
+fn migrate_playlists(app: &AppHandle) -> Vec<Playlist> {
+    let store = match app.store(PLAYLIST_STORE_FILENAME) {
+        Ok(store) => store,
+        Err(err) => {
+            warn!("failed to load playlists: {}", err);
+            return Vec::new();
+        }
+    };
+    let tracks = match store.get(PLAYLIST_STORE_KEY).map(serde_json::from_value) {
+        Some(Ok(tracks)) => tracks,
+        Some(Err(err)) => {
+            warn!("failed to parse playlist of an older version: {}", err);
+            return Vec::new();
+        }
+        None => Vec::new(),
+    };
+    let playlist = Playlist::new(
+        Uuid::new_v4().to_string(),
+        DEFAULT_PLAYLIST_NAME.to_string(),
+        tracks,
+    );
+    store.set(PLAYLISTS_STORE_KEY, json!([playlist]));
+    store.set(CURRENT_PLAYLIST_STORE_KEY, json!(playlist.id));
+    store.delete(PLAYLIST_STORE_KEY);
+    vec![playlist]
+}
+
+pub fn store_current_playlist_id(app: &AppHandle, id: &str) {
+    match app.store(PLAYLIST_STORE_FILENAME) {
+        Ok(store) => store.set(CURRENT_PLAYLIST_STORE_KEY, json!(id)),
+        Err(err) => warn!("failed to save current playlist: {}", err),
+    }
+}
+
+/// Id of the now playing playlist, falling back to the first one.
+pub fn load_current_playlist_id(app: &AppHandle) -> Option<String> {
+    let playlists = load_playlists(app);
+    current_index(app, &playlists).map(|index| playlists[index].id.clone())
+}
+
+fn current_index(app: &AppHandle, playlists: &[Playlist]) -> Option<usize> {
+    let current: Option<String> = app
+        .store(PLAYLIST_STORE_FILENAME)
+        .ok()
+        .and_then(|store| store.get(CURRENT_PLAYLIST_STORE_KEY))
+        .and_then(|data| serde_json::from_value(data).ok());
+    current
+        .and_then(|id| playlists.iter().position(|playlist| playlist.id == id))
+        .or_else(|| (!playlists.is_empty()).then_some(0))
+}
+
+pub fn store_lookup_queue(app: &AppHandle, queue: &[MusicFile]) {
+    match app.store(PLAYLIST_STORE_FILENAME) {
+        Ok(store) => store.set(LOOKUP_QUEUE_STORE_KEY, json!(queue)),
+        Err(err) => warn!("failed to save lookup queue: {}", err),
+    }
+}
+
+pub fn load_lookup_queue(app: &AppHandle) -> Vec<MusicFile> {
     match app.store(PLAYLIST_STORE_FILENAME) {
-        Ok(store) => match store.get(PLAYLIST_STORE_KEY) {
-            Some(data) => serde_json::from_value(data).unwrap_or_default(),
-            None => Vec::new(),
-        },
+        Ok(store) => store
+            .get(LOOKUP_QUEUE_STORE_KEY)
+            .and_then(|data| serde_json::from_value(data).ok())
+            .unwrap_or_default(),
         Err(err) => {
-            warn!("failed to load playlist: {}", err);
+            warn!("failed to load lookup queue: {}", err);
             Vec::new()
         }
     }
@@ -53,6 +214,155 @@ pub fn load_play_state(app: &AppHandle) -> Option<PlayState> {
     }
 }
 
+/// Saves the resume point of the track named by `key`, or forgets it when `point`
+/// is `None`.
+pub fn store_resume_point(app: &AppHandle, key: &str, point: Option<ResumePoint>) {
+    let mut points = load_resume_points(app);
+    match point {
+        Some(point) => {
+            points.insert(key.to_string(), point);
+        }
+        None => {
+            points.remove(key);
+        }
+    }
+    match app.store(PLAY_STATE_STORE_FILENAME) {
+        Ok(store) => store.set(RESUME_POINTS_STORE_KEY, json!(points)),
+        Err(err) => warn!("failed to save resume points: {}", err),
+    }
+}
+
+/// Resume points by `MusicFile::resume_key`.
+pub fn load_resume_points(app: &AppHandle) -> HashMap<String, ResumePoint> {
+    match app.store(PLAY_STATE_STORE_FILENAME) {
+        Ok(store) => store
+            .get(RESUME_POINTS_STORE_KEY)
+            .and_then(|data| serde_json::from_value(data).ok())
+            .unwrap_or_default(),
+        Err(err) => {
+            warn!("failed to load resume points: {}", err);
+            HashMap::new()
+        }
+    }
+}
+
+/// Remembers the audio stream chosen for the file at `path`, or forgets the
+/// choice when `index` is `None`.
+pub fn store_audio_stream(app: &AppHandle, path: &str, index: Option<usize>) {
+    let mut streams = load_audio_streams(app);
+    match index {
+        Some(index) => {
+            streams.insert(path.to_string(), index);
+        }
+        None => {
+            streams.remove(path);
+        }
+    }
+    match app.store(PLAY_STATE_STORE_FILENAME) {
+        Ok(store) => store.set(AUDIO_STREAMS_STORE_KEY, json!(streams)),
+        Err(err) => warn!("failed to save audio streams: {}", err),
+    }
+}
+
+/// Chosen audio stream indexes by file path.
+pub fn load_audio_streams(app: &AppHandle) -> HashMap<String, usize> {
+    match app.store(PLAY_STATE_STORE_FILENAME) {
+        Ok(store) => store
+            .get(AUDIO_STREAMS_STORE_KEY)
+            .and_then(|data| serde_json::from_value(data).ok())
+            .unwrap_or_default(),
+        Err(err) => {
+            warn!("failed to load audio streams: {}", err);
+            HashMap::new()
+        }
+    }
+}
+
+pub fn store_ratings(app: &AppHandle, ratings: &HashMap<String, TrackRating>) {
+    match app.store(LIBRARY_STORE_FILENAME) {
+        Ok(store) => store.set(LIBRARY_RATINGS_STORE_KEY, json!(ratings)),
+        Err(err) => warn!("failed to save ratings: {}", err),
+    }
+}
+
+/// Ratings by `ratings::rating_key`; older versions stored them by
+/// `MusicFile::resume_key`.
+pub fn load_ratings(app: &AppHandle) -> HashMap<String, TrackRating> {
+    match app.store(LIBRARY_STORE_FILENAME) {
+        Ok(store) => store
+            .get(LIBRARY_RATINGS_STORE_KEY)
+            .and_then(|data| serde_json::from_value(data).ok())
+            .unwrap_or_default(),
+        Err(err) => {
+            warn!("failed to load ratings: {}", err);
+            HashMap::new()
+        }
+    }
+}
+
+/// Adds `event` to the play log.
+pub fn append_play_event(app: &AppHandle, event: PlayEvent) {
+    let store = match app.store(STATS_STORE_FILENAME) {
+        Ok(store) => store,
+        Err(err) => {
+            warn!("failed to save play log: {}", err);
+            return;
+        }
+    };
+    let mut tail = read_play_events(&store, PLAY_LOG_TAIL_STORE_KEY);
+    tail.push(event);
+    if tail.len() >= PLAY_LOG_TAIL_LEN {
+        let mut log = read_play_events(&store, PLAY_LOG_STORE_KEY);
+        log.append(&mut tail);
+        let excess = log.len().saturating_sub(MAX_PLAY_EVENTS);
+        log.drain(..excess);
+        store.set(PLAY_LOG_STORE_KEY, json!(log));
+    }
+    store.set(PLAY_LOG_TAIL_STORE_KEY, json!(tail));
+}
+
+/// The plays stored under `key`. Plays that cannot be parsed are moved to a new
+/// timestamped backup key, so they are never overwritten, and the log carries on
+/// without them.
+fn read_play_events(store: &Store<Wry>, key: &str) -> Vec<PlayEvent> {
+    let Some(data) = store.get(key) else {
+        return Vec::new();
+    };
+    serde_json::from_value(data.clone()).unwrap_or_else(|err| {
+        let backup_key = format!(
+            "{}_{}_{}",
+            PLAY_LOG_BACKUP_STORE_KEY,
+            key,
+            Utc::now().timestamp_millis()
+        );
+        warn!("failed to parse {}, moved to {}: {}", key, backup_key, err);
+        store.set(backup_key, data);
+        store.delete(key);
+        Vec::new()
+    })
+}
+
+/// Every recorded play, oldest first.
+pub fn load_play_log(app: &AppHandle) -> Vec<PlayEvent> {
+    let store = match app.store(STATS_STORE_FILENAME) {
+        Ok(store) => store,
+        Err(err) => {
+            warn!("failed to load play log: {}", err);
+            return Vec::new();
+        }
+    };
+    [PLAY_LOG_STORE_KEY, PLAY_LOG_TAIL_STORE_KEY]
+        .into_iter()
+        .filter_map(|key| store.get(key))
+        .flat_map(|data| {
+            serde_json::from_value::<Vec<PlayEvent>>(data).unwrap_or_else(|err| {
+                warn!("failed to parse play log: {}", err);
+                Vec::new()
+            })
+        })
+        .collect()
+}
+
 pub fn store_settings(app: &AppHandle, settings: MusicSetting) {
     match app.store(SETTINGS_STORE_FILENAME) {
         Ok(store) => store.set(SETTINGS_STORE_KEY, json!(settings)),
@@ -72,3 +382,103 @@ pub fn load_settings(app: &AppHandle) -> MusicSetting {
         }
     }
 }
+
+pub fn store_library_folders(app: &AppHandle, folders: &[LibraryFolder]) {
+    match app.store(LIBRARY_STORE_FILENAME) {
+        Ok(store) => store.set(LIBRARY_FOLDERS_STORE_KEY, json!(folders)),
+        Err(err) => warn!("failed to save library folders: {}", err),
+    }
+}
+
+pub fn load_library_folders(app: &AppHandle) -> Vec<LibraryFolder> {
+    match app.store(LIBRARY_STORE_FILENAME) {
+        Ok(store) => store
+            .get(LIBRARY_FOLDERS_STORE_KEY)
+            .and_then(|data| serde_json::from_value(data).ok())
+            .unwrap_or_default(),
+        Err(err) => {
+            warn!("failed to load library folders: {}", err);
+            Vec::new()
+        }
+    }
+}
+
+pub fn store_library_tracks(app: &AppHandle, tracks: &[MusicFile]) {
+    match app.store(LIBRARY_STORE_FILENAME) {
+        Ok(store) => store.set(LIBRARY_TRACKS_STORE_KEY, json!(tracks)),
+        Err(err) => warn!("failed to save library tracks: {}", err),
+    }
+}
+
+pub fn load_library_tracks(app: &AppHandle) -> Vec<MusicFile> {
+    match app.store(LIBRARY_STORE_FILENAME) {
+        Ok(store) => store
+            .get(LIBRARY_TRACKS_STORE_KEY)
+            .and_then(|data| serde_json::from_value(data).ok())
+            .unwrap_or_default(),
+        Err(err) => {
+            warn!("failed to load library tracks: {}", err);
+            Vec::new()
+        }
+    }
+}
+
+pub fn store_fingerprints(app: &AppHandle, fingerprints: &HashMap<String, StoredFingerprint>) {
+    match app.store(LIBRARY_STORE_FILENAME) {
+        Ok(store) => store.set(LIBRARY_FINGERPRINTS_STORE_KEY, json!(fingerprints)),
+        Err(err) => warn!("failed to save fingerprints: {}", err),
+    }
+}
+
+pub fn load_fingerprints(app: &AppHandle) -> HashMap<String, StoredFingerprint> {
+    match app.store(LIBRARY_STORE_FILENAME) {
+        Ok(store) => store
+            .get(LIBRARY_FINGERPRINTS_STORE_KEY)
+            .and_then(|data| serde_json::from_value(data).ok())
+            .unwrap_or_default(),
+        Err(err) => {
+            warn!("failed to load fingerprints: {}", err);
+            HashMap::new()
+        }
+    }
+}
+
+pub fn store_file_infos(app: &AppHandle, file_infos: &HashMap<String, StoredFileInfo>) {
+    match app.store(LIBRARY_STORE_FILENAME) {
+        Ok(store) => store.set(LIBRARY_FILE_INFOS_STORE_KEY, json!(file_infos)),
+        Err(err) => warn!("failed to save file infos: {}", err),
+    }
+}
+
+pub fn load_file_infos(app: &AppHandle) -> HashMap<String, StoredFileInfo> {
+    match app.store(LIBRARY_STORE_FILENAME) {
+        Ok(store) => store
+            .get(LIBRARY_FILE_INFOS_STORE_KEY)
+            .and_then(|data| serde_json::from_value(data).ok())
+            .unwrap_or_default(),
+        Err(err) => {
+            warn!("failed to load file infos: {}", err);
+            HashMap::new()
+        }
+    }
+}
+
+pub fn store_rename_history(app: &AppHandle, moves: &[FileMove]) {
+    match app.store(LIBRARY_STORE_FILENAME) {
+        Ok(store) => store.set(LIBRARY_RENAME_HISTORY_STORE_KEY, json!(moves)),
+        Err(err) => warn!("failed to save rename history: {}", err),
+    }
+}
+
+pub fn load_rename_history(app: &AppHandle) -> Vec<FileMove> {
+    match app.store(LIBRARY_STORE_FILENAME) {
+        Ok(store) => store
+            .get(LIBRARY_RENAME_HISTORY_STORE_KEY)
+            .and_then(|data| serde_json::from_value(data).ok())
+            .unwrap_or_default(),
+        Err(err) => {
+            warn!("failed to load rename history: {}", err);
+            Vec::new()
+        }
+    }
+}
@@ -0,0 +1,424 @@
+use std::{borrow::Cow, fs::File, path::Path};
+
+use lofty::{
+    config::{ParseOptions, WriteOptions},
+    file::TaggedFile,
+    id3::v2::{Frame, FrameId, Id3v2Tag, PopularimeterFrame},
+    mpeg::MpegFile,
+    picture::{Picture, PictureType},
+    prelude::*,
+    tag::{Tag, TagType},
+};
+use rayon::prelude::*;
+
+use crate::{
+    file_reader,
+    music::{SkippedEntry, TagEdit, TagEditReport, TrackTags},
+};
+
+/// Spam that download sites prepend to every text tag; stripped unless the user
+/// replaces the list in their settings.
+pub const DEFAULT_STRIP_PATTERNS: [&str; 1] = ["【熊猫无损音乐www.xmwav.com】更多打包资源下载"];
+
+/// Text tags that cleanup rules are applied to.
+const CLEANED_KEYS: [ItemKey; 6] = [
+    ItemKey::TrackTitle,
+    ItemKey::TrackArtist,
+    ItemKey::AlbumTitle,
+    ItemKey::AlbumArtist,
+    ItemKey::Genre,
+    ItemKey::Comment,
+];
+
+/// POPM frames are kept per player; Windows and most taggers read this one.
+const POPM_EMAIL: &str = "Windows Media Player 9 Series";
+
+/// POPM values Windows Media Player uses for one to five stars.
+const POPM_STARS: [u8; 5] = [1, 64, 128, 196, 255];
+
+/// Vorbis comment holding the rating as a percentage.
+const VORBIS_RATING_KEY: &str = "RATING";
+
+/// Removes every occurrence of each pattern from `value`, trimming whatever is left
+/// around them. Patterns are matched literally; empty patterns are ignored.
+pub fn strip_patterns(value: &str, patterns: &[String]) -> String {
+    let mut value = Cow::Borrowed(value);
+    for pattern in patterns.iter().filter(|pattern| !pattern.is_empty()) {
+        if value.contains(pattern.as_str()) {
+            value = Cow::Owned(value.replace(pattern.as_str(), "").trim().to_string());
+        }
+    }
+    value.into_owned()
+}
+
+/// Reads the tags shown in the editor from the file's primary tag, falling back to
+/// whichever tag the file has.
+pub fn read_tags(path: &str) -> Result<TrackTags, String> {
+    let tagged_file =
+        lofty::read_from_path(file_reader::local_path(path)).map_err(|e| e.to_string())?;
+    let writable = writable(tagged_file.primary_tag_type());
+    let Some(tag) = tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())
+    else {
+        return Ok(TrackTags {
+            path: path.to_string(),
+            writable,
+            ..Default::default()
+        });
+    };
+
+    let text = |key: &ItemKey| tag.get_string(key).map(str::to_string);
+    Ok(TrackTags {
+        path: path.to_string(),
+        title: text(&ItemKey::TrackTitle),
+        artist: text(&ItemKey::TrackArtist),
+        album: text(&ItemKey::AlbumTitle),
+        album_artist: text(&ItemKey::AlbumArtist),
+        genre: text(&ItemKey::Genre),
+        year: text(&ItemKey::RecordingDate),
+        track: tag.track(),
+        disc: tag.disk(),
+        comment: text(&ItemKey::Comment),
+        has_cover: !tag.pictures().is_empty(),
+        writable,
+    })
+}
+
+/// Applies `edit` to every file in `paths`, reporting each file that could not be
+/// written instead of stopping at the first failure.
+pub fn edit_tags(paths: Vec<String>, edit: &TagEdit) -> Result<TagEditReport, String> {
+    let prepared = PreparedEdit::new(edit)?;
+    let results: Vec<(String, Result<bool, String>)> = paths
+        .into_par_iter()
+        .map(|path| {
+            let result = update_tag(&path, |tag| prepared.apply(tag));
+            (path, result)
+        })
+        .collect();
+    Ok(collect_report(results))
+}
+
+/// Applies a separate edit to each file, e.g. tags parsed from every file's own path.
+pub fn edit_each(edits: Vec<(String, TagEdit)>) -> TagEditReport {
+    let results: Vec<(String, Result<bool, String>)> = edits
+        .into_par_iter()
+        .map(|(path, edit)| {
+            let result = PreparedEdit::new(&edit)
+                .and_then(|prepared| update_tag(&path, |tag| prepared.apply(tag)));
+            (path, result)
+        })
+        .collect();
+    collect_report(results)
+}
+
+/// A [`TagEdit`] with its numbers parsed and its cover loaded, ready to be applied
+/// to any number of tags.
+struct PreparedEdit<'a> {
+    edit: &'a TagEdit,
+    track: Option<Option<u32>>,
+    disc: Option<Option<u32>>,
+    cover: Option<Picture>,
+}
+
+impl<'a> PreparedEdit<'a> {
+    fn new(edit: &'a TagEdit) -> Result<Self, String> {
+        Ok(Self {
+            edit,
+            track: parse_number("track", edit.track.as_deref())?,
+            disc: parse_number("disc", edit.disc.as_deref())?,
+            cover: edit.cover.as_deref().map(load_cover).transpose()?,
+        })
+    }
+
+    fn apply(&self, tag: &mut Tag) -> bool {
+        let edit = self.edit;
+        set_text(tag, ItemKey::TrackTitle, edit.title.as_deref());
+        set_text(tag, ItemKey::TrackArtist, edit.artist.as_deref());
+        set_text(tag, ItemKey::AlbumTitle, edit.album.as_deref());
+        set_text(tag, ItemKey::AlbumArtist, edit.album_artist.as_deref());
+        set_text(tag, ItemKey::Genre, edit.genre.as_deref());
+        set_text(tag, ItemKey::RecordingDate, edit.year.as_deref());
+        set_text(tag, ItemKey::Comment, edit.comment.as_deref());
+        match self.track {
+            Some(Some(track)) => tag.set_track(track),
+            Some(None) => tag.remove_track(),
+            None => {}
+        }
+        match self.disc {
+            Some(Some(disc)) => tag.set_disk(disc),
+            Some(None) => tag.remove_disk(),
+            None => {}
+        }
+        if edit.remove_cover || self.cover.is_some() {
+            tag.remove_picture_type(PictureType::CoverFront);
+        }
+        if let Some(cover) = &self.cover {
+            tag.push_picture(cover.clone());
+        }
+        true
+    }
+}
+
+/// Strips `patterns` from the text tags of every file in `paths` and writes back the
+/// files that changed.
+pub fn clean_tags(paths: Vec<String>, patterns: &[String]) -> TagEditReport {
+    let results: Vec<(String, Result<bool, String>)> = paths
+        .into_par_iter()
+        .map(|path| {
+            let result = update_tag(&path, |tag| {
+                let mut changed = false;
+                for key in &CLEANED_KEYS {
+                    let Some(value) = tag.get_string(key).map(str::to_string) else {
+                        continue;
+                    };
+                    let cleaned = strip_patterns(&value, patterns);
+                    if cleaned != value {
+                        set_text(tag, key.clone(), Some(&cleaned));
+                        changed = true;
+                    }
+                }
+                changed
+            });
+            (path, result)
+        })
+        .collect();
+    collect_report(results)
+}
+
+/// Stores a rating of 0 to 5 stars in the file, or removes it: a POPM frame in
+/// MP3s, a RATING comment in FLAC and Ogg files. Returns whether the file was
+/// written; other formats have no common rating tag and are left alone.
+pub fn write_rating(path: &str, rating: Option<f32>) -> Result<bool, String> {
+    if is_mpeg(path) {
+        return write_popularimeter(path, rating);
+    }
+    update_tag(path, |tag| {
+        if tag.tag_type() != TagType::VorbisComments {
+            return false;
+        }
+        let key = ItemKey::Unknown(VORBIS_RATING_KEY.to_string());
+        match rating {
+            Some(stars) => {
+                tag.insert_text(key, ((stars * 20.0).round() as u32).to_string());
+            }
+            None => tag.remove_key(&key),
+        }
+        true
+    })
+}
+
+/// Reads the rating `write_rating` stores, in stars. The POPM frames of other
+/// players are used when the file has none of this player's. `None` when the file
+/// is unrated.
+pub fn read_rating(path: &str) -> Option<f32> {
+    if is_mpeg(path) {
+        return read_popularimeter(path);
+    }
+    let tagged_file = lofty::read_from_path(file_reader::local_path(path)).ok()?;
+    let percent: f32 = tagged_file
+        .tags()
+        .iter()
+        .filter(|tag| tag.tag_type() == TagType::VorbisComments)
+        .find_map(|tag| tag.get_string(&ItemKey::Unknown(VORBIS_RATING_KEY.to_string())))?
+        .trim()
+        .parse()
+        .ok()?;
+    (percent > 0.0).then(|| ((percent / 10.0).round() / 2.0).clamp(0.0, 5.0))
+}
+
+fn is_mpeg(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"))
+}
+
+fn read_popularimeter(path: &str) -> Option<f32> {
+    let mut file = File::open(file_reader::local_path(path)).ok()?;
+    let mpeg = MpegFile::read_from(&mut file, ParseOptions::new()).ok()?;
+    let frames: Vec<&PopularimeterFrame> = mpeg
+        .id3v2()?
+        .into_iter()
+        .filter_map(|frame| match frame {
+            Frame::Popularimeter(popm) => Some(popm),
+            _ => None,
+        })
+        .collect();
+    let popm = frames
+        .iter()
+        .find(|popm| popm.email == POPM_EMAIL)
+        .or_else(|| frames.first())?;
+    (popm.rating > 0).then(|| popularimeter_stars(popm.rating))
+}
+
+/// Replaces this player's POPM frame, keeping its play counter. Clearing the rating
+/// removes the frame unless it counts plays, in which case it is kept unrated.
+fn write_popularimeter(path: &str, rating: Option<f32>) -> Result<bool, String> {
+    let mut mpeg = {
+        let mut file = File::open(file_reader::local_path(path)).map_err(|e| e.to_string())?;
+        MpegFile::read_from(&mut file, ParseOptions::new()).map_err(|e| e.to_string())?
+    };
+    if mpeg.id3v2().is_none() {
+        mpeg.set_id3v2(Id3v2Tag::new());
+    }
+    let Some(tag) = mpeg.id3v2_mut() else {
+        return Ok(false);
+    };
+
+    let mut counter = 0;
+    let mut others = Vec::new();
+    for frame in tag.remove(&FrameId::Valid(Cow::Borrowed("POPM"))) {
+        match frame {
+            Frame::Popularimeter(popm) if popm.email == POPM_EMAIL => counter = popm.counter,
+            frame => others.push(frame),
+        }
+    }
+    for frame in others {
+        tag.insert(frame);
+    }
+    let value = match rating {
+        Some(stars) => Some(popularimeter_value(stars)),
+        None if counter > 0 => Some(0),
+        None => None,
+    };
+    if let Some(value) = value {
+        tag.insert(Frame::Popularimeter(PopularimeterFrame::new(
+            POPM_EMAIL.to_string(),
+            value,
+            counter,
+        )));
+    }
+    mpeg.save_to_path(file_reader::local_path(path), WriteOptions::default())
+        .map_err(|e| format!("failed to write rating: {}", e))?;
+    Ok(true)
+}
+
+/// Whole stars use the values of Windows Media Player; half stars sit halfway
+/// between them.
+fn popularimeter_value(stars: f32) -> u8 {
+    let halves = (stars * 2.0).round().clamp(0.0, 10.0) as usize;
+    let value = |whole: usize| match whole {
+        0 => 0,
+        whole => POPM_STARS[whole - 1] as u32,
+    };
+    if halves % 2 == 0 {
+        value(halves / 2) as u8
+    } else {
+        ((value(halves / 2) + value(halves / 2 + 1)) / 2) as u8
+    }
+}
+
+/// The half star whose POPM value is closest to `value`.
+fn popularimeter_stars(value: u8) -> f32 {
+    let halves = (0..=10)
+        .min_by_key(|halves| {
+            (i32::from(popularimeter_value(*halves as f32 / 2.0)) - i32::from(value)).abs()
+        })
+        .unwrap_or(0);
+    halves as f32 / 2.0
+}
+
+/// Opens `path`, lets `apply` modify its primary tag and saves the file if `apply`
+/// reports a change. Returns whether the file was written.
+fn update_tag<F>(path: &str, apply: F) -> Result<bool, String>
+where
+    F: FnOnce(&mut Tag) -> bool,
+{
+    let mut tagged_file =
+        lofty::read_from_path(file_reader::local_path(path)).map_err(|e| e.to_string())?;
+    let tag_type = tagged_file.primary_tag_type();
+    if !writable(tag_type) {
+        return Err(format!("{:?} tags cannot be edited", tag_type));
+    }
+    let Some(tag) = primary_tag_mut(&mut tagged_file) else {
+        return Err(format!("{:?} tags cannot be added to this file", tag_type));
+    };
+    if !apply(tag) {
+        return Ok(false);
+    }
+    tagged_file
+        .save_to_path(file_reader::local_path(path), WriteOptions::default())
+        .map_err(|e| format!("failed to write tags: {}", e))?;
+    Ok(true)
+}
+
+/// The primary tag, added first if the file has none. `None` if the file refuses
+/// the tag.
+fn primary_tag_mut(tagged_file: &mut TaggedFile) -> Option<&mut Tag> {
+    if tagged_file.primary_tag().is_none() {
+        tagged_file.insert_tag(Tag::new(tagged_file.primary_tag_type()));
+    }
+    tagged_file.primary_tag_mut()
+}
+
+/// Tag formats the editor writes: ID3v2, Vorbis comments (FLAC, Ogg) and MP4 atoms.
+fn writable(tag_type: TagType) -> bool {
+    matches!(
+        tag_type,
+        TagType::Id3v2 | TagType::VorbisComments | TagType::Mp4Ilst
+    )
+}
+
+/// `None` leaves the field untouched and an empty value removes it.
+fn set_text(tag: &mut Tag, key: ItemKey, value: Option<&str>) {
+    match value.map(str::trim) {
+        Some("") => {
+            tag.remove_key(&key);
+        }
+        Some(value) => {
+            tag.insert_text(key, value.to_string());
+        }
+        None => {}
+    }
+}
+
+fn parse_number(field: &str, value: Option<&str>) -> Result<Option<Option<u32>>, String> {
+    match value.map(str::trim) {
+        None => Ok(None),
+        Some("") => Ok(Some(None)),
+        Some(value) => value
+            .parse::<u32>()
+            .map(|number| Some(Some(number)))
+            .map_err(|_| format!("invalid {} number: {}", field, value)),
+    }
+}
+
+fn load_cover(path: &str) -> Result<Picture, String> {
+    let mut file = File::open(file_reader::local_path(path))
+        .map_err(|e| format!("failed to open {}: {}", path, e))?;
+    let mut picture =
+        Picture::from_reader(&mut file).map_err(|e| format!("invalid cover {}: {}", path, e))?;
+    picture.set_pic_type(PictureType::CoverFront);
+    Ok(picture)
+}
+
+fn collect_report(results: Vec<(String, Result<bool, String>)>) -> TagEditReport {
+    let mut report = TagEditReport::default();
+    for (path, result) in results {
+        match result {
+            Ok(true) => report.updated.push(path),
+            Ok(false) => {}
+            Err(reason) => report.failed.push(SkippedEntry::new(path, reason)),
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn popularimeter_values_round_trip() {
+        // Half a star shares the value of an unrated file, so it starts at one star.
+        for halves in 2..=10 {
+            let stars = halves as f32 / 2.0;
+            assert_eq!(popularimeter_stars(popularimeter_value(stars)), stars);
+        }
+        assert_eq!(popularimeter_stars(255), 5.0);
+        // Other players spread their values differently.
+        assert_eq!(popularimeter_stars(250), 5.0);
+        assert_eq!(popularimeter_stars(133), 3.0);
+    }
+}
@@ -13,6 +13,7 @@ import {
   MusicInfo,
   MusicSetting,
   MusicError,
+  ScanReport,
 } from './declare.ts';
 import Info from './info';
 import {
@@ -264,9 +265,9 @@ function App() {
       directory: true,
     });
     if (paths) {
-      const files = await invoke<string[]>('list_files', { dirs: paths });
-      if (files.length > 0) {
-        await addPlaylist(files);
+      const report = await invoke<ScanReport>('list_files', { dirs: paths });
+      if (report.files.length > 0) {
+        await addPlaylist(report.files);
       }
     }
   };
@@ -405,6 +406,30 @@ function App() {
     } catch (err) {
       console.log(err);
     }
+
+    for (let stars = 0; stars <= 5; stars++) {
+      try {
+        await register([`CommandOrControl+Alt+${stars}`], async (event) => {
+          if (event.state === 'Pressed') {
+            await invoke('rate_current_track', {
+              rating: stars === 0 ? null : stars,
+            });
+          }
+        });
+      } catch (err) {
+        console.log(err);
+      }
+    }
+
+    try {
+      await register(['CommandOrControl+Alt+L'], async (event) => {
+        if (event.state === 'Pressed') {
+          await invoke('love_current_track');
+        }
+      });
+    } catch (err) {
+      console.log(err);
+    }
   };
 
   const clearList = async () => {
@@ -603,7 +628,11 @@ function App() {
                     <div>
                       <img
                         src={
-                          music.imagePath ? convertFileSrc(music.imagePath) : bg
+                          music.thumbnailPath || music.imagePath
+                            ? convertFileSrc(
+                                music.thumbnailPath ?? music.imagePath!,
+                              )
+                            : bg
                         }
                         className={`w-10 h-10 p-0.5 bg-panel rounded-full ${play && activeId === music.id && 'rotate'}`}
                       />
@@ -4,21 +4,29 @@ const SEQUENCE_TYPES = {
   RANDOM: 3,
 } as const;
 
+// Extensions of the containers and codecs the backend's symphonia build decodes.
 const SUPPORTED_FORMATS = [
   'flac',
   'mp3',
   'wav',
+  'wave',
   'aac',
   'ogg',
   'riff',
   'aiff',
+  'aif',
+  'aifc',
   'mkv',
+  'mka',
   'caf',
   'mp4',
   'm4a',
+  'm4b',
+  'mp1',
   'mp2',
   'alac',
-  'wma',
+  'oga',
+  'webm',
 ];
 
 export { SEQUENCE_TYPES, SUPPORTED_FORMATS };
@@ -22,6 +22,8 @@ export interface PlayState {
   path: string;
   left_duration: string;
   progress: string;
+  position?: number;
+  chapter?: number;
 }
 
 export interface MusicMeta {
@@ -35,13 +37,68 @@ export interface MusicFile {
   name: string;
   path: string;
   imagePath?: string;
+  thumbnailPath?: string;
   artist?: string;
   album?: string;
+  unsupported?: string;
+  segment?: TrackSegment;
+  rating?: number;
+  favourite?: boolean;
+  addedAt?: number;
+}
+
+export interface TrackSegment {
+  start: number;
+  end?: number;
+}
+
+export interface Playlist {
+  id: string;
+  name: string;
+  tracks: MusicFile[];
+  lastTrackId?: string;
+  lastPosition?: number;
+  smart?: SmartRules;
+}
+
+export interface PlaylistImport {
+  playlist: MusicFile[];
+  skipped: SkippedEntry[];
+}
+
+export interface PlaylistSummary {
+  id: string;
+  name: string;
+  trackCount: number;
+  lastTrackId?: string;
+  lastPosition?: number;
+  current: boolean;
+  smart: boolean;
 }
 
 export interface MusicSetting {
   volume: number;
   sequence_type: number;
+  acoustid_url: string;
+  acoustid_key?: string;
+  strip_patterns: string[];
+  path_patterns: string[];
+  providers: ProviderSetting[];
+  cache_limit_mb: number;
+  network_policy: NetworkPolicy;
+  resume_threshold_mins: number;
+  write_rating_tags: boolean;
+  weighted_shuffle: boolean;
+}
+
+export type NetworkPolicy = 'ALWAYS' | 'ON_REQUEST' | 'NEVER';
+
+export type ProviderKind = 'ITUNES' | 'MUSIC_BRAINZ' | 'COVER_ART_ARCHIVE' | 'LRCLIB';
+
+export interface ProviderSetting {
+  kind: ProviderKind;
+  enabled: boolean;
+  baseUrl: string;
 }
 
 export interface MusicError {
@@ -49,3 +106,226 @@ export interface MusicError {
   name: string;
   message: string;
 }
+
+export interface SkippedEntry {
+  path: string;
+  reason: string;
+}
+
+export interface ScanReport {
+  files: string[];
+  skipped: SkippedEntry[];
+  unsupported: SkippedEntry[];
+  cancelled: boolean;
+}
+
+export interface TrackTags {
+  path: string;
+  title?: string;
+  artist?: string;
+  album?: string;
+  albumArtist?: string;
+  genre?: string;
+  year?: string;
+  track?: number;
+  disc?: number;
+  comment?: string;
+  hasCover: boolean;
+  writable: boolean;
+}
+
+export interface TagEdit {
+  title?: string;
+  artist?: string;
+  album?: string;
+  albumArtist?: string;
+  genre?: string;
+  year?: string;
+  track?: string;
+  disc?: string;
+  comment?: string;
+  cover?: string;
+  removeCover?: boolean;
+}
+
+export interface TagEditReport {
+  updated: string[];
+  failed: SkippedEntry[];
+}
+
+export interface PathTags {
+  path: string;
+  tags?: TagEdit;
+}
+
+export interface FileMove {
+  from: string;
+  to: string;
+}
+
+export interface RenameReport {
+  moves: FileMove[];
+  skipped: SkippedEntry[];
+}
+
+export interface TrackMatch {
+  provider?: ProviderKind;
+  title: string;
+  artist: string;
+  album: string;
+  duration?: number;
+  artworkUrl?: string;
+  releaseId?: string;
+  score: number;
+}
+
+export interface MatchChoice {
+  name: string;
+  path: string;
+  candidates: TrackMatch[];
+}
+
+export type LyricsSource = 'SIDECAR' | 'EMBEDDED' | 'ONLINE';
+
+export interface LyricWord {
+  time: number;
+  text: string;
+}
+
+export interface LyricLine {
+  time?: number;
+  text: string;
+  words?: LyricWord[];
+}
+
+export interface Lyrics {
+  path: string;
+  source: LyricsSource;
+  synced: boolean;
+  lines: LyricLine[];
+}
+
+export interface LyricsPosition {
+  path: string;
+  line: number;
+  word?: number;
+}
+
+export interface TrackResume {
+  id: string;
+  position: number;
+  finished: boolean;
+}
+
+export interface Chapter {
+  title: string;
+  start: number;
+  end?: number;
+}
+
+export interface AudioStream {
+  index: number;
+  codec: string;
+  language?: string;
+  channels?: number;
+  sampleRate?: number;
+  selected: boolean;
+}
+
+export interface TrackStats {
+  track: string;
+  title: string;
+  artist?: string;
+  album?: string;
+  playCount: number;
+  skipCount: number;
+  lastPlayed?: number;
+  listened: number;
+}
+
+export type StatsPeriod = 'WEEK' | 'MONTH' | 'YEAR';
+
+export interface StatsEntry {
+  name: string;
+  artist?: string;
+  plays: number;
+  listened: number;
+}
+
+export interface ListeningStats {
+  period: StatsPeriod;
+  since: number;
+  totalListened: number;
+  topTracks: StatsEntry[];
+  topArtists: StatsEntry[];
+  topAlbums: StatsEntry[];
+}
+
+export type SortField =
+  | 'TITLE'
+  | 'ARTIST'
+  | 'ALBUM'
+  | 'PATH'
+  | 'RATING'
+  | 'FAVOURITE';
+
+export interface SmartRules {
+  group: RuleGroup;
+  sort?: SmartSort;
+  limit?: SmartLimit;
+}
+
+export interface RuleGroup {
+  any: boolean;
+  rules: RuleNode[];
+}
+
+export type RuleNode = ({ kind: 'RULE' } & Rule) | ({ kind: 'GROUP' } & RuleGroup);
+
+export interface Rule {
+  field: RuleField;
+  operator: RuleOperator;
+  value: string;
+}
+
+export type RuleField =
+  | 'TITLE'
+  | 'ARTIST'
+  | 'ALBUM'
+  | 'ALBUM_ARTIST'
+  | 'GENRE'
+  | 'YEAR'
+  | 'COMMENT'
+  | 'PLAY_COUNT'
+  | 'SKIP_COUNT'
+  | 'RATING'
+  | 'FAVOURITE'
+  | 'LAST_PLAYED'
+  | 'DATE_ADDED'
+  | 'DURATION'
+  | 'CODEC'
+  | 'PATH';
+
+export type RuleOperator =
+  | 'IS'
+  | 'IS_NOT'
+  | 'CONTAINS'
+  | 'NOT_CONTAINS'
+  | 'STARTS_WITH'
+  | 'ENDS_WITH'
+  | 'GREATER_THAN'
+  | 'LESS_THAN'
+  | 'IN_LAST_DAYS'
+  | 'NOT_IN_LAST_DAYS';
+
+export interface SmartSort {
+  field: RuleField;
+  descending: boolean;
+}
+
+export type LimitKind = 'COUNT' | 'MINUTES' | 'MEGABYTES';
+
+export interface SmartLimit {
+  kind: LimitKind;
+  value: number;
+}